@@ -1,3 +1,611 @@
+mod repl;
+
+use outer_compiler::bytecode::{self, Vm};
+use outer_compiler::lexer::tokens::{Token, TokenType};
+use outer_compiler::lexer::Lexer;
+use outer_compiler::position::FileId;
+use outer_compiler::wasm;
+use outer_compiler::{CheckedProgram, Diagnostic, Program, Renderer, Severity, SourceMap};
+use repl::Repl;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::process;
+
+const USAGE: &str = "Usage: outer <command> [options] <file>
+       outer repl
+
+Commands:
+    lex      Print the tokens of a file
+    parse    Print the syntax tree of a file
+    check    Run every static check on a file
+    run      Check and execute a file, printing the value it returns
+    build    Compile a file
+    repl     Start an interactive session
+
+Options:
+    --emit=<kind>      What `build` produces: `bytecode` by default, `ir` for
+                       the SSA intermediate representation, `c` for a single
+                       C99 file, `wasm` for a WebAssembly module of the
+                       functions, or `wat` for its text format
+    -o <path>          Write the output of `build` to a file, bytecode as `.outb`
+
+Use `-` as the file to read the program from the standard input, `run` also
+executes `.outb` files.";
+
+/// The program was processed without errors
+const EXIT_SUCCESS: i32 = 0;
+/// The program has errors, reported as diagnostics on stderr
+const EXIT_FAILURE: i32 = 1;
+/// The command line is invalid or the file cannot be read
+const EXIT_USAGE: i32 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Command {
+    Lex,
+    Parse,
+    Check,
+    Run,
+    /// `output` is the file to write, instead of printing to stdout
+    Build {
+        emit: Emit,
+        output: Option<String>,
+    },
+}
+
+/// Output of the `build` command
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Emit {
+    Bytecode,
+    Ir,
+    C,
+    Wasm,
+    Wat,
+}
+
+impl Emit {
+    fn from_name(name: &str) -> Option<Emit> {
+        match name {
+            "bytecode" => Some(Emit::Bytecode),
+            "ir" => Some(Emit::Ir),
+            "c" => Some(Emit::C),
+            "wasm" => Some(Emit::Wasm),
+            "wat" => Some(Emit::Wat),
+            _ => None,
+        }
+    }
+}
+
+impl Command {
+    /// Parses a command name and the options given to it, returns the error message of an
+    /// invalid command line
+    fn parse(name: &str, options: &[String]) -> Result<Command, String> {
+        let mut command = match name {
+            "lex" => Command::Lex,
+            "parse" => Command::Parse,
+            "check" => Command::Check,
+            "run" => Command::Run,
+            "build" => Command::Build {
+                emit: Emit::Bytecode,
+                output: None,
+            },
+            _ => return Err(format!("Unknown command `{}`", name)),
+        };
+        let mut options = options.iter();
+        while let Some(option) = options.next() {
+            match (&mut command, option.strip_prefix("--emit=")) {
+                (Command::Build { emit, .. }, Some(kind)) => {
+                    *emit = Emit::from_name(kind)
+                        .ok_or_else(|| format!("Unknown output `{}` for `--emit`", kind))?;
+                }
+                (Command::Build { output, .. }, None) if option == "-o" => {
+                    let path = options.next().ok_or("Missing the path after `-o`")?;
+                    *output = Some(path.clone());
+                }
+                _ => return Err(format!("Unknown option `{}` for `{}`", option, name)),
+            }
+        }
+        Ok(command)
+    }
+}
+
+/// Standard streams of a command, replaced by buffers in tests
+struct Io<'a> {
+    stdin: &'a mut dyn Read,
+    stdout: &'a mut dyn Write,
+    stderr: &'a mut dyn Write,
+    colored: bool,
+}
+
+/// A source file loaded for a command, with the diagnostics reported so far
+struct Session<'a, 'io> {
+    io: &'a mut Io<'io>,
+    source_map: SourceMap,
+    file: FileId,
+    errors: usize,
+}
+
+impl<'a, 'io> Session<'a, 'io> {
+    fn source(&self) -> &str {
+        &self.source_map.file(self.file).source
+    }
+
+    fn report(&mut self, diagnostic: Diagnostic) {
+        if diagnostic.severity == Severity::Error {
+            self.errors += 1;
+        }
+        let rendered = Renderer::new(&self.source_map)
+            .colored(self.io.colored)
+            .render(&diagnostic);
+        let _ = writeln!(self.io.stderr, "{}", rendered);
+    }
+
+    fn report_error(&mut self, error: &outer_compiler::Error) {
+        for diagnostic in error.diagnostics() {
+            self.report(diagnostic);
+        }
+    }
+
+    fn exit_code(&self) -> i32 {
+        if self.errors == 0 {
+            EXIT_SUCCESS
+        } else {
+            EXIT_FAILURE
+        }
+    }
+
+    fn lex(&mut self) {
+        let tokens: Vec<_> = Lexer::with_file(self.source(), self.file).collect();
+        for token in tokens {
+            let _ = writeln!(self.io.stdout, "{}", format_token(&token));
+            if let TokenType::Error(error) = &token.ty {
+                self.report(
+                    Diagnostic::error(error.to_string()).with_label(token.span, String::new()),
+                );
+            }
+        }
+    }
+
+    fn parse(&mut self) -> Option<Program> {
+        match outer_compiler::parse(self.source()) {
+            Ok(program) => Some(program),
+            Err(error) => {
+                self.report_error(&error);
+                None
+            }
+        }
+    }
+
+    /// Runs every static pass, the program is returned if none of them found an error
+    fn check(&mut self) -> Option<CheckedProgram> {
+        match outer_compiler::check(self.source()) {
+            Ok(checked) => {
+                for warning in &checked.warnings {
+                    self.report(warning.clone());
+                }
+                Some(checked)
+            }
+            Err(error) => {
+                self.report_error(&error);
+                None
+            }
+        }
+    }
+
+    fn run(&mut self) {
+        let checked = match self.check() {
+            Some(checked) => checked,
+            None => return,
+        };
+        match checked.run() {
+            Ok(Some(value)) => {
+                let _ = writeln!(self.io.stdout, "{}", value);
+            }
+            Ok(None) => {}
+            Err(error) => self.report_error(&error),
+        }
+    }
+
+    fn build(&mut self, emit: Emit, output: Option<&str>) {
+        let checked = match self.check() {
+            Some(checked) => checked,
+            None => return,
+        };
+        let bytes = match emit {
+            // bytecode is printed disassembled, and written in its binary format
+            Emit::Bytecode => {
+                let module = checked.compile();
+                match output {
+                    Some(_) => bytecode::encode(&module),
+                    None => module.to_string().into_bytes(),
+                }
+            }
+            Emit::Ir => checked.to_ir().to_string().into_bytes(),
+            Emit::C => checked.to_c().into_bytes(),
+            Emit::Wasm | Emit::Wat => {
+                let module = match checked.to_wasm() {
+                    Ok(module) => module,
+                    Err(error) => return self.report(Diagnostic::from(&error)),
+                };
+                match emit {
+                    Emit::Wasm => wasm::encode(&module),
+                    _ => format!("{}\n", module).into_bytes(),
+                }
+            }
+        };
+        match output {
+            Some(path) => {
+                if let Err(error) = fs::write(path, bytes) {
+                    self.report(Diagnostic::error(format!(
+                        "Cannot write `{}`: {}",
+                        path, error
+                    )));
+                }
+            }
+            None => {
+                let _ = self.io.stdout.write_all(&bytes);
+            }
+        }
+    }
+}
+
+/// A token as printed by `outer lex`
+fn format_token(token: &Token) -> String {
+    format!("{} {:?} {:?}", token.position, token.ty, token.value)
+}
+
+/// Reads inputs until the end of stdin or `:quit`, an input continues on the next lines
+/// while it has unclosed delimiters
+fn repl(io: &mut Io) -> i32 {
+    let mut repl = Repl::new();
+    let mut stdin = BufReader::new(&mut *io.stdin);
+    let mut input = String::new();
+    let _ = writeln!(io.stdout, "outer REPL, enter :help for help");
+    loop {
+        let prompt = if input.is_empty() { ">> " } else { ".. " };
+        let _ = write!(io.stdout, "{}", prompt);
+        let _ = io.stdout.flush();
+
+        let mut line = String::new();
+        match stdin.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => input.push_str(&line),
+            Err(error) => {
+                let _ = writeln!(
+                    io.stderr,
+                    "error: Cannot read the standard input: {}",
+                    error
+                );
+                return EXIT_USAGE;
+            }
+        }
+        if Repl::is_incomplete(&input) {
+            continue;
+        }
+
+        let evaluation = repl.eval(&input);
+        input.clear();
+        if let Some(output) = evaluation.output {
+            let _ = writeln!(io.stdout, "{}", output);
+        }
+        let renderer = Renderer::new(repl.source_map()).colored(io.colored);
+        for diagnostic in &evaluation.diagnostics {
+            let _ = writeln!(io.stderr, "{}", renderer.render(diagnostic));
+        }
+        if evaluation.quit {
+            break;
+        }
+    }
+    let _ = writeln!(io.stdout);
+    EXIT_SUCCESS
+}
+
+/// Runs a module built with `outer build -o`, without its source runtime errors are reported
+/// with the position alone
+fn run_bytecode(io: &mut Io, name: &str, bytes: &[u8]) -> i32 {
+    let module = match bytecode::decode(bytes) {
+        Ok(module) => module,
+        Err(error) => {
+            let _ = writeln!(io.stderr, "error: Cannot read `{}`: {}", name, error);
+            return EXIT_USAGE;
+        }
+    };
+    match Vm::new(&module).run() {
+        Ok(value) => {
+            if let Some(value) = value {
+                let _ = writeln!(io.stdout, "{}", value);
+            }
+            EXIT_SUCCESS
+        }
+        Err(error) => {
+            let diagnostic = Diagnostic::error(error.kind().to_string()).with_note(format!(
+                "at {} of the source of `{}`",
+                error.position(),
+                name
+            ));
+            let rendered = Renderer::new(&SourceMap::new())
+                .colored(io.colored)
+                .render(&diagnostic);
+            let _ = writeln!(io.stderr, "{}", rendered);
+            EXIT_FAILURE
+        }
+    }
+}
+
+/// Runs the command line `args`, without the program name, and returns the exit code
+fn run(args: &[String], io: &mut Io) -> i32 {
+    let (command, path) = match args {
+        [command] if command == "repl" => return repl(io),
+        [flag] if flag == "-h" || flag == "--help" => {
+            let _ = writeln!(io.stdout, "{}", USAGE);
+            return EXIT_SUCCESS;
+        }
+        [command, options @ .., path] => match Command::parse(command, options) {
+            Ok(command) => (command, path),
+            Err(message) => {
+                let _ = writeln!(io.stderr, "error: {}\n\n{}", message, USAGE);
+                return EXIT_USAGE;
+            }
+        },
+        _ => {
+            let _ = writeln!(io.stderr, "{}", USAGE);
+            return EXIT_USAGE;
+        }
+    };
+
+    let (name, bytes) = if path == "-" {
+        let mut bytes = vec![];
+        (
+            String::from("<stdin>"),
+            io.stdin.read_to_end(&mut bytes).map(|_| bytes),
+        )
+    } else {
+        (path.clone(), fs::read(path))
+    };
+    if command == Command::Run {
+        if let Ok(bytes) = bytes.as_ref().map(Vec::as_slice) {
+            if bytes.starts_with(&bytecode::format::MAGIC) {
+                return run_bytecode(io, &name, bytes);
+            }
+        }
+    }
+    let source = bytes.and_then(|bytes| {
+        String::from_utf8(bytes)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "not valid UTF-8"))
+    });
+    let source = match source {
+        Ok(source) => source,
+        Err(error) => {
+            let _ = writeln!(io.stderr, "error: Cannot read `{}`: {}", name, error);
+            return EXIT_USAGE;
+        }
+    };
+
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file(name, source);
+    let mut session = Session {
+        io,
+        source_map,
+        file,
+        errors: 0,
+    };
+    match command {
+        Command::Lex => session.lex(),
+        Command::Parse => {
+            if let Some(program) = session.parse() {
+                let _ = writeln!(session.io.stdout, "{:#?}", program);
+            }
+        }
+        Command::Check => {
+            session.check();
+        }
+        Command::Run => session.run(),
+        Command::Build { emit, output } => session.build(emit, output.as_deref()),
+    }
+    session.exit_code()
+}
+
 fn main() {
-    println!("Hello, world!");
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut stdout = io::stdout().lock();
+    let code = run(
+        &args,
+        &mut Io {
+            stdin: &mut io::stdin().lock(),
+            stdout: &mut stdout,
+            stderr: &mut io::stderr().lock(),
+            colored: io::stderr().is_terminal(),
+        },
+    );
+    // `process::exit` does not run destructors, which would flush stdout
+    let _ = stdout.flush();
+    process::exit(code);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a command with `stdin` as the program, returns the exit code, stdout and stderr
+    fn outer(args: &[&str], stdin: &str) -> (i32, String, String) {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let (mut stdout, mut stderr) = (vec![], vec![]);
+        let code = run(
+            &args,
+            &mut Io {
+                stdin: &mut stdin.as_bytes(),
+                stdout: &mut stdout,
+                stderr: &mut stderr,
+                colored: false,
+            },
+        );
+        (
+            code,
+            String::from_utf8(stdout).unwrap(),
+            String::from_utf8(stderr).unwrap(),
+        )
+    }
+
+    #[test]
+    fn commands() {
+        let (code, stdout, _) = outer(&["lex", "-"], "let a");
+        assert_eq!(code, EXIT_SUCCESS);
+        assert_eq!(stdout, "1:1 Let \"let\"\n1:5 Identifier \"a\"\n");
+
+        let (code, stdout, _) = outer(&["parse", "-"], "return 1;");
+        assert_eq!(code, EXIT_SUCCESS);
+        assert!(stdout.starts_with("Program(\n"));
+        assert!(stdout.contains("Int32(\n"));
+
+        let (code, stdout, stderr) = outer(&["check", "-"], "auto a = 1; return a;");
+        assert_eq!(
+            (code, stdout.as_str(), stderr.as_str()),
+            (EXIT_SUCCESS, "", "")
+        );
+
+        let program = "func f(n: u64): u64 { return n < 2 ? 1 : n * f(n - 1); } return f(20);";
+        let (code, stdout, _) = outer(&["run", "-"], program);
+        assert_eq!(
+            (code, stdout.as_str()),
+            (EXIT_SUCCESS, "2432902008176640000\n")
+        );
+
+        let (code, stdout, _) = outer(&["build", "--emit=bytecode", "-"], program);
+        assert_eq!(code, EXIT_SUCCESS);
+        assert!(stdout.starts_with("constants:\n    #0 u64 20\n"));
+        assert!(stdout.contains("function @1 f (1 parameters, 1 locals):\n"));
+
+        let (code, stdout, _) = outer(&["build", "--emit=ir", "-"], program);
+        assert_eq!(code, EXIT_SUCCESS);
+        assert!(stdout.starts_with("function @0 <program>() {\nbb0:\n    %0: u64 = const 20\n"));
+        assert!(stdout.contains("function @1 f(u64): u64 {\n"));
+
+        let (code, stdout, _) = outer(&["build", "--emit=c", "-"], program);
+        assert_eq!(code, EXIT_SUCCESS);
+        assert!(stdout.contains("static uint64_t f_0(uint64_t n_1) {\n"));
+        assert!(stdout.ends_with("    outer_print_u64(t6);\n    return 0;\n}\n"));
+
+        let (code, stdout, _) = outer(&["build", "--emit=wat", "-"], program);
+        assert_eq!(code, EXIT_SUCCESS);
+        assert!(stdout.starts_with("(module\n  (type (;0;) (func (param i64) (result i64)))\n"));
+        assert!(stdout.ends_with("  (export \"f\" (func 0))\n)\n"));
+
+        let (code, _, stderr) = outer(
+            &["build", "--emit=wat", "-"],
+            "func f(): string { return \"\"; }",
+        );
+        assert_eq!(code, EXIT_FAILURE);
+        assert!(stderr.starts_with(
+            "error: Strings are not supported by the WebAssembly backend\n --> <stdin>:1:1\n"
+        ));
+    }
+
+    #[test]
+    fn diagnostics() {
+        let (code, _, stderr) = outer(&["check", "-"], "let a: i32 = true;");
+        assert_eq!(code, EXIT_FAILURE);
+        assert_eq!(
+            stderr,
+            r#"error: Mismatched types, expected `i32`, found `bool`
+ --> <stdin>:1:14
+  |
+1 | let a: i32 = true;
+  |              ^^^^ expected `i32`, found `bool`
+
+"#
+        );
+
+        let (code, _, stderr) = outer(&["lex", "-"], "a $");
+        assert_eq!(code, EXIT_FAILURE);
+        assert!(stderr.starts_with("error: Unexpected character"));
+
+        let (code, _, stderr) = outer(&["run", "-"], "auto a = 0; return 1 % a;");
+        assert_eq!(code, EXIT_FAILURE);
+        assert!(stderr.starts_with("error: Attempt to divide by zero"));
+
+        // on constants, the same error is found before running
+        let (code, _, stderr) = outer(&["check", "-"], "let a: u32 = 1 - 2;");
+        assert_eq!(code, EXIT_FAILURE);
+        assert_eq!(
+            stderr,
+            r#"error: Overflow in `-` on `u32`
+ --> <stdin>:1:14
+  |
+1 | let a: u32 = 1 - 2;
+  |              ^^^^^ this expression always fails
+
+"#
+        );
+
+        // warnings do not fail the check
+        let (code, _, stderr) = outer(&["check", "-"], "auto a = 1; if true { auto a = 2; }");
+        assert_eq!(code, EXIT_SUCCESS);
+        assert!(stderr.starts_with("warning: `a` shadows a previous declaration"));
+    }
+
+    #[test]
+    fn repl() {
+        let input = "func f(n: i32): i32 {\n  return n + 1;\n}\nf(41)\nf(true)\n:quit\n1\n";
+        let (code, stdout, stderr) = outer(&["repl"], input);
+        assert_eq!(code, EXIT_SUCCESS);
+        assert_eq!(
+            stdout,
+            "outer REPL, enter :help for help\n>> .. .. >> 42: i32\n>> >> \n"
+        );
+        assert!(stderr.starts_with(
+            "error: Mismatched types, expected `i32`, found `bool`\n --> <repl:3>:1:3"
+        ));
+    }
+
+    #[test]
+    fn bytecode_files() {
+        let path = env::temp_dir().join(format!("outer-test-{}.outb", process::id()));
+        let path = path.to_str().unwrap();
+
+        let program = "auto a = 40; return a + 2;";
+        let (code, stdout, _) = outer(&["build", "-o", path, "-"], program);
+        assert_eq!((code, stdout.as_str()), (EXIT_SUCCESS, ""));
+        let (code, stdout, _) = outer(&["run", path], "");
+        assert_eq!((code, stdout.as_str()), (EXIT_SUCCESS, "42\n"));
+
+        outer(&["build", "-o", path, "-"], "auto a = 0;\nreturn 1 % a;");
+        let (code, _, stderr) = outer(&["run", path], "");
+        assert_eq!(code, EXIT_FAILURE);
+        assert!(stderr.starts_with("error: Attempt to divide by zero\n = note: at 2:8"));
+
+        let mut bytes = fs::read(path).unwrap();
+        bytes[4] += 1;
+        fs::write(path, bytes).unwrap();
+        let (code, _, stderr) = outer(&["run", path], "");
+        assert_eq!(code, EXIT_USAGE);
+        assert!(stderr.contains(": Unsupported bytecode version 2, expected version 1"));
+
+        let (code, _, _) = outer(&["build", "--emit=wasm", "-o", path, "-"], "func f() {}");
+        assert_eq!(code, EXIT_SUCCESS);
+        assert!(fs::read(path).unwrap().starts_with(b"\0asm\x01\0\0\0"));
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn usage_errors() {
+        let (code, _, stderr) = outer(&["compile", "-"], "");
+        assert_eq!(code, EXIT_USAGE);
+        assert!(stderr.starts_with("error: Unknown command `compile`"));
+
+        let (code, _, stderr) = outer(&["build", "--emit=elf", "-"], "");
+        assert_eq!(code, EXIT_USAGE);
+        assert!(stderr.starts_with("error: Unknown output `elf` for `--emit`"));
+
+        let (code, _, stderr) = outer(&["run", "--emit=bytecode", "-"], "");
+        assert_eq!(code, EXIT_USAGE);
+        assert!(stderr.starts_with("error: Unknown option `--emit=bytecode` for `run`"));
+
+        let (code, _, stderr) = outer(&["run"], "");
+        assert_eq!(code, EXIT_USAGE);
+        assert!(stderr.starts_with("Usage: outer"));
+
+        let (code, _, stderr) = outer(&["run", "does/not/exist.out"], "");
+        assert_eq!(code, EXIT_USAGE);
+        assert!(stderr.starts_with("error: Cannot read `does/not/exist.out`"));
+    }
 }
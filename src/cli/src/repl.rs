@@ -0,0 +1,330 @@
+use crate::format_token;
+use outer_compiler::ast::{ExpressionNode, StatementNode};
+use outer_compiler::checker::initialization;
+use outer_compiler::checker::Checker;
+use outer_compiler::interpreter::{Environment, Interpreter};
+use outer_compiler::lexer::tokens::TokenType;
+use outer_compiler::lexer::Lexer;
+use outer_compiler::optimizer;
+use outer_compiler::parser::Parser;
+use outer_compiler::position::FileId;
+use outer_compiler::resolver::Resolver;
+use outer_compiler::{Diagnostic, Program, SourceMap, Statement, Value};
+
+pub const HELP: &str = "Enter statements to run them, or an expression to print its value.
+
+Commands:
+    :type <expr>      Print the type of an expression
+    :ast <expr>       Print the syntax tree of an expression
+    :tokens <expr>    Print the tokens of an expression
+    :reset            Forget every variable and function
+    :help             Print this message
+    :quit             Exit the REPL";
+
+/// What an input printed: its output for stdout and the diagnostics for stderr
+#[derive(Debug, Default)]
+pub struct Evaluation {
+    pub output: Option<String>,
+    pub diagnostics: Vec<Diagnostic>,
+    pub quit: bool,
+}
+
+impl Evaluation {
+    fn output(output: String) -> Self {
+        Self {
+            output: Some(output),
+            ..Self::default()
+        }
+    }
+
+    fn diagnostics(diagnostics: Vec<Diagnostic>) -> Self {
+        Self {
+            diagnostics,
+            ..Self::default()
+        }
+    }
+}
+
+/// Runs inputs one after the other, each one sees the variables and functions of the
+/// previous ones. An input failing to compile leaves the state untouched.
+pub struct Repl {
+    /// every input is a file of its own, so the spans of different inputs never collide
+    source_map: SourceMap,
+    resolver: Resolver,
+    checker: Checker,
+    environment: Environment,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Self {
+            source_map: SourceMap::new(),
+            resolver: Resolver::new(),
+            checker: Checker::new(),
+            environment: Environment::default(),
+        }
+    }
+
+    pub fn source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
+
+    /// Whether `input` has unclosed `{`, `(` or `[` and continues on the next line
+    pub fn is_incomplete(input: &str) -> bool {
+        let depth: isize = Lexer::new(input)
+            .map(|token| match token.ty {
+                TokenType::LBrace | TokenType::LParen | TokenType::LBracket => 1,
+                TokenType::RBrace | TokenType::RParen | TokenType::RBracket => -1,
+                _ => 0,
+            })
+            .sum();
+        depth > 0
+    }
+
+    pub fn eval(&mut self, input: &str) -> Evaluation {
+        let input = input.trim();
+        if input.is_empty() {
+            return Evaluation::default();
+        }
+        if let Some(command) = input.strip_prefix(':') {
+            let (command, argument) = match command.find(char::is_whitespace) {
+                Some(i) => (&command[..i], command[i..].trim()),
+                None => (command, ""),
+            };
+            return match command {
+                "type" => self.type_of(argument),
+                "ast" => {
+                    let file = self.add_file(argument);
+                    match parse_expression(argument, file) {
+                        Ok(expression) => Evaluation::output(format!("{:#?}", expression)),
+                        Err(diagnostics) => Evaluation::diagnostics(diagnostics),
+                    }
+                }
+                "tokens" => {
+                    let tokens: Vec<String> =
+                        Lexer::new(argument).map(|t| format_token(&t)).collect();
+                    Evaluation::output(tokens.join("\n"))
+                }
+                "reset" => {
+                    *self = Repl::new();
+                    Evaluation::default()
+                }
+                "help" => Evaluation::output(String::from(HELP)),
+                "quit" => Evaluation {
+                    quit: true,
+                    ..Evaluation::default()
+                },
+                _ => Evaluation::diagnostics(vec![Diagnostic::error(format!(
+                    "Unknown command `:{}`, try `:help`",
+                    command
+                ))]),
+            };
+        }
+        self.execute(input)
+    }
+
+    fn add_file(&mut self, input: &str) -> FileId {
+        let name = format!("<repl:{}>", self.source_map.files().len() + 1);
+        self.source_map.add_file(name, String::from(input))
+    }
+
+    fn execute(&mut self, input: &str) -> Evaluation {
+        let file = self.add_file(input);
+        let mut program = match Parser::new(Lexer::with_file(input, file)).parse_program() {
+            Ok(program) => program,
+            // not statements, it may be an expression to print
+            Err(errors) => match parse_expression(input.trim_end_matches(';'), file) {
+                Ok(expression) => expression_program(expression),
+                Err(_) => {
+                    return Evaluation::diagnostics(errors.iter().map(Diagnostic::from).collect())
+                }
+            },
+        };
+
+        // the passes and the run work on copies, kept only if the input compiles and runs
+        let mut resolver = self.resolver.clone();
+        let resolve_errors = resolver.resolve_more(&program);
+        let mut diagnostics: Vec<Diagnostic> =
+            resolve_errors.iter().map(Diagnostic::from).collect();
+        if resolve_errors.iter().any(|e| e.is_error()) {
+            return Evaluation::diagnostics(diagnostics);
+        }
+        let mut checker = self.checker.clone();
+        if let Err(errors) = checker.check_more(&mut program) {
+            diagnostics.extend(errors.iter().map(Diagnostic::from));
+            return Evaluation::diagnostics(diagnostics);
+        }
+        if let Err(errors) = optimizer::fold_program(&mut program) {
+            diagnostics.extend(errors.iter().map(Diagnostic::from));
+            return Evaluation::diagnostics(diagnostics);
+        }
+        if let Err(errors) = initialization::check_initialization(&program, resolver.symbols()) {
+            diagnostics.extend(errors.iter().map(Diagnostic::from));
+            return Evaluation::diagnostics(diagnostics);
+        }
+        let mut interpreter =
+            Interpreter::with_environment(resolver.symbols(), self.environment.clone());
+        let result = interpreter.run(&program);
+        let environment = interpreter.into_environment();
+        if result.is_ok() {
+            self.resolver = resolver;
+            self.checker = checker;
+            self.environment = environment;
+        }
+
+        match result {
+            Ok(Some(value)) => Evaluation {
+                output: Some(format!("{}: {}", show(&value), value.ty())),
+                diagnostics,
+                quit: false,
+            },
+            Ok(None) => Evaluation::diagnostics(diagnostics),
+            Err(error) => {
+                diagnostics.push(Diagnostic::from(&error));
+                Evaluation::diagnostics(diagnostics)
+            }
+        }
+    }
+
+    fn type_of(&mut self, input: &str) -> Evaluation {
+        let file = self.add_file(input);
+        let program = match parse_expression(input, file) {
+            Ok(expression) => expression_program(expression),
+            Err(diagnostics) => return Evaluation::diagnostics(diagnostics),
+        };
+
+        let resolve_errors = self.resolver.clone().resolve_more(&program);
+        if resolve_errors.iter().any(|e| e.is_error()) {
+            return Evaluation::diagnostics(resolve_errors.iter().map(Diagnostic::from).collect());
+        }
+        let mut expression = match program.0.into_iter().next().map(|s| s.value) {
+            Some(Statement::Return(expression)) => expression,
+            _ => unreachable!(),
+        };
+        match self.checker.clone().type_of(&mut expression) {
+            Ok(Some(ty)) => Evaluation::output(ty.to_string()),
+            Ok(None) => Evaluation::default(),
+            Err(errors) => Evaluation::diagnostics(errors.iter().map(Diagnostic::from).collect()),
+        }
+    }
+}
+
+fn parse_expression(input: &str, file: FileId) -> Result<ExpressionNode, Vec<Diagnostic>> {
+    Parser::new(Lexer::with_file(input, file))
+        .parse_expression()
+        .map_err(|errors| errors.iter().map(Diagnostic::from).collect())
+}
+
+/// An expression is evaluated as a top level `return`, which makes it the result of the input
+fn expression_program(expression: ExpressionNode) -> Program {
+    let (start, end, span) = (expression.start, expression.end, expression.span);
+    let statement = StatementNode::from(Statement::Return(expression))
+        .set_start(start)
+        .set_end(end)
+        .set_span(span);
+    Program(vec![statement])
+}
+
+fn show(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", s),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Evaluates inputs in order, returns what each one printed, or its first diagnostic
+    fn session(inputs: &[&str]) -> Vec<String> {
+        let mut repl = Repl::new();
+        inputs
+            .iter()
+            .map(|input| {
+                let evaluation = repl.eval(input);
+                match (evaluation.output, evaluation.diagnostics.first()) {
+                    (_, Some(diagnostic)) => diagnostic.to_string(),
+                    (Some(output), None) => output,
+                    (None, None) => String::new(),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keep_state_across_inputs() {
+        let outputs = session(&[
+            "auto a = 20;",
+            "func twice(n: i32): i32 { return 2 * n; }",
+            "twice(a) + 2",
+            "a = a + 1;",
+            "a > 20 ? \"big\" : \"small\"",
+            "let b: i32 = true;",
+            "b",
+            "3000000000",
+            "auto z = 0;",
+            "a = 1; auto q = 1 / z;",
+            "q",
+            "a",
+            ":type a == 1",
+            ":type twice(a) << 1",
+            ":reset",
+            "a",
+        ]);
+        assert_eq!(
+            outputs,
+            vec![
+                "",
+                "",
+                "42: i32",
+                "",
+                "\"big\": string",
+                "error: Mismatched types, expected `i32`, found `bool`",
+                "error: Undefined variable `b`",
+                "3000000000: i64",
+                "",
+                "error: Attempt to divide by zero",
+                "error: Undefined variable `q`",
+                "21: i32",
+                "bool",
+                "i32",
+                "",
+                "error: Undefined variable `a`",
+            ]
+        );
+    }
+
+    #[test]
+    fn commands() {
+        let mut repl = Repl::new();
+        let tokens = repl.eval(":tokens 1 + x").output.unwrap();
+        assert_eq!(
+            tokens,
+            "1:1 IntLiteral \"1\"\n1:3 Plus \"+\"\n1:5 Identifier \"x\""
+        );
+
+        let ast = repl.eval(":ast -x").output.unwrap();
+        assert!(ast.contains("Neg("));
+
+        assert!(repl.eval(":quit").quit);
+        assert_eq!(
+            repl.eval(":what").diagnostics[0].to_string(),
+            "error: Unknown command `:what`, try `:help`"
+        );
+    }
+
+    #[test]
+    fn incomplete_input() {
+        assert!(Repl::is_incomplete("func f(n: i32): i32 {"));
+        assert!(Repl::is_incomplete("f(1,"));
+        assert!(!Repl::is_incomplete("func f() { }"));
+        assert!(!Repl::is_incomplete("}"));
+    }
+}
@@ -1,5 +1,7 @@
 use crate::ast::node::Node;
 use crate::ast::types::{Type, TypeNode};
+use core::fmt;
+use std::convert::TryFrom;
 
 pub mod node;
 pub mod types;
@@ -18,6 +20,58 @@ pub enum Literal {
     Null,
 }
 
+impl Literal {
+    /// Value of an integer literal, wide enough for every integer type
+    pub fn as_integer(&self) -> Option<i128> {
+        match self {
+            Literal::Int32(v) => Some(*v as i128),
+            Literal::Int64(v) => Some(*v as i128),
+            Literal::UInt32(v) => Some(*v as i128),
+            Literal::UInt64(v) => Some(*v as i128),
+            _ => None,
+        }
+    }
+
+    /// Integer literal of type `ty`, `None` if `ty` is not an integer type holding the value
+    pub fn from_integer(value: i128, ty: &Type) -> Option<Literal> {
+        match ty {
+            Type::Int(32) => i32::try_from(value).ok().map(Literal::Int32),
+            Type::Int(64) => i64::try_from(value).ok().map(Literal::Int64),
+            Type::UInt(32) => u32::try_from(value).ok().map(Literal::UInt32),
+            Type::UInt(64) => u64::try_from(value).ok().map(Literal::UInt64),
+            _ => None,
+        }
+    }
+
+    /// Type of a literal before any contextual typing, `None` for literals without one
+    pub fn default_type(&self) -> Option<Type> {
+        match self {
+            Literal::Int32(_) => Some(Type::Int(32)),
+            Literal::Int64(_) => Some(Type::Int(64)),
+            Literal::UInt32(_) => Some(Type::UInt(32)),
+            Literal::UInt64(_) => Some(Type::UInt(64)),
+            Literal::Boolean(_) => Some(Type::Boolean),
+            Literal::String(_) => Some(Type::String),
+            Literal::Array(_) | Literal::Null => None,
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Literal::Int32(v) => write!(f, "{}", v),
+            Literal::Int64(v) => write!(f, "{}", v),
+            Literal::UInt32(v) => write!(f, "{}", v),
+            Literal::UInt64(v) => write!(f, "{}", v),
+            Literal::Boolean(v) => write!(f, "{}", v),
+            Literal::String(v) => write!(f, "\"{}\"", v),
+            Literal::Array(_) => write!(f, "[..]"),
+            Literal::Null => write!(f, "null"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expression {
     Identifier(Identifier),
@@ -36,7 +90,13 @@ pub enum Expression {
     Gt(Box<ExpressionNode>, Box<ExpressionNode>),
     And(Box<ExpressionNode>, Box<ExpressionNode>),
     Or(Box<ExpressionNode>, Box<ExpressionNode>),
+    BitAnd(Box<ExpressionNode>, Box<ExpressionNode>),
+    BitOr(Box<ExpressionNode>, Box<ExpressionNode>),
+    BitXor(Box<ExpressionNode>, Box<ExpressionNode>),
+    Shl(Box<ExpressionNode>, Box<ExpressionNode>),
+    Shr(Box<ExpressionNode>, Box<ExpressionNode>),
     Not(Box<ExpressionNode>),
+    Neg(Box<ExpressionNode>),
     Ternary(
         Box<ExpressionNode>,
         Box<ExpressionNode>,
@@ -86,8 +146,25 @@ pub enum Statement {
         BlockStatementNode,
         Option<BlockStatementNode>,
     ),
+    While(ExpressionNode, BlockStatementNode),
+    For(
+        Option<Box<StatementNode>>,
+        ExpressionNode,
+        Option<Box<StatementNode>>,
+        BlockStatementNode,
+    ),
+    Switch(
+        ExpressionNode,
+        Vec<SwitchCaseNode>,
+        Option<BlockStatementNode>,
+    ),
+    Break,
+    Continue,
     FunctionCall(Identifier, Vec<ExpressionNode>),
+    FunctionDefinition(FunctionNode),
     Return(ExpressionNode),
+    /// Placeholder for a statement that failed to parse
+    Error,
 }
 
 pub type StatementNode = Node<Statement>;
@@ -97,6 +174,20 @@ pub struct BlockStatement(pub Vec<StatementNode>);
 
 pub type BlockStatementNode = Node<BlockStatement>;
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwitchCase {
+    pub value: ExpressionNode,
+    pub body: BlockStatementNode,
+}
+
+impl SwitchCase {
+    pub fn new(value: ExpressionNode, body: BlockStatementNode) -> Self {
+        Self { value, body }
+    }
+}
+
+pub type SwitchCaseNode = Node<SwitchCase>;
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct FunctionSignature {
     pub inputs: Vec<Type>,
@@ -127,7 +218,7 @@ impl Function {
                 .iter()
                 .map(|p| p.value.ty.as_ref().cloned().unwrap())
                 .collect(),
-            output: return_type.clone().map(|r| r),
+            output: return_type.clone(),
         };
         Self {
             id,
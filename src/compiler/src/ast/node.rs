@@ -1,10 +1,11 @@
-use crate::position::Position;
+use crate::position::{Position, Span};
 use core::fmt;
 
 #[derive(Debug, Clone)]
 pub struct Node<T> {
     pub start: Position,
     pub end: Position,
+    pub span: Span,
     pub value: T,
 }
 
@@ -13,6 +14,7 @@ impl<T: fmt::Debug> Node<T> {
         Self {
             start: Default::default(),
             end: Default::default(),
+            span: Default::default(),
             value,
         }
     }
@@ -24,6 +26,10 @@ impl<T: fmt::Debug> Node<T> {
         self.end = end;
         self
     }
+    pub fn set_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
 }
 
 impl<T: PartialEq> PartialEq for Node<T> {
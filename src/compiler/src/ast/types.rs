@@ -3,9 +3,32 @@ use core::fmt;
 use std::convert::TryFrom;
 use std::fmt::Formatter;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TypeError {
     InvalidType(String),
+    Mismatch {
+        expected: Type,
+        found: Type,
+    },
+    InvalidOperand(String, Type),
+    InvalidOperands(String, Type, Type),
+    LiteralOutOfRange(String, Type),
+    UndefinedVariable(String),
+    UndefinedFunction(String),
+    ArgumentCount {
+        function: String,
+        expected: usize,
+        found: usize,
+    },
+    NoReturnValue(String),
+    UnexpectedReturn(String),
+    MissingReturn(String),
+    CannotInfer(String),
+    Unsupported(String),
+    NonConstantCase,
+    DuplicateCase,
+    UnreachableDefault,
+    UncoveredBoolean,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -18,6 +41,30 @@ pub enum Type {
 
 pub type TypeNode = Node<Type>;
 
+impl Type {
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Type::Int(_) | Type::UInt(_))
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(self, Type::Int(_))
+    }
+
+    /// Inclusive range of the values of an integer type
+    pub fn integer_range(&self) -> Option<(i128, i128)> {
+        match self {
+            Type::Int(width) => Some((-(1i128 << (width - 1)), (1i128 << (width - 1)) - 1)),
+            Type::UInt(width) => Some((0, (1i128 << width) - 1)),
+            _ => None,
+        }
+    }
+
+    pub fn fits(&self, value: i128) -> bool {
+        self.integer_range()
+            .is_some_and(|(min, max)| min <= value && value <= max)
+    }
+}
+
 impl fmt::Display for Type {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -33,6 +80,53 @@ impl fmt::Display for TypeError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             TypeError::InvalidType(s) => write!(f, "Invalid type `{}`", s),
+            TypeError::Mismatch { expected, found } => write!(
+                f,
+                "Mismatched types, expected `{}`, found `{}`",
+                expected, found
+            ),
+            TypeError::InvalidOperand(operator, ty) => {
+                write!(f, "Cannot apply `{}` to `{}`", operator, ty)
+            }
+            TypeError::InvalidOperands(operator, lhs, rhs) => {
+                write!(f, "Cannot apply `{}` to `{}` and `{}`", operator, lhs, rhs)
+            }
+            TypeError::LiteralOutOfRange(literal, ty) => {
+                write!(f, "Literal `{}` does not fit in `{}`", literal, ty)
+            }
+            TypeError::UndefinedVariable(id) => write!(f, "Undefined variable `{}`", id),
+            TypeError::UndefinedFunction(id) => write!(f, "Undefined function `{}`", id),
+            TypeError::ArgumentCount {
+                function,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Function `{}` takes {} argument(s) but {} were supplied",
+                function, expected, found
+            ),
+            TypeError::NoReturnValue(id) => write!(f, "Function `{}` does not return a value", id),
+            TypeError::UnexpectedReturn(id) => {
+                write!(
+                    f,
+                    "Function `{}` has no return type but returns a value",
+                    id
+                )
+            }
+            TypeError::MissingReturn(id) => {
+                write!(f, "Function `{}` does not return a value on every path", id)
+            }
+            TypeError::CannotInfer(id) => write!(f, "Cannot infer the type of `{}`", id),
+            TypeError::Unsupported(s) => write!(f, "{} are not supported", s),
+            TypeError::NonConstantCase => write!(f, "Case value must be a constant"),
+            TypeError::DuplicateCase => write!(f, "Duplicate case value in switch"),
+            TypeError::UnreachableDefault => write!(
+                f,
+                "Unreachable `default` arm, switch over bool covers `true` and `false`"
+            ),
+            TypeError::UncoveredBoolean => {
+                write!(f, "Switch over bool covers neither `true` nor `false`")
+            }
         }
     }
 }
@@ -59,7 +153,7 @@ mod tests {
 
     #[test]
     fn test_types() {
-        let types: Result<Vec<_>, _> = vec!["i32", "i64", "u32", "u64", "bool", "string"]
+        let types: Result<Vec<_>, _> = ["i32", "i64", "u32", "u64", "bool", "string"]
             .iter()
             .map(|ty| Type::try_from(String::from(*ty)))
             .collect();
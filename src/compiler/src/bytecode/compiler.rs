@@ -0,0 +1,449 @@
+use super::{Chunk, Instruction, Location, Module, MAIN};
+use crate::ast::node::Node;
+use crate::ast::{
+    Assignee, BlockStatementNode, Expression, ExpressionNode, Function, ParameterNode, Program,
+    Statement, StatementNode,
+};
+use crate::interpreter::value::{BinaryOperator, Value};
+use crate::resolver::{SymbolId, SymbolTable};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// Compiles a checked program, `table` is the symbol table the resolver built for it
+pub fn compile(program: &Program, table: &SymbolTable) -> Module {
+    let mut compiler = Compiler {
+        table,
+        module: Module {
+            constants: vec![],
+            functions: vec![],
+        },
+        functions: HashMap::new(),
+        chunk: Chunk::new(String::new()),
+        slots: HashMap::new(),
+        loops: vec![],
+    };
+
+    // every function gets its index up front, so that calls can be compiled before the
+    // function they reach, the same way the interpreter registers them
+    let mut functions = vec![];
+    collect_functions(table, &program.0, &mut functions);
+    for (index, (id, _)) in functions.iter().enumerate() {
+        compiler.functions.insert(*id, index as u32 + MAIN + 1);
+    }
+
+    // the implicit return of the program is located at its end
+    let end = Location {
+        position: program.0.last().map(|s| s.end).unwrap_or_default(),
+        span: Default::default(),
+    };
+    compiler.compile_function(String::from("<program>"), &[], &program.0, end);
+    for (id, function) in functions {
+        let symbol = table.symbol(id);
+        let location = Location {
+            position: symbol.position,
+            span: symbol.span,
+        };
+        compiler.compile_function(
+            symbol.name.clone(),
+            &function.parameters,
+            &function.statements,
+            location,
+        );
+    }
+    compiler.module
+}
+
+/// Finds every named function, whatever block it is defined in, with its symbol
+pub(crate) fn collect_functions<'p>(
+    table: &SymbolTable,
+    statements: &'p [StatementNode],
+    functions: &mut Vec<(SymbolId, &'p Function)>,
+) {
+    for statement in statements {
+        match &statement.value {
+            Statement::FunctionDefinition(function) => {
+                if let Some(id) = table.symbol_id(function.span) {
+                    functions.push((id, &function.value));
+                }
+                collect_functions(table, &function.value.statements, functions);
+            }
+            Statement::Definition(_, variable, expression) => {
+                if let Expression::FunctionDef(function) = &expression.value {
+                    if let Some(id) = table.symbol_id(variable.span) {
+                        functions.push((id, &function.value));
+                    }
+                    collect_functions(table, &function.value.statements, functions);
+                }
+            }
+            Statement::Condition(_, consequence, alternative) => {
+                collect_functions(table, &consequence.value.0, functions);
+                if let Some(alternative) = alternative {
+                    collect_functions(table, &alternative.value.0, functions);
+                }
+            }
+            Statement::While(_, body) | Statement::For(_, _, _, body) => {
+                collect_functions(table, &body.value.0, functions)
+            }
+            Statement::Switch(_, cases, default) => {
+                for case in cases {
+                    collect_functions(table, &case.value.body.value.0, functions);
+                }
+                if let Some(default) = default {
+                    collect_functions(table, &default.value.0, functions);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Jumps of the `break` and `continue` statements of a loop, patched once its end is known
+#[derive(Default)]
+struct Loop {
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+struct Compiler<'a> {
+    table: &'a SymbolTable,
+    module: Module,
+    functions: HashMap<SymbolId, u32>,
+    /// the function being compiled
+    chunk: Chunk,
+    slots: HashMap<SymbolId, u32>,
+    loops: Vec<Loop>,
+}
+
+impl<'a> Compiler<'a> {
+    /// Compiles a function into the next chunk of the module, `end` is the location of the
+    /// return at its end
+    fn compile_function(
+        &mut self,
+        name: String,
+        parameters: &[ParameterNode],
+        statements: &[StatementNode],
+        end: Location,
+    ) {
+        self.chunk = Chunk::new(name);
+        self.slots.clear();
+        self.chunk.parameters = parameters.len() as u32;
+        for parameter in parameters {
+            self.slot(parameter);
+        }
+        self.compile_statements(statements);
+        // falling off the end returns nothing
+        self.emit(Instruction::ReturnVoid, end);
+
+        let chunk = std::mem::replace(&mut self.chunk, Chunk::new(String::new()));
+        self.module.functions.push(chunk);
+    }
+
+    fn emit(&mut self, instruction: Instruction, location: Location) -> usize {
+        self.chunk.code.push(instruction);
+        self.chunk.locations.push(location);
+        self.chunk.code.len() - 1
+    }
+
+    fn emit_at<T>(&mut self, instruction: Instruction, node: &Node<T>) -> usize {
+        self.emit(instruction, location(node))
+    }
+
+    fn here(&self) -> u32 {
+        self.chunk.code.len() as u32
+    }
+
+    /// Points the jump at `index` to the next instruction
+    fn patch(&mut self, index: usize) {
+        let target = self.here();
+        match &mut self.chunk.code[index] {
+            Instruction::Jump(to) | Instruction::JumpIfFalse(to) => *to = target,
+            instruction => panic!("cannot patch `{}`", instruction),
+        }
+    }
+
+    fn constant(&mut self, value: Value) -> u32 {
+        let constants = &mut self.module.constants;
+        let index = match constants.iter().position(|c| *c == value) {
+            Some(index) => index,
+            None => {
+                constants.push(value);
+                constants.len() - 1
+            }
+        };
+        u32::try_from(index).expect("too many constants")
+    }
+
+    /// The local slot of the variable declared or used at `node`
+    fn slot<T>(&mut self, node: &Node<T>) -> u32 {
+        let id = self
+            .table
+            .symbol_id(node.span)
+            .expect("names are resolved before compiling");
+        match self.slots.get(&id) {
+            Some(slot) => *slot,
+            None => {
+                let slot = self.new_slot(self.table.symbol(id).name.clone());
+                self.slots.insert(id, slot);
+                slot
+            }
+        }
+    }
+
+    fn new_slot(&mut self, name: String) -> u32 {
+        self.chunk.locals.push(name);
+        self.chunk.locals.len() as u32 - 1
+    }
+
+    fn function<T>(&self, node: &Node<T>) -> u32 {
+        self.table
+            .symbol_id(node.span)
+            .and_then(|id| self.functions.get(&id))
+            .copied()
+            .expect("calls are resolved before compiling")
+    }
+
+    fn compile_statements(&mut self, statements: &[StatementNode]) {
+        for statement in statements {
+            self.compile_statement(statement);
+        }
+    }
+
+    fn compile_block(&mut self, block: &BlockStatementNode) {
+        self.compile_statements(&block.value.0);
+    }
+
+    fn compile_statement(&mut self, statement: &StatementNode) {
+        match &statement.value {
+            Statement::Declaration(variable) => {
+                let slot = self.slot(variable);
+                self.emit_at(Instruction::Unset(slot), variable);
+            }
+            Statement::Definition(_, _, expression)
+                if matches!(expression.value, Expression::FunctionDef(_)) => {}
+            Statement::Definition(_, variable, expression) => {
+                self.compile_expression(expression);
+                let slot = self.slot(variable);
+                self.emit_at(Instruction::Store(slot), variable);
+            }
+            Statement::Assignment(assignee, expression) => {
+                self.compile_expression(expression);
+                let slot = match &assignee.value {
+                    Assignee::Identifier(_) => self.slot(assignee),
+                };
+                self.emit_at(Instruction::Store(slot), assignee);
+            }
+            Statement::Condition(condition, consequence, alternative) => {
+                self.compile_expression(condition);
+                let skip = self.emit_at(Instruction::JumpIfFalse(0), condition);
+                self.compile_block(consequence);
+                match alternative {
+                    Some(alternative) => {
+                        let end = self.emit_at(Instruction::Jump(0), alternative);
+                        self.patch(skip);
+                        self.compile_block(alternative);
+                        self.patch(end);
+                    }
+                    None => self.patch(skip),
+                }
+            }
+            Statement::While(condition, body) => {
+                let start = self.here();
+                self.compile_expression(condition);
+                let exit = self.emit_at(Instruction::JumpIfFalse(0), condition);
+                self.compile_loop(body, start, None, exit);
+            }
+            Statement::For(init, condition, step, body) => {
+                if let Some(init) = init {
+                    self.compile_statement(init);
+                }
+                let start = self.here();
+                self.compile_expression(condition);
+                let exit = self.emit_at(Instruction::JumpIfFalse(0), condition);
+                self.compile_loop(body, start, step.as_deref(), exit);
+            }
+            Statement::Switch(scrutinee, cases, default) => {
+                // the value is kept in a slot of its own, the cases compare against it
+                self.compile_expression(scrutinee);
+                let slot = self.new_slot(String::from("<switch>"));
+                self.emit_at(Instruction::Store(slot), scrutinee);
+                let mut ends = vec![];
+                for case in cases {
+                    self.emit_at(Instruction::Load(slot), scrutinee);
+                    self.compile_expression(&case.value.value);
+                    self.emit_at(Instruction::Eq, case);
+                    let next = self.emit_at(Instruction::JumpIfFalse(0), case);
+                    self.compile_block(&case.value.body);
+                    ends.push(self.emit_at(Instruction::Jump(0), case));
+                    self.patch(next);
+                }
+                if let Some(default) = default {
+                    self.compile_block(default);
+                }
+                for end in ends {
+                    self.patch(end);
+                }
+            }
+            Statement::Break | Statement::Continue => {
+                let index = self.emit_at(Instruction::Jump(0), statement);
+                let is_break = matches!(statement.value, Statement::Break);
+                match self.loops.last_mut() {
+                    Some(labels) if is_break => labels.breaks.push(index),
+                    Some(labels) => labels.continues.push(index),
+                    // like the interpreter, outside of a loop it leaves the function
+                    None => self.chunk.code[index] = Instruction::ReturnVoid,
+                }
+            }
+            Statement::FunctionCall(_, arguments) => {
+                for argument in arguments {
+                    self.compile_expression(argument);
+                }
+                let function = self.function(statement);
+                self.emit_at(Instruction::CallVoid(function), statement);
+            }
+            Statement::Return(expression) => {
+                self.compile_expression(expression);
+                self.emit_at(Instruction::Return, statement);
+            }
+            Statement::TypeDefinition(..) | Statement::FunctionDefinition(_) | Statement::Error => {
+            }
+        }
+    }
+
+    /// Compiles the body of a loop whose condition starts at `start` and exits with the jump
+    /// at `exit`, `continue` goes to the `step` statement of a `for` loop if there is one
+    fn compile_loop(
+        &mut self,
+        body: &BlockStatementNode,
+        start: u32,
+        step: Option<&StatementNode>,
+        exit: usize,
+    ) {
+        self.loops.push(Loop::default());
+        self.compile_block(body);
+        let labels = self.loops.pop().unwrap();
+        for index in labels.continues {
+            self.patch(index);
+        }
+        if let Some(step) = step {
+            self.compile_statement(step);
+        }
+        self.emit_at(Instruction::Jump(start), body);
+        self.patch(exit);
+        for index in labels.breaks {
+            self.patch(index);
+        }
+    }
+
+    fn compile_expression(&mut self, expression: &ExpressionNode) {
+        match &expression.value {
+            Expression::Identifier(_) => {
+                let slot = self.slot(expression);
+                self.emit_at(Instruction::Load(slot), expression);
+            }
+            Expression::Literal(literal) => {
+                let value =
+                    Value::from_literal(literal).expect("only checked literals are compiled");
+                let index = self.constant(value);
+                self.emit_at(Instruction::Constant(index), expression);
+            }
+            Expression::Add(lhs, rhs) => self.binary(BinaryOperator::Add, lhs, rhs, expression),
+            Expression::Sub(lhs, rhs) => self.binary(BinaryOperator::Sub, lhs, rhs, expression),
+            Expression::Mul(lhs, rhs) => self.binary(BinaryOperator::Mul, lhs, rhs, expression),
+            Expression::Div(lhs, rhs) => self.binary(BinaryOperator::Div, lhs, rhs, expression),
+            Expression::Pow(lhs, rhs) => self.binary(BinaryOperator::Pow, lhs, rhs, expression),
+            Expression::Mod(lhs, rhs) => self.binary(BinaryOperator::Mod, lhs, rhs, expression),
+            Expression::BitAnd(lhs, rhs) => {
+                self.binary(BinaryOperator::BitAnd, lhs, rhs, expression)
+            }
+            Expression::BitOr(lhs, rhs) => self.binary(BinaryOperator::BitOr, lhs, rhs, expression),
+            Expression::BitXor(lhs, rhs) => {
+                self.binary(BinaryOperator::BitXor, lhs, rhs, expression)
+            }
+            Expression::Shl(lhs, rhs) => self.binary(BinaryOperator::Shl, lhs, rhs, expression),
+            Expression::Shr(lhs, rhs) => self.binary(BinaryOperator::Shr, lhs, rhs, expression),
+            Expression::Eq(lhs, rhs) => self.operands(Instruction::Eq, lhs, rhs, expression),
+            Expression::Neq(lhs, rhs) => self.operands(Instruction::Neq, lhs, rhs, expression),
+            Expression::Lt(lhs, rhs) => self.operands(Instruction::Lt, lhs, rhs, expression),
+            Expression::Le(lhs, rhs) => self.operands(Instruction::Le, lhs, rhs, expression),
+            Expression::Gt(lhs, rhs) => self.operands(Instruction::Gt, lhs, rhs, expression),
+            Expression::Ge(lhs, rhs) => self.operands(Instruction::Ge, lhs, rhs, expression),
+            // `&&` and `||` only evaluate their right operand when needed
+            Expression::And(lhs, rhs) => {
+                self.compile_expression(lhs);
+                let short = self.emit_at(Instruction::JumpIfFalse(0), expression);
+                self.compile_expression(rhs);
+                let end = self.emit_at(Instruction::Jump(0), expression);
+                self.patch(short);
+                let index = self.constant(Value::Boolean(false));
+                self.emit_at(Instruction::Constant(index), expression);
+                self.patch(end);
+            }
+            Expression::Or(lhs, rhs) => {
+                self.compile_expression(lhs);
+                let evaluate_rhs = self.emit_at(Instruction::JumpIfFalse(0), expression);
+                let index = self.constant(Value::Boolean(true));
+                self.emit_at(Instruction::Constant(index), expression);
+                let end = self.emit_at(Instruction::Jump(0), expression);
+                self.patch(evaluate_rhs);
+                self.compile_expression(rhs);
+                self.patch(end);
+            }
+            Expression::Not(operand) => {
+                self.compile_expression(operand);
+                self.emit_at(Instruction::Not, expression);
+            }
+            Expression::Neg(operand) => {
+                self.compile_expression(operand);
+                self.emit_at(Instruction::Neg, expression);
+            }
+            Expression::Ternary(condition, consequence, alternative) => {
+                self.compile_expression(condition);
+                let skip = self.emit_at(Instruction::JumpIfFalse(0), condition);
+                self.compile_expression(consequence);
+                let end = self.emit_at(Instruction::Jump(0), consequence);
+                self.patch(skip);
+                self.compile_expression(alternative);
+                self.patch(end);
+            }
+            Expression::FunctionCall(_, arguments) => {
+                for argument in arguments {
+                    self.compile_expression(argument);
+                }
+                let function = self.function(expression);
+                self.emit_at(Instruction::Call(function), expression);
+            }
+            Expression::FunctionDef(_) => {
+                panic!("anonymous functions are only compiled when bound with `auto`")
+            }
+        }
+    }
+
+    fn binary(
+        &mut self,
+        operator: BinaryOperator,
+        lhs: &ExpressionNode,
+        rhs: &ExpressionNode,
+        expression: &ExpressionNode,
+    ) {
+        self.operands(Instruction::Binary(operator), lhs, rhs, expression);
+    }
+
+    fn operands(
+        &mut self,
+        instruction: Instruction,
+        lhs: &ExpressionNode,
+        rhs: &ExpressionNode,
+        expression: &ExpressionNode,
+    ) {
+        self.compile_expression(lhs);
+        self.compile_expression(rhs);
+        self.emit_at(instruction, expression);
+    }
+}
+
+fn location<T>(node: &Node<T>) -> Location {
+    Location {
+        position: node.start,
+        span: node.span,
+    }
+}
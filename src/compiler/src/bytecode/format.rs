@@ -0,0 +1,780 @@
+//! The `.outb` container, a module serialized to bytes.
+//!
+//! Integers are little-endian, strings are a `u32` length followed by their UTF-8 bytes:
+//!
+//! ```text
+//! magic       b"OUTB"
+//! version     u16
+//! constants   u32 count, then per constant a tag byte and its value
+//! functions   u32 count, then per function its name, parameter count, local names,
+//!             code (u32 count of instructions, each an opcode byte and maybe a u32 operand)
+//!             and line table (u32 count of rows, each the first instruction of a run
+//!             sharing a location, then the line, column, offset, span start and span end)
+//! checksum    u32, CRC-32 of every byte before it
+//! ```
+
+use super::{binary_type, neg_type, Chunk, Instruction, Location, Module, MAIN};
+use crate::ast::types::Type;
+use crate::interpreter::value::{BinaryOperator, Value};
+use crate::position::{Position, Span};
+use core::fmt;
+use std::convert::TryFrom;
+
+pub const MAGIC: [u8; 4] = *b"OUTB";
+
+/// Bumped on every change of the layout, files of another version are rejected
+pub const VERSION: u16 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatError {
+    /// the bytes do not start with `MAGIC`
+    NotBytecode,
+    UnsupportedVersion(u16),
+    /// the bytes end before the module does
+    Truncated,
+    ChecksumMismatch,
+    /// bytes that do not form a valid module, such as an unknown opcode or a jump out of
+    /// its function
+    Invalid(String),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormatError::NotBytecode => write!(f, "Not an outer bytecode file"),
+            FormatError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported bytecode version {}, expected version {}",
+                version, VERSION
+            ),
+            FormatError::Truncated => write!(f, "Truncated bytecode file"),
+            FormatError::ChecksumMismatch => write!(f, "Corrupted bytecode file, wrong checksum"),
+            FormatError::Invalid(reason) => write!(f, "Invalid bytecode file, {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+/// CRC-32 with the polynomial of zlib and PNG
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+const BINARY_OPERATORS: [BinaryOperator; 11] = [
+    BinaryOperator::Add,
+    BinaryOperator::Sub,
+    BinaryOperator::Mul,
+    BinaryOperator::Div,
+    BinaryOperator::Pow,
+    BinaryOperator::Mod,
+    BinaryOperator::BitAnd,
+    BinaryOperator::BitOr,
+    BinaryOperator::BitXor,
+    BinaryOperator::Shl,
+    BinaryOperator::Shr,
+];
+
+/// Opcode of the first binary operator, the others follow in the order of `BINARY_OPERATORS`
+const BINARY: u8 = 0x10;
+
+/// Opcode and operand of an instruction
+fn opcode(instruction: Instruction) -> (u8, Option<u32>) {
+    match instruction {
+        Instruction::Constant(index) => (0x01, Some(index)),
+        Instruction::Load(slot) => (0x02, Some(slot)),
+        Instruction::Store(slot) => (0x03, Some(slot)),
+        Instruction::Unset(slot) => (0x04, Some(slot)),
+        Instruction::Binary(operator) => {
+            let index = BINARY_OPERATORS.iter().position(|o| *o == operator);
+            (BINARY + index.unwrap() as u8, None)
+        }
+        Instruction::Neg => (0x20, None),
+        Instruction::Not => (0x21, None),
+        Instruction::Eq => (0x22, None),
+        Instruction::Neq => (0x23, None),
+        Instruction::Lt => (0x24, None),
+        Instruction::Le => (0x25, None),
+        Instruction::Gt => (0x26, None),
+        Instruction::Ge => (0x27, None),
+        Instruction::Jump(target) => (0x30, Some(target)),
+        Instruction::JumpIfFalse(target) => (0x31, Some(target)),
+        Instruction::Call(function) => (0x32, Some(function)),
+        Instruction::CallVoid(function) => (0x33, Some(function)),
+        Instruction::Return => (0x34, None),
+        Instruction::ReturnVoid => (0x35, None),
+    }
+}
+
+/// Serializes a module into the `.outb` format
+pub fn encode(module: &Module) -> Vec<u8> {
+    let mut writer = Writer { bytes: vec![] };
+    writer.bytes.extend_from_slice(&MAGIC);
+    writer.bytes.extend_from_slice(&VERSION.to_le_bytes());
+
+    writer.length(module.constants.len());
+    for constant in &module.constants {
+        match constant {
+            Value::Int32(v) => writer.tagged(0, &v.to_le_bytes()),
+            Value::Int64(v) => writer.tagged(1, &v.to_le_bytes()),
+            Value::UInt32(v) => writer.tagged(2, &v.to_le_bytes()),
+            Value::UInt64(v) => writer.tagged(3, &v.to_le_bytes()),
+            Value::Boolean(v) => writer.tagged(4, &[*v as u8]),
+            Value::String(v) => {
+                writer.bytes.push(5);
+                writer.string(v);
+            }
+        }
+    }
+
+    writer.length(module.functions.len());
+    for chunk in &module.functions {
+        writer.string(&chunk.name);
+        writer.u32(chunk.parameters);
+        writer.length(chunk.locals.len());
+        for local in &chunk.locals {
+            writer.string(local);
+        }
+
+        writer.length(chunk.code.len());
+        for instruction in &chunk.code {
+            let (opcode, operand) = opcode(*instruction);
+            writer.bytes.push(opcode);
+            if let Some(operand) = operand {
+                writer.u32(operand);
+            }
+        }
+
+        // consecutive instructions mostly come from the same node, only changes are stored
+        let mut rows = vec![];
+        for (offset, location) in chunk.locations.iter().enumerate() {
+            if rows.last().is_none_or(|(_, last)| last != location) {
+                rows.push((offset, *location));
+            }
+        }
+        writer.length(rows.len());
+        for (offset, location) in rows {
+            writer.length(offset);
+            writer.length(location.position.line);
+            writer.length(location.position.col);
+            writer.length(location.position.offset);
+            writer.length(location.span.lo);
+            writer.length(location.span.hi);
+        }
+    }
+
+    let checksum = crc32(&writer.bytes);
+    writer.u32(checksum);
+    writer.bytes
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn length(&mut self, value: usize) {
+        self.u32(u32::try_from(value).expect("module too large for the bytecode format"));
+    }
+
+    fn tagged(&mut self, tag: u8, value: &[u8]) {
+        self.bytes.push(tag);
+        self.bytes.extend_from_slice(value);
+    }
+
+    fn string(&mut self, value: &str) {
+        self.length(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+}
+
+/// Reads a module in the `.outb` format, checking that the VM can run its instructions
+pub fn decode(bytes: &[u8]) -> Result<Module, FormatError> {
+    if !bytes.starts_with(&MAGIC) {
+        return Err(FormatError::NotBytecode);
+    }
+    let mut reader = Reader {
+        bytes,
+        position: MAGIC.len(),
+    };
+    let version = u16::from_le_bytes(reader.array()?);
+    if version != VERSION {
+        return Err(FormatError::UnsupportedVersion(version));
+    }
+    if bytes.len() < reader.position + 4 {
+        return Err(FormatError::Truncated);
+    }
+    let (content, checksum) = bytes.split_at(bytes.len() - 4);
+    if crc32(content) != u32::from_le_bytes(<[u8; 4]>::try_from(checksum).unwrap()) {
+        return Err(FormatError::ChecksumMismatch);
+    }
+    reader.bytes = content;
+
+    let mut constants = vec![];
+    for _ in 0..reader.count(2)? {
+        let constant = match reader.byte()? {
+            0 => Value::Int32(i32::from_le_bytes(reader.array()?)),
+            1 => Value::Int64(i64::from_le_bytes(reader.array()?)),
+            2 => Value::UInt32(u32::from_le_bytes(reader.array()?)),
+            3 => Value::UInt64(u64::from_le_bytes(reader.array()?)),
+            4 => Value::Boolean(reader.byte()? != 0),
+            5 => Value::String(reader.string()?),
+            tag => return Err(invalid(format!("unknown constant tag {}", tag))),
+        };
+        constants.push(constant);
+    }
+
+    let mut functions = vec![];
+    for _ in 0..reader.count(20)? {
+        let mut chunk = Chunk::new(reader.string()?);
+        chunk.parameters = reader.u32()?;
+        for _ in 0..reader.count(4)? {
+            chunk.locals.push(reader.string()?);
+        }
+        for _ in 0..reader.count(1)? {
+            chunk.code.push(reader.instruction()?);
+        }
+
+        let mut rows = vec![];
+        for _ in 0..reader.count(24)? {
+            let offset = reader.u32()? as usize;
+            let position = Position {
+                line: reader.u32()? as usize,
+                col: reader.u32()? as usize,
+                offset: reader.u32()? as usize,
+            };
+            let span = Span::new(0, reader.u32()? as usize, reader.u32()? as usize);
+            rows.push((offset, Location { position, span }));
+        }
+        if rows.first().is_some_and(|(offset, _)| *offset != 0)
+            || rows.windows(2).any(|rows| rows[0].0 >= rows[1].0)
+        {
+            return Err(invalid(format!("unordered line table in `{}`", chunk.name)));
+        }
+        if rows.iter().any(|(offset, _)| *offset >= chunk.code.len()) {
+            return Err(invalid(format!(
+                "line table past the code of `{}`",
+                chunk.name
+            )));
+        }
+        for (i, (start, location)) in rows.iter().enumerate() {
+            let end = rows.get(i + 1).map_or(chunk.code.len(), |row| row.0);
+            chunk
+                .locations
+                .extend(std::iter::repeat_n(*location, end.saturating_sub(*start)));
+        }
+        chunk
+            .locations
+            .resize(chunk.code.len(), Location::default());
+        functions.push(chunk);
+    }
+    if reader.position != reader.bytes.len() {
+        return Err(invalid(String::from(
+            "unexpected bytes after the functions",
+        )));
+    }
+
+    let module = Module {
+        constants,
+        functions,
+    };
+    validate(&module)?;
+    Ok(module)
+}
+
+fn invalid(reason: String) -> FormatError {
+    FormatError::Invalid(reason)
+}
+
+/// Checks the instructions of a module, the VM trusts them: every index is in range, no
+/// function runs past its last instruction and every instruction finds its operands, of the
+/// types it takes, on the stack
+fn validate(module: &Module) -> Result<(), FormatError> {
+    let main = match module.functions.get(MAIN as usize) {
+        Some(main) => main,
+        None => return Err(invalid(String::from("no function to run"))),
+    };
+    if main.parameters != 0 {
+        return Err(invalid(format!("`{}` takes parameters", main.name)));
+    }
+    for chunk in &module.functions {
+        if chunk.parameters as usize > chunk.locals.len() {
+            return Err(invalid(format!(
+                "more parameters than locals in `{}`",
+                chunk.name
+            )));
+        }
+        // a function must not run past its last instruction
+        if !matches!(
+            chunk.code.last(),
+            Some(Instruction::Return | Instruction::ReturnVoid | Instruction::Jump(_))
+        ) {
+            return Err(invalid(format!(
+                "`{}` does not end with a return",
+                chunk.name
+            )));
+        }
+        for instruction in &chunk.code {
+            let in_range = match *instruction {
+                Instruction::Constant(index) => (index as usize) < module.constants.len(),
+                Instruction::Load(slot) | Instruction::Store(slot) | Instruction::Unset(slot) => {
+                    (slot as usize) < chunk.locals.len()
+                }
+                Instruction::Jump(target) | Instruction::JumpIfFalse(target) => {
+                    (target as usize) < chunk.code.len()
+                }
+                // the program cannot be called, see `Types::instruction`
+                Instruction::Call(function) | Instruction::CallVoid(function) => {
+                    function != MAIN && (function as usize) < module.functions.len()
+                }
+                _ => true,
+            };
+            if !in_range {
+                return Err(invalid(format!(
+                    "`{}` out of range in `{}`",
+                    instruction, chunk.name
+                )));
+            }
+        }
+    }
+    Types::check(module)
+}
+
+/// The type of a value on the stack, in a local or returned by a function, `None` where no
+/// value can be at runtime, such as in a local nothing is stored in
+type Slot = Option<Type>;
+
+/// Infers the types of the locals and of the values returned by every function, checking
+/// each instruction against them until nothing changes
+struct Types<'a> {
+    module: &'a Module,
+    locals: Vec<Vec<Slot>>,
+    returns: Vec<Slot>,
+    changed: bool,
+}
+
+impl<'a> Types<'a> {
+    fn check(module: &'a Module) -> Result<(), FormatError> {
+        let mut types = Types {
+            module,
+            locals: module
+                .functions
+                .iter()
+                .map(|chunk| vec![None; chunk.locals.len()])
+                .collect(),
+            returns: vec![None; module.functions.len()],
+            changed: true,
+        };
+        while types.changed {
+            types.changed = false;
+            for function in 0..module.functions.len() {
+                types.function(function)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Follows every path of a function from its first instruction, with the types on the
+    /// stack before each instruction, which have to be the same on every path to it
+    fn function(&mut self, function: usize) -> Result<(), FormatError> {
+        let chunk = &self.module.functions[function];
+        let mut stacks: Vec<Option<Vec<Slot>>> = vec![None; chunk.code.len()];
+        stacks[0] = Some(vec![]);
+        let mut pending = vec![0];
+        while let Some(ip) = pending.pop() {
+            let mut stack = stacks[ip].clone().unwrap();
+            let instruction = chunk.code[ip];
+            self.instruction(function, instruction, &mut stack)
+                .map_err(|reason| {
+                    invalid(format!(
+                        "`{}` at {:04} in `{}` {}",
+                        instruction, ip, chunk.name, reason
+                    ))
+                })?;
+
+            let next = match instruction {
+                Instruction::Jump(target) => vec![target as usize],
+                Instruction::JumpIfFalse(target) => vec![ip + 1, target as usize],
+                Instruction::Return | Instruction::ReturnVoid => vec![],
+                _ => vec![ip + 1],
+            };
+            for target in next {
+                let merged = match &stacks[target] {
+                    None => stack.clone(),
+                    Some(known) => merge(known, &stack).ok_or_else(|| {
+                        invalid(format!(
+                            "the paths to {:04} in `{}` leave different values on the stack",
+                            target, chunk.name
+                        ))
+                    })?,
+                };
+                if stacks[target].as_ref() != Some(&merged) {
+                    stacks[target] = Some(merged);
+                    pending.push(target);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies an instruction to the types on the stack, the error completes a sentence about
+    /// the instruction
+    fn instruction(
+        &mut self,
+        function: usize,
+        instruction: Instruction,
+        stack: &mut Vec<Slot>,
+    ) -> Result<(), String> {
+        let module = self.module;
+        let mut pop = || {
+            stack
+                .pop()
+                .ok_or_else(|| String::from("pops an empty stack"))
+        };
+        let wrong_types = || String::from("takes operands of the wrong type");
+        let boolean = |slot: Slot| match slot {
+            None | Some(Type::Boolean) => Ok(()),
+            Some(_) => Err(wrong_types()),
+        };
+
+        // the type of the value pushed, if any
+        let pushed = match instruction {
+            Instruction::Constant(index) => Some(Some(module.constants[index as usize].ty())),
+            Instruction::Load(slot) => Some(self.locals[function][slot as usize].clone()),
+            Instruction::Store(slot) => {
+                let value = pop()?;
+                if !unify(
+                    &mut self.locals[function][slot as usize],
+                    value,
+                    &mut self.changed,
+                ) {
+                    return Err(String::from("stores a value of another type"));
+                }
+                None
+            }
+            Instruction::Unset(_) | Instruction::Jump(_) | Instruction::ReturnVoid => None,
+            Instruction::Binary(operator) => match (pop()?, pop()?) {
+                (Some(rhs), Some(lhs)) => Some(Some(
+                    binary_type(operator, &lhs, &rhs).ok_or_else(wrong_types)?,
+                )),
+                _ => Some(None),
+            },
+            Instruction::Neg => match pop()? {
+                Some(operand) => Some(Some(neg_type(&operand).ok_or_else(wrong_types)?)),
+                None => Some(None),
+            },
+            Instruction::Not => {
+                boolean(pop()?)?;
+                Some(Some(Type::Boolean))
+            }
+            Instruction::Eq
+            | Instruction::Neq
+            | Instruction::Lt
+            | Instruction::Le
+            | Instruction::Gt
+            | Instruction::Ge => {
+                if let (Some(rhs), Some(lhs)) = (pop()?, pop()?) {
+                    if lhs != rhs {
+                        return Err(wrong_types());
+                    }
+                }
+                Some(Some(Type::Boolean))
+            }
+            Instruction::JumpIfFalse(_) => {
+                boolean(pop()?)?;
+                None
+            }
+            Instruction::Call(callee) | Instruction::CallVoid(callee) => {
+                let callee = callee as usize;
+                let parameters = module.functions[callee].parameters as usize;
+                for parameter in (0..parameters).rev() {
+                    let argument = pop()?;
+                    if !unify(
+                        &mut self.locals[callee][parameter],
+                        argument,
+                        &mut self.changed,
+                    ) {
+                        return Err(String::from("passes an argument of another type"));
+                    }
+                }
+                match instruction {
+                    Instruction::Call(_) => Some(self.returns[callee].clone()),
+                    _ => None,
+                }
+            }
+            Instruction::Return => {
+                let value = pop()?;
+                // the program is never called, its top level returns may differ in type
+                if function != MAIN as usize
+                    && !unify(&mut self.returns[function], value, &mut self.changed)
+                {
+                    return Err(String::from("returns a value of another type"));
+                }
+                None
+            }
+        };
+        stack.extend(pushed);
+        Ok(())
+    }
+}
+
+/// Records that a value of type `value` goes in `slot`, false if it holds another type
+fn unify(slot: &mut Slot, value: Slot, changed: &mut bool) -> bool {
+    match (&slot, value) {
+        (_, None) => true,
+        (None, value) => {
+            *slot = value;
+            *changed = true;
+            true
+        }
+        (Some(ty), Some(value)) => *ty == value,
+    }
+}
+
+/// The types on the stack at an instruction reached by two paths, `None` if they differ
+fn merge(known: &[Slot], stack: &[Slot]) -> Option<Vec<Slot>> {
+    if known.len() != stack.len() {
+        return None;
+    }
+    known
+        .iter()
+        .zip(stack)
+        .map(|slots| match slots {
+            (Some(a), Some(b)) if a != b => None,
+            (Some(ty), _) | (None, Some(ty)) => Some(Some(ty.clone())),
+            (None, None) => Some(None),
+        })
+        .collect()
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], FormatError> {
+        let end = self
+            .position
+            .checked_add(count)
+            .ok_or(FormatError::Truncated)?;
+        let bytes = self
+            .bytes
+            .get(self.position..end)
+            .ok_or(FormatError::Truncated)?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], FormatError> {
+        Ok(<[u8; N]>::try_from(self.take(N)?).unwrap())
+    }
+
+    fn byte(&mut self) -> Result<u8, FormatError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, FormatError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    /// Reads the length of a list whose elements take at least `size` bytes each, which
+    /// have to fit in the bytes left
+    fn count(&mut self, size: usize) -> Result<u32, FormatError> {
+        let count = self.u32()?;
+        let left = self.bytes.len() - self.position;
+        if (count as usize).saturating_mul(size) > left {
+            return Err(FormatError::Truncated);
+        }
+        Ok(count)
+    }
+
+    fn string(&mut self) -> Result<String, FormatError> {
+        let length = self.u32()? as usize;
+        String::from_utf8(self.take(length)?.to_vec())
+            .map_err(|_| invalid(String::from("a string is not valid UTF-8")))
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, FormatError> {
+        let opcode = self.byte()?;
+        let instruction = match opcode {
+            0x01 => Instruction::Constant(self.u32()?),
+            0x02 => Instruction::Load(self.u32()?),
+            0x03 => Instruction::Store(self.u32()?),
+            0x04 => Instruction::Unset(self.u32()?),
+            _ if (BINARY..BINARY + BINARY_OPERATORS.len() as u8).contains(&opcode) => {
+                Instruction::Binary(BINARY_OPERATORS[(opcode - BINARY) as usize])
+            }
+            0x20 => Instruction::Neg,
+            0x21 => Instruction::Not,
+            0x22 => Instruction::Eq,
+            0x23 => Instruction::Neq,
+            0x24 => Instruction::Lt,
+            0x25 => Instruction::Le,
+            0x26 => Instruction::Gt,
+            0x27 => Instruction::Ge,
+            0x30 => Instruction::Jump(self.u32()?),
+            0x31 => Instruction::JumpIfFalse(self.u32()?),
+            0x32 => Instruction::Call(self.u32()?),
+            0x33 => Instruction::CallVoid(self.u32()?),
+            0x34 => Instruction::Return,
+            0x35 => Instruction::ReturnVoid,
+            _ => return Err(invalid(format!("unknown opcode {:#04x}", opcode))),
+        };
+        Ok(instruction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::Vm;
+    use crate::testing::{self, PROGRAMS};
+
+    #[test]
+    fn round_trip() {
+        for program in PROGRAMS {
+            let checked = testing::check(program);
+            let module = checked.compile();
+            let decoded = decode(&encode(&module)).unwrap();
+            assert_eq!(decoded, module, "{}", program);
+
+            // errors are still reported at the position of the source
+            let result = Vm::new(&decoded).run().map_err(|e| e.to_string());
+            assert_eq!(result, testing::interpret(&checked), "{}", program);
+        }
+    }
+
+    #[test]
+    fn reject_invalid_files() {
+        let module = testing::check("auto a = 1; return a + 2;").compile();
+        let bytes = encode(&module);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+
+        assert_eq!(decode(b"\x7fELF"), Err(FormatError::NotBytecode));
+
+        let mut newer = bytes.clone();
+        newer[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        let error = decode(&newer).unwrap_err();
+        assert_eq!(error, FormatError::UnsupportedVersion(VERSION + 1));
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Unsupported bytecode version {}, expected version {}",
+                VERSION + 1,
+                VERSION
+            )
+        );
+
+        let mut corrupted = bytes.clone();
+        corrupted[12] ^= 1;
+        assert_eq!(decode(&corrupted), Err(FormatError::ChecksumMismatch));
+        assert_eq!(decode(&bytes[..5]), Err(FormatError::Truncated));
+
+        // a valid checksum over a call to a function that does not exist
+        let mut module = module;
+        module.functions[0].code[2] = Instruction::Call(7);
+        assert_eq!(
+            decode(&encode(&module)),
+            Err(FormatError::Invalid(String::from(
+                "`call @7` out of range in `<program>`"
+            )))
+        );
+
+        // counts and line table offsets past the end, with a valid checksum
+        let with_checksum = |mut bytes: Vec<u8>| {
+            let end = bytes.len() - 4;
+            let checksum = crc32(&bytes[..end]);
+            bytes[end..].copy_from_slice(&checksum.to_le_bytes());
+            bytes
+        };
+        let bytes = encode(&testing::check("auto a = 1; return a;").compile());
+        let mut constants = bytes.clone();
+        constants[6..10].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            decode(&with_checksum(constants)),
+            Err(FormatError::Truncated)
+        );
+        // the offset of the last row of the line table, the 24 bytes before the checksum
+        let mut rows = bytes.clone();
+        let offset = rows.len() - 28;
+        rows[offset..offset + 4].copy_from_slice(&0x7fff_fff0u32.to_le_bytes());
+        assert_eq!(
+            decode(&with_checksum(rows)),
+            Err(FormatError::Invalid(String::from(
+                "line table past the code of `<program>`"
+            )))
+        );
+    }
+
+    #[test]
+    fn reject_malformed_code() {
+        // `constant #0, store 0, load 0, constant #1, binary +, return, return_void`
+        let module = testing::check("auto a = 1; return a + 2;").compile();
+        let run = |module: &Module| Vm::new(module).run().map_err(|e| e.to_string());
+        let rejected = |module: &Module| match decode(&encode(module)) {
+            Err(FormatError::Invalid(reason)) => reason,
+            result => panic!("{:?} was not rejected: {:?}", module, result),
+        };
+
+        let mut empty_stack = module.clone();
+        empty_stack.functions[0].code[0] = Instruction::Not;
+        assert_eq!(
+            rejected(&empty_stack),
+            "`not` at 0000 in `<program>` pops an empty stack"
+        );
+        // the VM reports it too when it runs a module which was not decoded
+        assert_eq!(
+            run(&empty_stack),
+            Err(String::from(
+                "Invalid bytecode, the value stack is empty at 1:10"
+            ))
+        );
+
+        let mut wrong_type = module.clone();
+        wrong_type.constants[0] = Value::Boolean(true);
+        assert_eq!(
+            rejected(&wrong_type),
+            "`binary +` at 0004 in `<program>` takes operands of the wrong type"
+        );
+        assert_eq!(
+            run(&wrong_type),
+            Err(String::from(
+                "Invalid bytecode, `+` applied to `bool` and `i32` at 1:20"
+            ))
+        );
+
+        let mut parameters = module.clone();
+        parameters.functions[0].parameters = 1;
+        assert_eq!(rejected(&parameters), "`<program>` takes parameters");
+        assert_eq!(
+            run(&parameters),
+            Err(String::from(
+                "Invalid bytecode, missing arguments for `<program>` at 1:1"
+            ))
+        );
+
+        // `constant #1` is only pushed when the jump is not taken
+        let mut unbalanced = module;
+        unbalanced.constants[0] = Value::Boolean(false);
+        unbalanced.functions[0].code[1] = Instruction::JumpIfFalse(3);
+        unbalanced.functions[0].code[2] = Instruction::Constant(1);
+        unbalanced.functions[0].code[4] = Instruction::Return;
+        assert_eq!(
+            rejected(&unbalanced),
+            "the paths to 0003 in `<program>` leave different values on the stack"
+        );
+    }
+}
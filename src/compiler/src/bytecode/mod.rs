@@ -0,0 +1,245 @@
+//! Stack-based bytecode: a checked program is compiled into a `Module` which the `Vm` executes.
+//!
+//! Instructions take their operands from the value stack and push their result on it.
+//! Variables live in the local slots of the function they are declared in, the top level
+//! statements of the program form a function of their own, always the first one.
+
+use crate::ast::types::Type;
+use crate::interpreter::value::{BinaryOperator, Value};
+use crate::position::{Position, Span};
+use core::fmt;
+
+pub mod compiler;
+pub mod format;
+pub mod vm;
+
+pub use compiler::compile;
+pub use format::{decode, encode, FormatError};
+pub use vm::Vm;
+
+/// Index of the function run first, made of the top level statements
+pub const MAIN: u32 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    /// Pushes a value of the constant pool
+    Constant(u32),
+    /// Pushes the value of a local, an error if it is not initialized
+    Load(u32),
+    /// Pops a value into a local
+    Store(u32),
+    /// Marks a local as uninitialized, for declarations run again in a loop
+    Unset(u32),
+    Binary(BinaryOperator),
+    Neg,
+    Not,
+    Eq,
+    Neq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// Continues at an instruction of the same function
+    Jump(u32),
+    /// Pops a `bool`, jumps if it is `false`
+    JumpIfFalse(u32),
+    /// Pops the arguments of a function and calls it, the value it returns is pushed
+    Call(u32),
+    /// Calls a function as a statement, discarding the value it may return
+    CallVoid(u32),
+    /// Pops the value returned to the caller
+    Return,
+    /// Returns without a value
+    ReturnVoid,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Constant(index) => write!(f, "constant #{}", index),
+            Instruction::Load(slot) => write!(f, "load {}", slot),
+            Instruction::Store(slot) => write!(f, "store {}", slot),
+            Instruction::Unset(slot) => write!(f, "unset {}", slot),
+            Instruction::Binary(operator) => write!(f, "binary {}", operator),
+            Instruction::Neg => write!(f, "neg"),
+            Instruction::Not => write!(f, "not"),
+            Instruction::Eq => write!(f, "eq"),
+            Instruction::Neq => write!(f, "neq"),
+            Instruction::Lt => write!(f, "lt"),
+            Instruction::Le => write!(f, "le"),
+            Instruction::Gt => write!(f, "gt"),
+            Instruction::Ge => write!(f, "ge"),
+            Instruction::Jump(target) => write!(f, "jump {:04}", target),
+            Instruction::JumpIfFalse(target) => write!(f, "jump_if_false {:04}", target),
+            Instruction::Call(function) => write!(f, "call @{}", function),
+            Instruction::CallVoid(function) => write!(f, "call_void @{}", function),
+            Instruction::Return => write!(f, "return"),
+            Instruction::ReturnVoid => write!(f, "return_void"),
+        }
+    }
+}
+
+/// Where the source of an instruction starts, used to report runtime errors
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Location {
+    pub position: Position,
+    pub span: Span,
+}
+
+/// The compiled code of a function
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub name: String,
+    /// the parameters are the first locals, the arguments are stored in them by a call
+    pub parameters: u32,
+    /// name of every local slot, for errors and the disassembly
+    pub locals: Vec<String>,
+    pub code: Vec<Instruction>,
+    /// location of each instruction of `code`
+    pub locations: Vec<Location>,
+}
+
+impl Chunk {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            parameters: 0,
+            locals: vec![],
+            code: vec![],
+            locations: vec![],
+        }
+    }
+}
+
+/// A compiled program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    pub constants: Vec<Value>,
+    /// the functions of the program, starting with `MAIN`
+    pub functions: Vec<Chunk>,
+}
+
+/// Type of the result of `Instruction::Binary`, `None` for operands `Value::binary` rejects
+pub(crate) fn binary_type(operator: BinaryOperator, lhs: &Type, rhs: &Type) -> Option<Type> {
+    match (lhs, rhs) {
+        (Type::Int(_) | Type::UInt(_), _) if lhs == rhs => Some(lhs.clone()),
+        (Type::String, Type::String) if operator == BinaryOperator::Add => Some(Type::String),
+        _ => None,
+    }
+}
+
+/// Type of the result of `Instruction::Neg`, `None` for operands `Value::neg` rejects
+pub(crate) fn neg_type(operand: &Type) -> Option<Type> {
+    match operand {
+        Type::Int(32 | 64) => Some(operand.clone()),
+        _ => None,
+    }
+}
+
+/// Disassembles the module, one instruction per line with the source line it comes from
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "constants:")?;
+        for (index, constant) in self.constants.iter().enumerate() {
+            match constant {
+                Value::String(s) => writeln!(f, "    #{} {} {:?}", index, constant.ty(), s)?,
+                _ => writeln!(f, "    #{} {} {}", index, constant.ty(), constant)?,
+            }
+        }
+        for (index, chunk) in self.functions.iter().enumerate() {
+            writeln!(
+                f,
+                "\nfunction @{} {} ({} parameters, {} locals):",
+                index,
+                chunk.name,
+                chunk.parameters,
+                chunk.locals.len()
+            )?;
+            let mut line = None;
+            for (offset, (instruction, location)) in
+                chunk.code.iter().zip(&chunk.locations).enumerate()
+            {
+                // the line is only printed when it changes
+                let source = if line == Some(location.position.line) {
+                    String::new()
+                } else {
+                    line = Some(location.position.line);
+                    (location.position.line + 1).to_string()
+                };
+                let instruction = instruction.to_string();
+                let comment = match chunk.code[offset] {
+                    Instruction::Constant(index) => match &self.constants[index as usize] {
+                        Value::String(s) => format!("{:?}", s),
+                        value => value.to_string(),
+                    },
+                    Instruction::Load(slot)
+                    | Instruction::Store(slot)
+                    | Instruction::Unset(slot) => chunk.locals[slot as usize].clone(),
+                    Instruction::Call(function) | Instruction::CallVoid(function) => {
+                        self.functions[function as usize].name.clone()
+                    }
+                    _ => String::new(),
+                };
+                if comment.is_empty() {
+                    writeln!(f, "    {:04} {:>4}  {}", offset, source, instruction)?;
+                } else {
+                    writeln!(
+                        f,
+                        "    {:04} {:>4}  {:<20} ; {}",
+                        offset, source, instruction, comment
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, PROGRAMS};
+
+    /// The harness: every program gives the same result, or the same error at the same
+    /// position, on the interpreter and on the VM
+    #[test]
+    fn same_results_as_the_interpreter() {
+        for program in PROGRAMS {
+            let checked = testing::check(program);
+            let module = compile(&checked.program, &checked.symbols);
+            let result = Vm::new(&module).run().map_err(|e| e.to_string());
+            assert_eq!(result, testing::interpret(&checked), "{}", program);
+        }
+    }
+
+    #[test]
+    fn disassemble() {
+        let checked = testing::check(
+            "func half(n: u32): u32 {\n  return n / 2;\n}\nauto s = \"a\";\nreturn half(7);",
+        );
+        let module = compile(&checked.program, &checked.symbols);
+        assert_eq!(
+            module.to_string(),
+            r#"constants:
+    #0 string "a"
+    #1 u32 7
+    #2 u32 2
+
+function @0 <program> (0 parameters, 1 locals):
+    0000    4  constant #0          ; "a"
+    0001       store 0              ; s
+    0002    5  constant #1          ; 7
+    0003       call @1              ; half
+    0004       return
+    0005       return_void
+
+function @1 half (1 parameters, 1 locals):
+    0000    2  load 0               ; n
+    0001       constant #2          ; 2
+    0002       binary /
+    0003       return
+    0004    1  return_void
+"#
+        );
+    }
+}
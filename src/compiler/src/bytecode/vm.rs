@@ -0,0 +1,200 @@
+use super::{binary_type, neg_type, Instruction, Location, Module, MAIN};
+use crate::interpreter::value::Value;
+use crate::interpreter::{RuntimeError, RuntimeErrorKind, MAX_CALL_DEPTH};
+
+/// A function call being executed
+struct Frame {
+    function: usize,
+    /// index of the next instruction
+    ip: usize,
+    locals: Vec<Option<Value>>,
+    /// height of the value stack when the call started
+    base: usize,
+    /// called as a statement, the value it returns is dropped
+    void: bool,
+}
+
+/// Executes the bytecode of a module, with the same semantics and errors as the interpreter
+pub struct Vm<'a> {
+    module: &'a Module,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(module: &'a Module) -> Self {
+        Self {
+            module,
+            stack: vec![],
+            frames: vec![],
+        }
+    }
+
+    /// Runs the program, returns the value of its top level `return` if it reaches one
+    pub fn run(&mut self) -> Result<Option<Value>, RuntimeError> {
+        self.stack.clear();
+        self.frames.clear();
+        self.push_frame(MAIN as usize, false)?;
+
+        let module = self.module;
+        loop {
+            let frame = self.frames.last_mut().unwrap();
+            let instruction = module.functions[frame.function].code[frame.ip];
+            frame.ip += 1;
+
+            match instruction {
+                Instruction::Constant(index) => {
+                    self.stack.push(module.constants[index as usize].clone())
+                }
+                Instruction::Load(slot) => match &frame.locals[slot as usize] {
+                    Some(value) => self.stack.push(value.clone()),
+                    None => {
+                        let name = &module.functions[frame.function].locals[slot as usize];
+                        return Err(
+                            self.error(RuntimeErrorKind::UninitializedVariable(name.clone()))
+                        );
+                    }
+                },
+                Instruction::Store(slot) => {
+                    let value = self.pop()?;
+                    self.frames.last_mut().unwrap().locals[slot as usize] = Some(value);
+                }
+                Instruction::Unset(slot) => frame.locals[slot as usize] = None,
+                Instruction::Binary(operator) => {
+                    let rhs = self.pop()?;
+                    let lhs = self.pop()?;
+                    if binary_type(operator, &lhs.ty(), &rhs.ty()).is_none() {
+                        return Err(self.invalid(format!(
+                            "`{}` applied to `{}` and `{}`",
+                            operator,
+                            lhs.ty(),
+                            rhs.ty()
+                        )));
+                    }
+                    match Value::binary(operator, &lhs, &rhs) {
+                        Ok(value) => self.stack.push(value),
+                        Err(e) => return Err(self.error(RuntimeErrorKind::Arithmetic(e))),
+                    }
+                }
+                Instruction::Neg => {
+                    let operand = self.pop()?;
+                    if neg_type(&operand.ty()).is_none() {
+                        return Err(self.invalid(format!("`-` applied to `{}`", operand.ty())));
+                    }
+                    match operand.neg() {
+                        Ok(value) => self.stack.push(value),
+                        Err(e) => return Err(self.error(RuntimeErrorKind::Arithmetic(e))),
+                    }
+                }
+                Instruction::Not => {
+                    let value = self.pop_bool()?;
+                    self.stack.push(Value::Boolean(!value));
+                }
+                Instruction::Eq => self.compare(|a, b| a == b)?,
+                Instruction::Neq => self.compare(|a, b| a != b)?,
+                Instruction::Lt => self.compare(|a, b| a < b)?,
+                Instruction::Le => self.compare(|a, b| a <= b)?,
+                Instruction::Gt => self.compare(|a, b| a > b)?,
+                Instruction::Ge => self.compare(|a, b| a >= b)?,
+                Instruction::Jump(target) => frame.ip = target as usize,
+                Instruction::JumpIfFalse(target) => {
+                    if !self.pop_bool()? {
+                        self.frames.last_mut().unwrap().ip = target as usize;
+                    }
+                }
+                Instruction::Call(function) | Instruction::CallVoid(function) => {
+                    if self.frames.len() > MAX_CALL_DEPTH {
+                        return Err(self.error(RuntimeErrorKind::StackOverflow));
+                    }
+                    let void = matches!(instruction, Instruction::CallVoid(_));
+                    self.push_frame(function as usize, void)?;
+                }
+                Instruction::Return => {
+                    let value = self.pop()?;
+                    let frame = self.pop_frame();
+                    if self.frames.is_empty() {
+                        return Ok(Some(value));
+                    }
+                    if !frame.void {
+                        self.stack.push(value);
+                    }
+                }
+                Instruction::ReturnVoid => {
+                    let frame = self.pop_frame();
+                    if self.frames.is_empty() {
+                        return Ok(None);
+                    }
+                    // reported at the call, which is the current instruction of the caller
+                    if !frame.void {
+                        let name = module.functions[frame.function].name.clone();
+                        return Err(self.error(RuntimeErrorKind::NoReturnValue(name)));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Starts a call, its arguments are taken from the top of the stack
+    fn push_frame(&mut self, function: usize, void: bool) -> Result<(), RuntimeError> {
+        let chunk = &self.module.functions[function];
+        let base = match self.stack.len().checked_sub(chunk.parameters as usize) {
+            Some(base) => base,
+            None => {
+                return Err(self.invalid(format!("missing arguments for `{}`", chunk.name)));
+            }
+        };
+        let mut locals: Vec<Option<Value>> = self.stack.drain(base..).map(Some).collect();
+        locals.resize(chunk.locals.len(), None);
+        self.frames.push(Frame {
+            function,
+            ip: 0,
+            locals,
+            base,
+            void,
+        });
+        Ok(())
+    }
+
+    fn pop_frame(&mut self) -> Frame {
+        let frame = self.frames.pop().unwrap();
+        self.stack.truncate(frame.base);
+        frame
+    }
+
+    fn pop(&mut self) -> Result<Value, RuntimeError> {
+        match self.stack.pop() {
+            Some(value) => Ok(value),
+            None => Err(self.invalid(String::from("the value stack is empty"))),
+        }
+    }
+
+    fn pop_bool(&mut self) -> Result<bool, RuntimeError> {
+        let value = self.pop()?;
+        match value.as_bool() {
+            Some(value) => Ok(value),
+            None => Err(self.invalid(format!("expected a `bool`, found `{}`", value.ty()))),
+        }
+    }
+
+    fn compare(&mut self, predicate: fn(&Value, &Value) -> bool) -> Result<(), RuntimeError> {
+        let rhs = self.pop()?;
+        let lhs = self.pop()?;
+        self.stack.push(Value::Boolean(predicate(&lhs, &rhs)));
+        Ok(())
+    }
+
+    /// An error at the instruction being executed, at the start of the program before the
+    /// first one
+    fn error(&self, kind: RuntimeErrorKind) -> RuntimeError {
+        let location = match self.frames.last() {
+            Some(frame) => self.module.functions[frame.function].locations[frame.ip - 1],
+            None => Location::default(),
+        };
+        RuntimeError::new(kind, location.position, location.span)
+    }
+
+    /// A module `decode` would have rejected, built by hand
+    fn invalid(&self, reason: String) -> RuntimeError {
+        self.error(RuntimeErrorKind::InvalidBytecode(reason))
+    }
+}
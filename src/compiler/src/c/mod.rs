@@ -0,0 +1,642 @@
+//! C backend: lowers a checked program to a single C99 file, with the runtime of `runtime.h`
+//! bundled at its top.
+//!
+//! Every intermediate value is stored in a temporary, so that operands are evaluated from
+//! left to right like in the interpreter, whatever order the C compiler picks. The top level
+//! statements form `main`, which prints the value of a top level `return`.
+
+use crate::ast::node::Node;
+use crate::ast::types::Type;
+use crate::ast::{
+    Assignee, BlockStatementNode, Expression, ExpressionNode, Function, Literal, Program,
+    Statement, StatementNode, SwitchCaseNode,
+};
+use crate::bytecode::compiler::collect_functions;
+use crate::resolver::{SymbolId, SymbolTable};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+const RUNTIME: &str = include_str!("runtime.h");
+
+/// Lowers a checked program, `table` is the symbol table the resolver built for it
+pub fn transpile(program: &Program, table: &SymbolTable) -> String {
+    let mut functions = vec![];
+    collect_functions(table, &program.0, &mut functions);
+
+    let mut transpiler = Transpiler {
+        table,
+        functions: functions.iter().copied().collect(),
+        variables: HashMap::new(),
+        flagged: HashSet::new(),
+        body: String::new(),
+        indent: 1,
+        temporaries: 0,
+        labels: 0,
+        loops: vec![],
+        in_function: false,
+    };
+
+    let mut output = format!("/* Generated by outer */\n\n{}\n", RUNTIME);
+    for (id, function) in &functions {
+        let _ = writeln!(output, "{};", transpiler.signature(*id, function));
+    }
+    for (id, function) in &functions {
+        transpiler.in_function = true;
+        transpiler.statement_line("outer_depth++;");
+        transpiler.statements(&function.statements);
+        match &function.return_type {
+            // every path returns, the checker made sure of it, but C compilers cannot tell
+            Some(ty) if !ends_with_return(&function.statements) => {
+                transpiler.statement_line(&format!("return {};", zero(ty)))
+            }
+            Some(_) => {}
+            None => transpiler.statement_line("outer_depth--;"),
+        }
+        let _ = write!(
+            output,
+            "\n{} {{\n{}}}\n",
+            transpiler.signature(*id, function),
+            std::mem::take(&mut transpiler.body)
+        );
+    }
+
+    transpiler.in_function = false;
+    transpiler.statements(&program.0);
+    if !matches!(
+        program.0.last(),
+        Some(Node {
+            value: Statement::Return(_),
+            ..
+        })
+    ) {
+        transpiler.statement_line("return 0;");
+    }
+    let _ = write!(output, "\nint main(void) {{\n{}}}\n", transpiler.body);
+    output
+}
+
+struct Transpiler<'a> {
+    table: &'a SymbolTable,
+    functions: HashMap<SymbolId, &'a Function>,
+    /// types of the variables and parameters met so far
+    variables: HashMap<SymbolId, Type>,
+    /// variables declared without a value, which carry a flag telling if they are initialized
+    flagged: HashSet<SymbolId>,
+    /// the C statements of the function being lowered
+    body: String,
+    indent: usize,
+    temporaries: usize,
+    labels: usize,
+    /// for each enclosing loop, the label `continue` jumps to if it is not the loop start
+    loops: Vec<Option<String>>,
+    /// whether a `return` leaves a function, or prints the result of the program
+    in_function: bool,
+}
+
+impl<'a> Transpiler<'a> {
+    fn signature(&mut self, id: SymbolId, function: &Function) -> String {
+        let parameters: Vec<String> = function
+            .parameters
+            .iter()
+            .map(|parameter| {
+                let ty = parameter.value.ty.clone().expect("parameters are typed");
+                let name = self.declare(parameter, ty.clone());
+                format!("{} {}", c_type(&ty), name)
+            })
+            .collect();
+        let output = function.return_type.as_ref().map_or("void", c_type);
+        let parameters = if parameters.is_empty() {
+            String::from("void")
+        } else {
+            parameters.join(", ")
+        };
+        format!("static {} {}({})", output, self.name(id), parameters)
+    }
+
+    /// C name of a symbol, the id keeps it unique and apart from C keywords
+    fn name(&self, id: SymbolId) -> String {
+        format!("{}_{}", self.table.symbol(id).name, id)
+    }
+
+    fn symbol<T>(&self, node: &Node<T>) -> SymbolId {
+        self.table
+            .symbol_id(node.span)
+            .expect("names are resolved before lowering to C")
+    }
+
+    /// Registers the variable declared at `node`, returns its C name
+    fn declare<T>(&mut self, node: &Node<T>, ty: Type) -> String {
+        let id = self.symbol(node);
+        self.variables.insert(id, ty);
+        self.name(id)
+    }
+
+    fn statement_line(&mut self, line: &str) {
+        for _ in 0..self.indent {
+            self.body.push_str("    ");
+        }
+        self.body.push_str(line);
+        self.body.push('\n');
+    }
+
+    /// Stores a value in a new temporary, returns its name
+    fn temporary(&mut self, ty: &Type, value: &str) -> String {
+        self.temporaries += 1;
+        let name = format!("t{}", self.temporaries);
+        self.statement_line(&format!("{} {} = {};", c_type(ty), name, value));
+        name
+    }
+
+    fn label(&mut self) -> String {
+        self.labels += 1;
+        format!("continue_{}", self.labels)
+    }
+
+    fn statements(&mut self, statements: &[StatementNode]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn block(&mut self, block: &BlockStatementNode) {
+        self.indent += 1;
+        self.statements(&block.value.0);
+        self.indent -= 1;
+    }
+
+    fn statement(&mut self, statement: &StatementNode) {
+        match &statement.value {
+            Statement::Declaration(variable) => {
+                let ty = variable.value.ty.clone().expect("declarations are typed");
+                let name = self.declare(variable, ty.clone());
+                self.flagged.insert(self.symbol(variable));
+                self.statement_line(&format!("{} {} = {};", c_type(&ty), name, zero(&ty)));
+                self.statement_line(&format!("bool {}_set = false;", name));
+            }
+            Statement::Definition(_, _, expression)
+                if matches!(expression.value, Expression::FunctionDef(_)) => {}
+            Statement::Definition(_, variable, expression) => {
+                let (value, value_ty) = self.expression(expression);
+                let ty = variable.value.ty.clone().unwrap_or(value_ty);
+                let name = self.declare(variable, ty.clone());
+                self.statement_line(&format!("{} {} = {};", c_type(&ty), name, value));
+            }
+            Statement::Assignment(assignee, expression) => {
+                let (value, _) = self.expression(expression);
+                let id = match &assignee.value {
+                    Assignee::Identifier(_) => self.symbol(assignee),
+                };
+                let name = self.name(id);
+                self.statement_line(&format!("{} = {};", name, value));
+                if self.flagged.contains(&id) {
+                    self.statement_line(&format!("{}_set = true;", name));
+                }
+            }
+            Statement::Condition(condition, consequence, alternative) => {
+                let (condition, _) = self.expression(condition);
+                self.statement_line(&format!("if ({}) {{", condition));
+                self.block(consequence);
+                if let Some(alternative) = alternative {
+                    self.statement_line("} else {");
+                    self.block(alternative);
+                }
+                self.statement_line("}");
+            }
+            Statement::While(condition, body) => {
+                self.statement_line("for (;;) {");
+                self.indent += 1;
+                let (condition, _) = self.expression(condition);
+                self.statement_line(&format!("if (!{}) break;", condition));
+                self.loops.push(None);
+                self.statements(&body.value.0);
+                self.loops.pop();
+                self.indent -= 1;
+                self.statement_line("}");
+            }
+            Statement::For(init, condition, step, body) => {
+                // the block scopes the variable of `init`
+                self.statement_line("{");
+                self.indent += 1;
+                if let Some(init) = init {
+                    self.statement(init);
+                }
+                self.statement_line("for (;;) {");
+                self.indent += 1;
+                let (condition, _) = self.expression(condition);
+                self.statement_line(&format!("if (!{}) break;", condition));
+                // `continue` has to run `step`, which the C `continue` would skip
+                let label = step.as_ref().map(|_| self.label());
+                self.loops.push(label.clone());
+                self.statement_line("{");
+                self.block(body);
+                self.statement_line("}");
+                self.loops.pop();
+                if let (Some(label), Some(step)) = (label, step) {
+                    self.statement_line(&format!("{}:;", label));
+                    self.statement(step);
+                }
+                self.indent -= 1;
+                self.statement_line("}");
+                self.indent -= 1;
+                self.statement_line("}");
+            }
+            Statement::Switch(scrutinee, cases, default) => {
+                let (value, ty) = self.expression(scrutinee);
+                let value = self.temporary(&ty, &value);
+                self.switch_cases(&value, &ty, cases, default.as_ref());
+            }
+            Statement::Break | Statement::Continue => {
+                let is_break = matches!(statement.value, Statement::Break);
+                match self.loops.last() {
+                    Some(_) if is_break => self.statement_line("break;"),
+                    Some(Some(label)) => {
+                        let line = format!("goto {};", label);
+                        self.statement_line(&line)
+                    }
+                    Some(None) => self.statement_line("continue;"),
+                    // like the interpreter, outside of a loop it leaves the function
+                    None if self.in_function => {
+                        self.statement_line("outer_depth--;");
+                        self.statement_line("return;");
+                    }
+                    None => self.statement_line("return 0;"),
+                }
+            }
+            Statement::FunctionCall(id, arguments) => {
+                let call = self.call(id, arguments, statement);
+                self.statement_line(&format!("{};", call));
+            }
+            Statement::Return(expression) => {
+                let (value, ty) = self.expression(expression);
+                if self.in_function {
+                    self.statement_line("outer_depth--;");
+                    self.statement_line(&format!("return {};", value));
+                } else {
+                    self.statement_line(&format!("{}({});", print_function(&ty), value));
+                    self.statement_line("return 0;");
+                }
+            }
+            Statement::TypeDefinition(..) | Statement::FunctionDefinition(_) | Statement::Error => {
+            }
+        }
+    }
+
+    /// Lowers the cases of a `switch` to nested `if`s, so that the value of a case is only
+    /// evaluated when the previous ones did not match
+    fn switch_cases(
+        &mut self,
+        value: &str,
+        ty: &Type,
+        cases: &[SwitchCaseNode],
+        default: Option<&BlockStatementNode>,
+    ) {
+        match cases.split_first() {
+            Some((case, rest)) => {
+                let (case_value, _) = self.expression(&case.value.value);
+                let matches = equals(ty, value, &case_value);
+                self.statement_line(&format!("if ({}) {{", matches));
+                self.block(&case.value.body);
+                self.statement_line("} else {");
+                self.indent += 1;
+                self.switch_cases(value, ty, rest, default);
+                self.indent -= 1;
+                self.statement_line("}");
+            }
+            None => {
+                if let Some(default) = default {
+                    self.statements(&default.value.0);
+                }
+            }
+        }
+    }
+
+    /// Lowers a call, the depth of the calls is checked before evaluating the arguments
+    fn call<T>(&mut self, id: &str, arguments: &[ExpressionNode], node: &Node<T>) -> String {
+        let symbol = self.symbol(node);
+        debug_assert_eq!(self.table.symbol(symbol).name, id);
+        self.statement_line(&format!("outer_check_depth(\"{}\");", node.start));
+        let arguments: Vec<String> = arguments
+            .iter()
+            .map(|argument| self.expression(argument).0)
+            .collect();
+        format!("{}({})", self.name(symbol), arguments.join(", "))
+    }
+
+    /// Lowers an expression, returns a C expression without side effects holding its value,
+    /// along with its type
+    fn expression(&mut self, expression: &ExpressionNode) -> (String, Type) {
+        let at = expression.start.to_string();
+        match &expression.value {
+            Expression::Identifier(id) => {
+                let symbol = self.symbol(expression);
+                let name = self.name(symbol);
+                if self.flagged.contains(&symbol) {
+                    let message = c_string(&format!("Use of uninitialized variable `{}`", id));
+                    self.statement_line(&format!(
+                        "if (!{}_set) outer_fail({}, \"{}\");",
+                        name, message, at
+                    ));
+                }
+                let ty = self.variables[&symbol].clone();
+                (name, ty)
+            }
+            Expression::Literal(literal) => literal_value(literal),
+            Expression::Add(lhs, rhs) => self.arithmetic("add", lhs, rhs, &at),
+            Expression::Sub(lhs, rhs) => self.arithmetic("sub", lhs, rhs, &at),
+            Expression::Mul(lhs, rhs) => self.arithmetic("mul", lhs, rhs, &at),
+            Expression::Div(lhs, rhs) => self.arithmetic("div", lhs, rhs, &at),
+            Expression::Pow(lhs, rhs) => self.arithmetic("pow", lhs, rhs, &at),
+            Expression::Mod(lhs, rhs) => self.arithmetic("mod", lhs, rhs, &at),
+            Expression::Shl(lhs, rhs) => self.arithmetic("shl", lhs, rhs, &at),
+            Expression::Shr(lhs, rhs) => self.arithmetic("shr", lhs, rhs, &at),
+            Expression::BitAnd(lhs, rhs) => self.operator("&", lhs, rhs, None),
+            Expression::BitOr(lhs, rhs) => self.operator("|", lhs, rhs, None),
+            Expression::BitXor(lhs, rhs) => self.operator("^", lhs, rhs, None),
+            Expression::Lt(lhs, rhs) => self.operator("<", lhs, rhs, Some(Type::Boolean)),
+            Expression::Le(lhs, rhs) => self.operator("<=", lhs, rhs, Some(Type::Boolean)),
+            Expression::Gt(lhs, rhs) => self.operator(">", lhs, rhs, Some(Type::Boolean)),
+            Expression::Ge(lhs, rhs) => self.operator(">=", lhs, rhs, Some(Type::Boolean)),
+            Expression::Eq(lhs, rhs) | Expression::Neq(lhs, rhs) => {
+                let (lhs, ty) = self.expression(lhs);
+                let (rhs, _) = self.expression(rhs);
+                let mut value = equals(&ty, &lhs, &rhs);
+                if matches!(expression.value, Expression::Neq(..)) {
+                    value = format!("!({})", value);
+                }
+                (self.temporary(&Type::Boolean, &value), Type::Boolean)
+            }
+            // `&&` and `||` only evaluate their right operand when needed
+            Expression::And(lhs, rhs) | Expression::Or(lhs, rhs) => {
+                let is_and = matches!(expression.value, Expression::And(..));
+                let (lhs, _) = self.expression(lhs);
+                let result = self.temporary(&Type::Boolean, &lhs);
+                let test = if is_and {
+                    result.clone()
+                } else {
+                    format!("!{}", result)
+                };
+                self.statement_line(&format!("if ({}) {{", test));
+                self.indent += 1;
+                let (rhs, _) = self.expression(rhs);
+                self.statement_line(&format!("{} = {};", result, rhs));
+                self.indent -= 1;
+                self.statement_line("}");
+                (result, Type::Boolean)
+            }
+            Expression::Not(operand) => {
+                let (operand, _) = self.expression(operand);
+                let value = format!("!{}", operand);
+                (self.temporary(&Type::Boolean, &value), Type::Boolean)
+            }
+            Expression::Neg(operand) => {
+                let (operand, ty) = self.expression(operand);
+                let value = format!("outer_neg_{}({}, \"{}\")", ty, operand, at);
+                (self.temporary(&ty, &value), ty)
+            }
+            Expression::Ternary(condition, consequence, alternative) => {
+                let (condition, _) = self.expression(condition);
+                self.temporaries += 1;
+                let result = format!("t{}", self.temporaries);
+                let declaration = self.body.len();
+                self.statement_line(&format!("if ({}) {{", condition));
+                self.indent += 1;
+                let (value, ty) = self.expression(consequence);
+                self.statement_line(&format!("{} = {};", result, value));
+                self.indent -= 1;
+                self.statement_line("} else {");
+                self.indent += 1;
+                let (value, _) = self.expression(alternative);
+                self.statement_line(&format!("{} = {};", result, value));
+                self.indent -= 1;
+                self.statement_line("}");
+                // the type is only known once a branch is lowered
+                let indent = "    ".repeat(self.indent);
+                self.body.insert_str(
+                    declaration,
+                    &format!("{}{} {} = {};\n", indent, c_type(&ty), result, zero(&ty)),
+                );
+                (result, ty)
+            }
+            Expression::FunctionCall(id, arguments) => {
+                let function = self.functions[&self.symbol(expression)];
+                let ty = function
+                    .return_type
+                    .clone()
+                    .expect("called functions return a value");
+                let call = self.call(id, arguments, expression);
+                (self.temporary(&ty, &call), ty)
+            }
+            Expression::FunctionDef(_) => {
+                panic!("anonymous functions are only lowered when bound with `auto`")
+            }
+        }
+    }
+
+    /// An operator of the runtime, checking for overflows
+    fn arithmetic(
+        &mut self,
+        operator: &str,
+        lhs: &ExpressionNode,
+        rhs: &ExpressionNode,
+        at: &str,
+    ) -> (String, Type) {
+        let (lhs, ty) = self.expression(lhs);
+        let (rhs, _) = self.expression(rhs);
+        let value = match (&ty, operator) {
+            (Type::String, "add") => format!("outer_concat({}, {})", lhs, rhs),
+            (Type::String, _) => unreachable!("only `+` applies to strings"),
+            (Type::Int(_), "mul") | (Type::UInt(_), "mul") => {
+                format!("outer_mul_{}({}, {}, \"*\", \"{}\")", ty, lhs, rhs, at)
+            }
+            _ => format!("outer_{}_{}({}, {}, \"{}\")", operator, ty, lhs, rhs, at),
+        };
+        (self.temporary(&ty, &value), ty)
+    }
+
+    /// A C operator which cannot fail, `ty` is the type of the result if it is not the type
+    /// of the operands
+    fn operator(
+        &mut self,
+        operator: &str,
+        lhs: &ExpressionNode,
+        rhs: &ExpressionNode,
+        ty: Option<Type>,
+    ) -> (String, Type) {
+        let (lhs, operand_ty) = self.expression(lhs);
+        let (rhs, _) = self.expression(rhs);
+        let ty = ty.unwrap_or(operand_ty);
+        let value = format!("{} {} {}", lhs, operator, rhs);
+        (self.temporary(&ty, &value), ty)
+    }
+}
+
+fn ends_with_return(statements: &[StatementNode]) -> bool {
+    matches!(
+        statements.last(),
+        Some(Node {
+            value: Statement::Return(_),
+            ..
+        })
+    )
+}
+
+fn c_type(ty: &Type) -> &'static str {
+    match ty {
+        Type::Int(32) => "int32_t",
+        Type::Int(64) => "int64_t",
+        Type::UInt(32) => "uint32_t",
+        Type::UInt(64) => "uint64_t",
+        Type::Boolean => "bool",
+        Type::String => "outer_string",
+        Type::Int(_) | Type::UInt(_) => panic!("unsupported integer type `{}`", ty),
+    }
+}
+
+fn zero(ty: &Type) -> &'static str {
+    match ty {
+        Type::Boolean => "false",
+        Type::String => "OUTER_STRING(\"\")",
+        _ => "0",
+    }
+}
+
+fn print_function(ty: &Type) -> String {
+    format!("outer_print_{}", ty)
+}
+
+fn equals(ty: &Type, lhs: &str, rhs: &str) -> String {
+    match ty {
+        Type::String => format!("outer_string_eq({}, {})", lhs, rhs),
+        _ => format!("{} == {}", lhs, rhs),
+    }
+}
+
+fn literal_value(literal: &Literal) -> (String, Type) {
+    let value = match literal {
+        Literal::Int32(i32::MIN) => String::from("INT32_MIN"),
+        Literal::Int64(i64::MIN) => String::from("INT64_MIN"),
+        Literal::Int32(v) => format!("INT32_C({})", v),
+        Literal::Int64(v) => format!("INT64_C({})", v),
+        Literal::UInt32(v) => format!("UINT32_C({})", v),
+        Literal::UInt64(v) => format!("UINT64_C({})", v),
+        Literal::Boolean(v) => v.to_string(),
+        Literal::String(v) => format!("OUTER_STRING({})", c_string(v)),
+        Literal::Array(_) | Literal::Null => panic!("only checked literals are lowered"),
+    };
+    let ty = literal.default_type().unwrap();
+    (value, ty)
+}
+
+/// A C string literal, bytes outside of printable ASCII are escaped in octal
+fn c_string(value: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in value.bytes() {
+        match byte {
+            b'"' | b'\\' | b'?' => {
+                literal.push('\\');
+                literal.push(byte as char);
+            }
+            0x20..=0x7e => literal.push(byte as char),
+            _ => {
+                let _ = write!(literal, "\\{:03o}", byte);
+            }
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, PROGRAMS};
+    use std::env;
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::process::Command;
+
+    /// Compiles C source with the system `cc` and runs it, returns the exit code, stdout and
+    /// stderr, or `None` if there is no C compiler
+    fn compile_and_run(source: &str, name: &str) -> Option<(i32, String, String)> {
+        let directory = env::temp_dir().join(format!("outer-c-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&directory).unwrap();
+        let (c_file, executable) = (directory.join("main.c"), directory.join("main"));
+        fs::write(&c_file, source).unwrap();
+
+        let compiled = Command::new("cc")
+            .args([
+                "-std=c99",
+                "-pedantic",
+                "-Wall",
+                "-Wextra",
+                "-Wno-unused",
+                "-Werror",
+            ])
+            .args(["-Wno-infinite-recursion"])
+            .arg(&c_file)
+            .arg("-o")
+            .arg(&executable)
+            .output();
+        let compiled = match compiled {
+            Err(error) if error.kind() == ErrorKind::NotFound => return None,
+            compiled => compiled.unwrap(),
+        };
+        assert!(
+            compiled.status.success(),
+            "{}\n{}",
+            String::from_utf8_lossy(&compiled.stderr),
+            source
+        );
+
+        let output = Command::new(&executable).output().unwrap();
+        let _ = fs::remove_dir_all(&directory);
+        Some((
+            output.status.code().unwrap(),
+            String::from_utf8(output.stdout).unwrap(),
+            String::from_utf8(output.stderr).unwrap(),
+        ))
+    }
+
+    /// Output of `outer run` for the result of the interpreter
+    fn expected_output(result: Result<Option<crate::Value>, String>) -> (i32, String, String) {
+        match result {
+            Ok(Some(value)) => (0, format!("{}\n", value), String::new()),
+            Ok(None) => (0, String::new(), String::new()),
+            Err(error) => (1, String::new(), format!("{}\n", error)),
+        }
+    }
+
+    #[test]
+    fn same_output_as_the_interpreter() {
+        for (index, program) in PROGRAMS.iter().enumerate() {
+            let checked = testing::check(program);
+            let source = transpile(&checked.program, &checked.symbols);
+            let output = match compile_and_run(&source, &index.to_string()) {
+                Some(output) => output,
+                None => return eprintln!("no C compiler, skipping"),
+            };
+            assert_eq!(
+                output,
+                expected_output(testing::interpret(&checked)),
+                "{}",
+                program
+            );
+        }
+    }
+
+    #[test]
+    fn strings_and_names() {
+        let checked = testing::check(
+            "auto s = \"tab\t ??= \\\\ ü\";\nauto int = 2;\nreturn s + \"!\" + (int == 2 ? \"\" : \"?\");",
+        );
+        let source = transpile(&checked.program, &checked.symbols);
+        assert!(
+            source.contains(r#"outer_string s_0 = OUTER_STRING("tab\011 \?\?= \\\\ \303\274");"#)
+        );
+        assert!(source.contains("int32_t int_1 = INT32_C(2);"));
+
+        if let Some(output) = compile_and_run(&source, "strings") {
+            assert_eq!(output, expected_output(testing::interpret(&checked)));
+        }
+    }
+}
@@ -0,0 +1,178 @@
+/* Runtime of the C code generated by outer, bundled at the top of every generated file.
+ *
+ * Integer operators follow the fixed-width semantics of outer: overflows, divisions by zero
+ * and negative exponents are runtime errors, which print a message to stderr and exit with
+ * status 1. Strings are immutable byte slices, concatenations are never freed. */
+
+#include <inttypes.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#define OUTER_MAX_CALL_DEPTH 1000
+
+typedef struct {
+    const char *data;
+    size_t length;
+} outer_string;
+
+#define OUTER_STRING(literal) ((outer_string){ literal, sizeof(literal) - 1 })
+
+static int outer_depth = 0;
+
+/* Reports a runtime error at `at`, a `line:column` position of the source */
+static inline void outer_fail(const char *message, const char *at) {
+    fflush(stdout);
+    fprintf(stderr, "%s at %s\n", message, at);
+    exit(1);
+}
+
+static inline void outer_overflow(const char *operator, const char *type, const char *at) {
+    fflush(stdout);
+    fprintf(stderr, "Overflow in `%s` on `%s` at %s\n", operator, type, at);
+    exit(1);
+}
+
+/* Checked before the arguments of a call are evaluated, like the interpreter does */
+static inline void outer_check_depth(const char *at) {
+    if (outer_depth >= OUTER_MAX_CALL_DEPTH) {
+        fflush(stdout);
+        fprintf(stderr, "Stack overflow, more than %d nested function calls at %s\n",
+                OUTER_MAX_CALL_DEPTH, at);
+        exit(1);
+    }
+}
+
+static inline outer_string outer_concat(outer_string a, outer_string b) {
+    char *data = malloc(a.length + b.length + 1);
+    if (data == NULL) {
+        fputs("Out of memory\n", stderr);
+        exit(1);
+    }
+    memcpy(data, a.data, a.length);
+    memcpy(data + a.length, b.data, b.length);
+    return (outer_string){ data, a.length + b.length };
+}
+
+static inline bool outer_string_eq(outer_string a, outer_string b) {
+    return a.length == b.length && memcmp(a.data, b.data, a.length) == 0;
+}
+
+static inline void outer_print_string(outer_string value) {
+    fwrite(value.data, 1, value.length, stdout);
+    putchar('\n');
+}
+
+static inline void outer_print_bool(bool value) {
+    puts(value ? "true" : "false");
+}
+
+/* Operators shared by signed and unsigned integers, `pow` and shifts take their amount as a
+ * `uint32_t` like the interpreter, a larger or negative amount overflows */
+#define OUTER_INTEGER(name, type, utype, width)                                                 \
+    static inline type outer_pow_##name(type a, type b, const char *at) {                       \
+        type result = 1;                                                                        \
+        uint64_t exponent = (uint64_t)b;                                                        \
+        if (outer_negative_##name(b)) outer_fail("Negative exponent in `**`", at);              \
+        if (exponent > UINT32_MAX) outer_overflow("**", #name, at);                             \
+        while (exponent > 0) {                                                                  \
+            if (exponent & 1) result = outer_mul_##name(result, a, "**", at);                   \
+            exponent >>= 1;                                                                     \
+            if (exponent > 0) a = outer_mul_##name(a, a, "**", at);                             \
+        }                                                                                       \
+        return result;                                                                          \
+    }                                                                                           \
+    /* bits shifted out, or into the sign bit, overflow */                                     \
+    static inline type outer_shl_##name(type a, type b, const char *at) {                       \
+        type result;                                                                            \
+        if (outer_negative_##name(b) || (uint64_t)b >= width)                                   \
+            outer_overflow("<<", #name, at);                                                    \
+        result = (type)((utype)a << b);                                                         \
+        if (result >> b != a) outer_overflow("<<", #name, at);                                  \
+        return result;                                                                          \
+    }                                                                                           \
+    static inline type outer_shr_##name(type a, type b, const char *at) {                       \
+        if (outer_negative_##name(b) || (uint64_t)b >= width)                                   \
+            outer_overflow(">>", #name, at);                                                    \
+        return a >> b;                                                                          \
+    }                                                                                           \
+    static inline void outer_print_##name(type value) {                                         \
+        printf("%" OUTER_FORMAT_##name "\n", value);                                            \
+    }
+
+#define OUTER_SIGNED(name, type, utype, min, max, width)                                        \
+    static inline bool outer_negative_##name(type value) {                                      \
+        return value < 0;                                                                       \
+    }                                                                                           \
+    static inline type outer_add_##name(type a, type b, const char *at) {                       \
+        if ((b > 0 && a > max - b) || (b < 0 && a < min - b))                                   \
+            outer_overflow("+", #name, at);                                                     \
+        return a + b;                                                                           \
+    }                                                                                           \
+    static inline type outer_sub_##name(type a, type b, const char *at) {                       \
+        if ((b < 0 && a > max + b) || (b > 0 && a < min + b))                                   \
+            outer_overflow("-", #name, at);                                                     \
+        return a - b;                                                                           \
+    }                                                                                           \
+    /* `operator` is `**` when called by `pow` */                                               \
+    static inline type outer_mul_##name(type a, type b, const char *operator, const char *at) { \
+        bool overflow = a > 0 ? (b > 0 ? a > max / b : b < min / a)                             \
+                              : (b > 0 ? a < min / b : (a != 0 && b < max / a));                \
+        if (overflow) outer_overflow(operator, #name, at);                                      \
+        return a * b;                                                                           \
+    }                                                                                           \
+    static inline type outer_div_##name(type a, type b, const char *at) {                       \
+        if (b == 0) outer_fail("Attempt to divide by zero", at);                                \
+        if (a == min && b == -1) outer_overflow("/", #name, at);                                \
+        return a / b;                                                                           \
+    }                                                                                           \
+    static inline type outer_mod_##name(type a, type b, const char *at) {                       \
+        if (b == 0) outer_fail("Attempt to divide by zero", at);                                \
+        if (a == min && b == -1) outer_overflow("%", #name, at);                                \
+        return a % b;                                                                           \
+    }                                                                                           \
+    static inline type outer_neg_##name(type a, const char *at) {                               \
+        if (a == min) outer_overflow("-", #name, at);                                           \
+        return -a;                                                                              \
+    }                                                                                           \
+    OUTER_INTEGER(name, type, utype, width)
+
+#define OUTER_UNSIGNED(name, type, max, width)                                                  \
+    static inline bool outer_negative_##name(type value) {                                      \
+        (void)value;                                                                            \
+        return false;                                                                           \
+    }                                                                                           \
+    static inline type outer_add_##name(type a, type b, const char *at) {                       \
+        if (a > max - b) outer_overflow("+", #name, at);                                        \
+        return a + b;                                                                           \
+    }                                                                                           \
+    static inline type outer_sub_##name(type a, type b, const char *at) {                       \
+        if (a < b) outer_overflow("-", #name, at);                                              \
+        return a - b;                                                                           \
+    }                                                                                           \
+    static inline type outer_mul_##name(type a, type b, const char *operator, const char *at) { \
+        if (b != 0 && a > max / b) outer_overflow(operator, #name, at);                         \
+        return a * b;                                                                           \
+    }                                                                                           \
+    static inline type outer_div_##name(type a, type b, const char *at) {                       \
+        if (b == 0) outer_fail("Attempt to divide by zero", at);                                \
+        return a / b;                                                                           \
+    }                                                                                           \
+    static inline type outer_mod_##name(type a, type b, const char *at) {                       \
+        if (b == 0) outer_fail("Attempt to divide by zero", at);                                \
+        return a % b;                                                                           \
+    }                                                                                           \
+    OUTER_INTEGER(name, type, type, width)
+
+#define OUTER_FORMAT_i32 PRId32
+#define OUTER_FORMAT_i64 PRId64
+#define OUTER_FORMAT_u32 PRIu32
+#define OUTER_FORMAT_u64 PRIu64
+
+OUTER_SIGNED(i32, int32_t, uint32_t, INT32_MIN, INT32_MAX, 32)
+OUTER_SIGNED(i64, int64_t, uint64_t, INT64_MIN, INT64_MAX, 64)
+OUTER_UNSIGNED(u32, uint32_t, UINT32_MAX, 32)
+OUTER_UNSIGNED(u64, uint64_t, UINT64_MAX, 64)
@@ -0,0 +1,436 @@
+//! Definite assignment: a variable declared without a value, `let a: i32;`, can only be read
+//! once every path from its declaration assigns it.
+//!
+//! The analysis follows the structured control flow of the syntax tree. It runs after constant
+//! folding, so that `while true` loops are only left by a `break` and `if` statements with a
+//! constant condition only take one branch.
+
+use crate::ast::{
+    Assignee, BlockStatementNode, Expression, ExpressionNode, Literal, Program, Statement,
+    StatementNode,
+};
+use crate::checker::is_exhaustive;
+use crate::diagnostics::Diagnostic;
+use crate::position::{Position, Span};
+use crate::resolver::{SymbolId, SymbolTable};
+use core::fmt;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone)]
+pub struct InitializationError {
+    id: String,
+    position: Position,
+    span: Span,
+    /// the declaration of the variable
+    declaration: Span,
+}
+
+impl InitializationError {
+    pub fn new(id: String, position: Position, span: Span, declaration: Span) -> Self {
+        Self {
+            id,
+            position,
+            span,
+            declaration,
+        }
+    }
+    pub fn position(&self) -> Position {
+        self.position
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+    pub fn declaration(&self) -> Span {
+        self.declaration
+    }
+}
+
+impl fmt::Display for InitializationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Use of possibly uninitialized variable `{}` at {}",
+            self.id, self.position
+        )
+    }
+}
+
+impl std::error::Error for InitializationError {}
+
+impl From<&InitializationError> for Diagnostic {
+    fn from(error: &InitializationError) -> Self {
+        Diagnostic::error(format!(
+            "Use of possibly uninitialized variable `{}`",
+            error.id
+        ))
+        .with_label(error.span, String::new())
+        .with_secondary_label(
+            error.declaration,
+            String::from("declared here without a value"),
+        )
+        .with_note(String::from(
+            "a variable has to be assigned on every path before it is read",
+        ))
+    }
+}
+
+/// Checks that no variable of a checked program is read before it is assigned. Variables
+/// declared outside of `program`, by the previous inputs of a REPL, are not checked.
+pub fn check_initialization(
+    program: &Program,
+    table: &SymbolTable,
+) -> Result<(), Vec<InitializationError>> {
+    let mut analysis = Analysis {
+        table,
+        declarations: HashMap::new(),
+        loops: vec![],
+        errors: vec![],
+    };
+    analysis.statements(&program.0, Some(HashSet::new()));
+    match analysis.errors.is_empty() {
+        true => Ok(()),
+        false => Err(analysis.errors),
+    }
+}
+
+/// The variables declared without a value which are assigned on every path to a point of the
+/// program, `None` where the program cannot get to
+type State = Option<HashSet<SymbolId>>;
+
+/// The state after either of two paths
+fn join(a: State, b: State) -> State {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.intersection(&b).copied().collect()),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// The states of the `break` and `continue` statements of a loop
+struct Loop {
+    breaks: State,
+    continues: State,
+}
+
+struct Analysis<'a> {
+    table: &'a SymbolTable,
+    /// span of every variable declared without a value so far
+    declarations: HashMap<SymbolId, Span>,
+    loops: Vec<Loop>,
+    errors: Vec<InitializationError>,
+}
+
+impl<'a> Analysis<'a> {
+    fn statements(&mut self, statements: &[StatementNode], mut state: State) -> State {
+        for statement in statements {
+            state = self.statement(statement, state);
+        }
+        state
+    }
+
+    fn block(&mut self, block: &BlockStatementNode, state: State) -> State {
+        self.statements(&block.value.0, state)
+    }
+
+    fn assign(&self, span: Span, state: &mut State) {
+        if let (Some(id), Some(state)) = (self.table.symbol_id(span), state) {
+            state.insert(id);
+        }
+    }
+
+    /// Returns the state after a statement, nothing is reported in code which cannot run but
+    /// the functions it defines are analysed
+    fn statement(&mut self, statement: &StatementNode, mut state: State) -> State {
+        match &statement.value {
+            Statement::Declaration(variable) => {
+                if let Some(id) = self.table.symbol_id(variable.span) {
+                    self.declarations.insert(id, variable.span);
+                    // declared again by each iteration of a loop
+                    if let Some(state) = &mut state {
+                        state.remove(&id);
+                    }
+                }
+                state
+            }
+            Statement::Definition(_, _, expression)
+                if matches!(expression.value, Expression::FunctionDef(_)) =>
+            {
+                if let Expression::FunctionDef(function) = &expression.value {
+                    self.function(&function.value.statements);
+                }
+                state
+            }
+            Statement::Definition(_, variable, expression) => {
+                self.expression(expression, &state);
+                self.assign(variable.span, &mut state);
+                state
+            }
+            Statement::Assignment(assignee, expression) => {
+                self.expression(expression, &state);
+                match &assignee.value {
+                    Assignee::Identifier(_) => self.assign(assignee.span, &mut state),
+                }
+                state
+            }
+            Statement::Condition(condition, consequence, alternative) => {
+                self.expression(condition, &state);
+                let (then, otherwise) = match constant(condition) {
+                    Some(true) => (state, None),
+                    Some(false) => (None, state),
+                    None => (state.clone(), state),
+                };
+                let then = self.block(consequence, then);
+                let otherwise = match alternative {
+                    Some(alternative) => self.block(alternative, otherwise),
+                    None => otherwise,
+                };
+                join(then, otherwise)
+            }
+            Statement::While(condition, body) => self.analyse_loop(condition, None, body, state),
+            Statement::For(init, condition, step, body) => {
+                let state = match init {
+                    Some(init) => self.statement(init, state),
+                    None => state,
+                };
+                self.analyse_loop(condition, step.as_deref(), body, state)
+            }
+            Statement::Switch(scrutinee, cases, default) => {
+                self.expression(scrutinee, &state);
+                let mut after = None;
+                for case in cases {
+                    self.expression(&case.value.value, &state);
+                    after = join(after, self.block(&case.value.body, state.clone()));
+                }
+                match default {
+                    Some(default) => join(after, self.block(default, state)),
+                    None if is_exhaustive(cases, default) => after,
+                    None => join(after, state),
+                }
+            }
+            Statement::Break | Statement::Continue => {
+                // outside of a loop, they leave the function
+                if let Some(labels) = self.loops.last_mut() {
+                    let target = match statement.value {
+                        Statement::Break => &mut labels.breaks,
+                        _ => &mut labels.continues,
+                    };
+                    *target = join(target.take(), state);
+                }
+                None
+            }
+            Statement::FunctionCall(_, arguments) => {
+                for argument in arguments {
+                    self.expression(argument, &state);
+                }
+                state
+            }
+            Statement::FunctionDefinition(function) => {
+                self.function(&function.value.statements);
+                state
+            }
+            Statement::Return(expression) => {
+                self.expression(expression, &state);
+                None
+            }
+            Statement::TypeDefinition(..) | Statement::Error => state,
+        }
+    }
+
+    /// Analyses a function on its own, it cannot see the variables around it
+    fn function(&mut self, statements: &[StatementNode]) {
+        let loops = std::mem::take(&mut self.loops);
+        self.statements(statements, Some(HashSet::new()));
+        self.loops = loops;
+    }
+
+    /// Assignments only add variables to the state, so the state at the start of each iteration
+    /// is the one before the loop, and one pass over the body is enough
+    fn analyse_loop(
+        &mut self,
+        condition: &ExpressionNode,
+        step: Option<&StatementNode>,
+        body: &BlockStatementNode,
+        state: State,
+    ) -> State {
+        self.expression(condition, &state);
+        // the loop is left when the condition is false, or by a `break`
+        let exit = match constant(condition) {
+            Some(true) => None,
+            _ => state.clone(),
+        };
+        let entry = match constant(condition) {
+            Some(false) => None,
+            _ => state,
+        };
+
+        self.loops.push(Loop {
+            breaks: None,
+            continues: None,
+        });
+        let end = self.block(body, entry);
+        let labels = self.loops.pop().expect("pushed before the body");
+        if let Some(step) = step {
+            self.statement(step, join(end, labels.continues));
+        }
+        join(exit, labels.breaks)
+    }
+
+    fn expression(&mut self, expression: &ExpressionNode, state: &State) {
+        if let Some(state) = state {
+            self.reads(expression, state);
+        }
+    }
+
+    /// Reports the variables an expression reads which are not in `state`
+    fn reads(&mut self, expression: &ExpressionNode, state: &HashSet<SymbolId>) {
+        match &expression.value {
+            Expression::Identifier(id) => {
+                let symbol = match self.table.symbol_id(expression.span) {
+                    Some(symbol) => symbol,
+                    None => return,
+                };
+                if let Some(declaration) = self.declarations.get(&symbol) {
+                    if !state.contains(&symbol) {
+                        self.errors.push(InitializationError::new(
+                            id.clone(),
+                            expression.start,
+                            expression.span,
+                            *declaration,
+                        ));
+                    }
+                }
+            }
+            Expression::Literal(_) => {}
+            Expression::Add(lhs, rhs)
+            | Expression::Sub(lhs, rhs)
+            | Expression::Mul(lhs, rhs)
+            | Expression::Div(lhs, rhs)
+            | Expression::Pow(lhs, rhs)
+            | Expression::Mod(lhs, rhs)
+            | Expression::Eq(lhs, rhs)
+            | Expression::Neq(lhs, rhs)
+            | Expression::Lt(lhs, rhs)
+            | Expression::Le(lhs, rhs)
+            | Expression::Ge(lhs, rhs)
+            | Expression::Gt(lhs, rhs)
+            | Expression::And(lhs, rhs)
+            | Expression::Or(lhs, rhs)
+            | Expression::BitAnd(lhs, rhs)
+            | Expression::BitOr(lhs, rhs)
+            | Expression::BitXor(lhs, rhs)
+            | Expression::Shl(lhs, rhs)
+            | Expression::Shr(lhs, rhs) => {
+                self.reads(lhs, state);
+                self.reads(rhs, state);
+            }
+            Expression::Not(operand) | Expression::Neg(operand) => self.reads(operand, state),
+            Expression::Ternary(condition, consequence, alternative) => {
+                self.reads(condition, state);
+                self.reads(consequence, state);
+                self.reads(alternative, state);
+            }
+            Expression::FunctionCall(_, arguments) => {
+                for argument in arguments {
+                    self.reads(argument, state);
+                }
+            }
+            // analysed on its own, with the definition binding it
+            Expression::FunctionDef(_) => {}
+        }
+    }
+}
+
+/// The value of a condition folded to a literal
+fn constant(condition: &ExpressionNode) -> Option<bool> {
+    match condition.value {
+        Expression::Literal(Literal::Boolean(value)) => Some(value),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Renderer, SourceMap};
+
+    fn errors(code: &str) -> Vec<String> {
+        match crate::check(code) {
+            Ok(_) => vec![],
+            Err(error) => error.to_string().lines().map(String::from).collect(),
+        }
+    }
+
+    #[test]
+    fn assigned_on_every_path() {
+        for code in [
+            "let x: i32; x = 1; return x;",
+            "func f(c: bool): i32 { let x: i32; if c { x = 1; } else { x = 2; } return x; }",
+            "func f(c: bool): i32 { let x: i32; if c { return 0; } else { x = 1; } return x; }",
+            "let x: i32; while true { x = 1; break; } return x;",
+            "let x: i32; if 1 < 2 { x = 1; } return x;",
+            "let x: i32; switch 1 { case 1 { x = 1; } default { x = 2; } } return x;",
+            "func f(b: bool): i32 { let x: i32; switch b { case true { x = 1; } case false { x = 0; } } return x; }",
+            "auto f = func(): i32 { let x: i32; x = 2; return x; }; return f();",
+            "return f(); func f(): i32 { let x: i32; x = 2; return x; }",
+        ] {
+            assert_eq!(errors(code), Vec::<String>::new(), "{}", code);
+        }
+    }
+
+    #[test]
+    fn possibly_uninitialized() {
+        let error = |position: &str| {
+            vec![format!(
+                "Use of possibly uninitialized variable `x` at {}",
+                position
+            )]
+        };
+        assert_eq!(errors("let x: i32; return x;"), error("1:20"));
+        assert_eq!(errors("let x: i32; x = x + 1;"), error("1:17"));
+        assert_eq!(
+            errors("func f(c: bool): i32 { let x: i32; if c { x = 1; } return x; }"),
+            error("1:59")
+        );
+        assert_eq!(
+            errors("let x: i32; auto i = 0; while i < 1 { x = 1; i++; } return x;"),
+            error("1:60")
+        );
+        // declared again by each iteration
+        assert_eq!(
+            errors("for let i: i32 = 0; i < 2; i++ { let x: i32; if i == 1 { return x; } x = i; }"),
+            error("1:65")
+        );
+        assert_eq!(
+            errors("let x: i32; for let i: i32 = 0; i < 2; i++ { if i == 0 { continue; } x = i; } return x;"),
+            error("1:86")
+        );
+        assert_eq!(
+            errors("let x: i32; switch 2 { case 1 { x = 1; } } return x;"),
+            error("1:51")
+        );
+        assert_eq!(
+            errors("return f(); func f(): i32 { let x: i32; return x; }"),
+            error("1:48")
+        );
+    }
+
+    #[test]
+    fn note_on_the_declaration() {
+        let code = "let x: i32;\nif false { x = 1; }\nreturn x;";
+        let mut source_map = SourceMap::new();
+        source_map.add_file(String::from("main.out"), String::from(code));
+        let error = crate::check(code).unwrap_err();
+        assert_eq!(
+            Renderer::new(&source_map).render(&error.diagnostics()[0]),
+            r#"error: Use of possibly uninitialized variable `x`
+ --> main.out:3:8
+  |
+1 | let x: i32;
+  |     ------ declared here without a value
+...
+3 | return x;
+  |        ^
+  = note: a variable has to be assigned on every path before it is read
+"#
+        );
+    }
+}
@@ -0,0 +1,1020 @@
+use crate::ast::node::Node;
+use crate::ast::types::{Type, TypeError};
+use crate::ast::{
+    Assignee, BlockStatementNode, DefinitionType, Expression, ExpressionNode, FunctionNode,
+    FunctionSignature, Identifier, Literal, Program, Statement, StatementNode, SwitchCaseNode,
+};
+use crate::diagnostics::Diagnostic;
+use crate::optimizer;
+use crate::position::{Position, Span};
+use core::fmt;
+use std::collections::HashMap;
+
+pub mod initialization;
+
+#[derive(Debug, Clone)]
+pub struct CheckError {
+    error: TypeError,
+    position: Position,
+    span: Span,
+}
+
+impl CheckError {
+    pub fn new(error: TypeError, position: Position, span: Span) -> Self {
+        Self {
+            error,
+            position,
+            span,
+        }
+    }
+    pub fn error(&self) -> &TypeError {
+        &self.error
+    }
+    pub fn position(&self) -> Position {
+        self.position
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.error, self.position)
+    }
+}
+
+impl std::error::Error for CheckError {}
+
+impl From<&CheckError> for Diagnostic {
+    fn from(error: &CheckError) -> Self {
+        let label = match &error.error {
+            TypeError::Mismatch { expected, found } => {
+                format!("expected `{}`, found `{}`", expected, found)
+            }
+            _ => String::new(),
+        };
+        Diagnostic::error(error.error.to_string()).with_label(error.span, label)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    /// `None` for variables whose type could not be determined, uses of those are not reported
+    variables: HashMap<Identifier, Option<Type>>,
+    functions: HashMap<Identifier, FunctionSignature>,
+    /// function bodies cannot see the variables of enclosing scopes
+    is_function: bool,
+}
+
+/// The function whose body is being checked
+#[derive(Debug, Clone)]
+struct FunctionContext {
+    id: Identifier,
+    return_type: Option<Type>,
+}
+
+/// Checks that every expression and statement of a program is well typed
+#[derive(Debug, Clone)]
+pub struct Checker {
+    scopes: Vec<Scope>,
+    function: Option<FunctionContext>,
+    errors: Vec<CheckError>,
+}
+
+impl Default for Checker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Checker {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![Scope::default()],
+            function: None,
+            errors: vec![],
+        }
+    }
+
+    /// Checks the program and fills in the types the source leaves implicit:
+    /// `auto` variables get the type of their initializer and every integer literal
+    /// gets the variant of the type it has in its context.
+    pub fn check_program(mut self, program: &mut Program) -> Result<(), Vec<CheckError>> {
+        self.check_more(program)
+    }
+
+    /// Checks top level statements following the ones checked before, which they can refer to
+    pub fn check_more(&mut self, program: &mut Program) -> Result<(), Vec<CheckError>> {
+        self.check_statements(&mut program.0);
+        self.take_errors()
+    }
+
+    /// Type of an expression evaluated after the statements checked so far
+    pub fn type_of(
+        &mut self,
+        expression: &mut ExpressionNode,
+    ) -> Result<Option<Type>, Vec<CheckError>> {
+        let ty = self.check_expression(expression, None);
+        self.take_errors().map(|_| ty)
+    }
+
+    fn take_errors(&mut self) -> Result<(), Vec<CheckError>> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    fn report<T>(&mut self, error: TypeError, node: &Node<T>) {
+        self.report_at(error, node.start, node.span);
+    }
+
+    fn report_at(&mut self, error: TypeError, position: Position, span: Span) {
+        self.errors.push(CheckError::new(error, position, span));
+    }
+
+    fn push_scope(&mut self, is_function: bool) {
+        self.scopes.push(Scope {
+            is_function,
+            ..Scope::default()
+        });
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare_variable(&mut self, id: &str, ty: Option<Type>) {
+        let scope = self.scopes.last_mut().unwrap();
+        scope.variables.insert(id.to_string(), ty);
+    }
+
+    fn declare_function(&mut self, id: &str, signature: FunctionSignature) {
+        let scope = self.scopes.last_mut().unwrap();
+        scope.functions.insert(id.to_string(), signature);
+    }
+
+    /// `None` if the variable is not visible, `Some(None)` if its type is unknown
+    fn lookup_variable(&self, id: &str) -> Option<Option<Type>> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(ty) = scope.variables.get(id) {
+                return Some(ty.clone());
+            }
+            if scope.is_function {
+                break;
+            }
+        }
+        None
+    }
+
+    fn lookup_function(&self, id: &str) -> Option<FunctionSignature> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.functions.get(id))
+            .cloned()
+    }
+
+    fn check_statements(&mut self, statements: &mut [StatementNode]) {
+        // functions are visible in their whole block, so they can call each other
+        for statement in statements.iter() {
+            if let Statement::FunctionDefinition(function) = &statement.value {
+                let function = &function.value;
+                self.declare_function(&function.id, function.signature.clone());
+            }
+        }
+        for statement in statements {
+            self.check_statement(statement);
+        }
+    }
+
+    fn check_block(&mut self, block: &mut BlockStatementNode) {
+        self.push_scope(false);
+        self.check_statements(&mut block.value.0);
+        self.pop_scope();
+    }
+
+    fn check_statement(&mut self, statement: &mut StatementNode) {
+        let (start, span) = (statement.start, statement.span);
+        match &mut statement.value {
+            Statement::Declaration(variable) => {
+                let variable = &variable.value;
+                self.declare_variable(&variable.id, variable.ty.clone());
+            }
+            Statement::Definition(DefinitionType::Auto, variable, expression) => {
+                if let Expression::FunctionDef(function) = &mut expression.value {
+                    // `auto f = func(...) {...};` binds a function named `f`
+                    let signature = function.value.signature.clone();
+                    self.declare_function(&variable.value.id, signature);
+                    self.check_function(&variable.value.id, function);
+                } else {
+                    let ty = self.infer_definition(&variable.value.id, expression);
+                    variable.value.ty = ty.clone();
+                    self.declare_variable(&variable.value.id, ty);
+                }
+            }
+            Statement::Definition(DefinitionType::Let, variable, expression) => {
+                let ty = variable.value.ty.clone();
+                if let Some(ty) = &ty {
+                    self.check_expected(expression, ty);
+                }
+                self.declare_variable(&variable.value.id, ty);
+            }
+            Statement::TypeDefinition(..) => {}
+            Statement::Assignment(assignee, expression) => match &assignee.value {
+                Assignee::Identifier(id) => match self.lookup_variable(id) {
+                    Some(Some(ty)) => self.check_expected(expression, &ty),
+                    Some(None) => {
+                        self.check_expression(expression, None);
+                    }
+                    None => {
+                        self.report(TypeError::UndefinedVariable(id.clone()), assignee);
+                        self.check_expression(expression, None);
+                    }
+                },
+            },
+            Statement::Condition(condition, consequence, alternative) => {
+                self.check_expected(condition, &Type::Boolean);
+                self.check_block(consequence);
+                if let Some(alternative) = alternative {
+                    self.check_block(alternative);
+                }
+            }
+            Statement::While(condition, body) => {
+                self.check_expected(condition, &Type::Boolean);
+                self.check_block(body);
+            }
+            Statement::For(init, condition, step, body) => {
+                self.push_scope(false);
+                if let Some(init) = init {
+                    self.check_statement(init);
+                }
+                self.check_expected(condition, &Type::Boolean);
+                if let Some(step) = step {
+                    self.check_statement(step);
+                }
+                self.check_block(body);
+                self.pop_scope();
+            }
+            Statement::Switch(scrutinee, cases, default) => {
+                let ty = self.check_expression(scrutinee, None);
+                // the cases whose value is ill-typed or always fails, which are reported on
+                // their own
+                let mut invalid = vec![];
+                for case in cases.iter_mut() {
+                    let errors = self.errors.len();
+                    match &ty {
+                        Some(ty) => self.check_expected(&mut case.value.value, ty),
+                        None => {
+                            self.check_expression(&mut case.value.value, None);
+                        }
+                    }
+                    // folded now so that the values can be compared, and so that a function
+                    // ending with `case !false` returns on every path
+                    invalid.push(
+                        self.errors.len() != errors
+                            || optimizer::fold_expression(&mut case.value.value).is_err(),
+                    );
+                    self.check_block(&mut case.value.body);
+                }
+                if let Some(default) = default {
+                    self.check_block(default);
+                }
+                self.check_cases(ty, cases, &invalid, default, start, span);
+            }
+            Statement::Break | Statement::Continue | Statement::Error => {}
+            Statement::FunctionCall(id, arguments) => {
+                self.check_call(id, arguments, start, span);
+            }
+            Statement::FunctionDefinition(function) => {
+                let id = function.value.id.clone();
+                self.check_function(&id, function);
+            }
+            Statement::Return(expression) => match &self.function {
+                Some(FunctionContext {
+                    return_type: Some(ty),
+                    ..
+                }) => {
+                    let ty = ty.clone();
+                    self.check_expected(expression, &ty);
+                }
+                Some(FunctionContext { id, .. }) => {
+                    let id = id.clone();
+                    self.report(TypeError::UnexpectedReturn(id), expression);
+                }
+                // a top level `return` ends the program with a value of any type
+                None => {
+                    self.check_expression(expression, None);
+                }
+            },
+        }
+    }
+
+    /// Infers the type of an `auto` variable from its initializer
+    fn infer_definition(&mut self, id: &str, expression: &mut ExpressionNode) -> Option<Type> {
+        if let Expression::Literal(Literal::Null) = expression.value {
+            self.report(TypeError::CannotInfer(id.to_string()), expression);
+            return None;
+        }
+        self.check_expression(expression, None)
+    }
+
+    fn check_function(&mut self, id: &str, function: &mut FunctionNode) {
+        let context = FunctionContext {
+            id: id.to_string(),
+            return_type: function.value.return_type.clone(),
+        };
+        let outer = self.function.replace(context);
+
+        self.push_scope(true);
+        for parameter in &function.value.parameters {
+            self.declare_variable(&parameter.value.id, parameter.value.ty.clone());
+        }
+        self.check_statements(&mut function.value.statements);
+        self.pop_scope();
+
+        self.function = outer;
+
+        if function.value.return_type.is_some() && !always_returns(&function.value.statements) {
+            self.report(TypeError::MissingReturn(id.to_string()), function);
+        }
+    }
+
+    /// Case values must be distinct constants, and a switch over `bool` must not have a
+    /// `default` arm that can never run or be missing both `true` and `false` arms
+    fn check_cases(
+        &mut self,
+        ty: Option<Type>,
+        cases: &[SwitchCaseNode],
+        invalid: &[bool],
+        default: &Option<BlockStatementNode>,
+        start: Position,
+        span: Span,
+    ) {
+        let mut constants: Vec<&Literal> = vec![];
+        for (case, invalid) in cases.iter().zip(invalid) {
+            let value = &case.value.value;
+            match &value.value {
+                Expression::Literal(literal) if constants.contains(&literal) => {
+                    self.report(TypeError::DuplicateCase, value)
+                }
+                Expression::Literal(literal) => constants.push(literal),
+                _ if *invalid => {}
+                _ => self.report(TypeError::NonConstantCase, value),
+            }
+        }
+
+        if ty != Some(Type::Boolean) {
+            return;
+        }
+        let booleans = constants
+            .iter()
+            .filter(|l| matches!(l, Literal::Boolean(_)))
+            .count();
+        match (booleans, default) {
+            (2, Some(default)) => self.report(TypeError::UnreachableDefault, default),
+            (0, _) => self.report_at(TypeError::UncoveredBoolean, start, span),
+            _ => {}
+        }
+    }
+
+    /// Checks an expression whose type must be `expected`
+    fn check_expected(&mut self, expression: &mut ExpressionNode, expected: &Type) {
+        if let Some(found) = self.check_expression(expression, Some(expected)) {
+            if &found != expected {
+                self.report(
+                    TypeError::Mismatch {
+                        expected: expected.clone(),
+                        found,
+                    },
+                    expression,
+                );
+            }
+        }
+    }
+
+    /// Returns the type of an expression, or `None` if it is ill-typed (the error is reported).
+    /// Integer literals take the `hint` type when it is an integer type holding their value,
+    /// and are rewritten to the matching `Literal` variant.
+    fn check_expression(
+        &mut self,
+        expression: &mut ExpressionNode,
+        hint: Option<&Type>,
+    ) -> Option<Type> {
+        let (start, span) = (expression.start, expression.span);
+        let operator = operator_symbol(&expression.value);
+        // a negated literal is folded into a negative literal once its type is known
+        let mut folded = None;
+
+        let ty = match &mut expression.value {
+            Expression::Identifier(id) => match self.lookup_variable(id) {
+                Some(ty) => ty,
+                None => {
+                    self.report_at(TypeError::UndefinedVariable(id.clone()), start, span);
+                    None
+                }
+            },
+            Expression::Literal(literal) => self.check_literal(literal, start, span, hint),
+            Expression::Add(lhs, rhs) => {
+                let (lhs, rhs) = self.check_operands(lhs, rhs, hint)?;
+                if lhs.is_integer() || lhs == Type::String {
+                    Some(lhs)
+                } else {
+                    self.report_at(
+                        TypeError::InvalidOperands(String::from(operator), lhs, rhs),
+                        start,
+                        span,
+                    );
+                    None
+                }
+            }
+            Expression::Sub(lhs, rhs)
+            | Expression::Mul(lhs, rhs)
+            | Expression::Div(lhs, rhs)
+            | Expression::Pow(lhs, rhs)
+            | Expression::Mod(lhs, rhs)
+            | Expression::BitAnd(lhs, rhs)
+            | Expression::BitOr(lhs, rhs)
+            | Expression::BitXor(lhs, rhs)
+            | Expression::Shl(lhs, rhs)
+            | Expression::Shr(lhs, rhs) => {
+                let (lhs, rhs) = self.check_operands(lhs, rhs, hint)?;
+                self.expect_integers(operator, lhs, rhs, start, span)
+            }
+            Expression::Lt(lhs, rhs)
+            | Expression::Le(lhs, rhs)
+            | Expression::Gt(lhs, rhs)
+            | Expression::Ge(lhs, rhs) => {
+                let (lhs, rhs) = self.check_operands(lhs, rhs, None)?;
+                self.expect_integers(operator, lhs, rhs, start, span)?;
+                Some(Type::Boolean)
+            }
+            Expression::Eq(lhs, rhs) | Expression::Neq(lhs, rhs) => {
+                self.check_operands(lhs, rhs, None)?;
+                Some(Type::Boolean)
+            }
+            Expression::And(lhs, rhs) | Expression::Or(lhs, rhs) => {
+                self.check_expected(lhs, &Type::Boolean);
+                self.check_expected(rhs, &Type::Boolean);
+                Some(Type::Boolean)
+            }
+            Expression::Not(operand) => {
+                self.check_expected(operand, &Type::Boolean);
+                Some(Type::Boolean)
+            }
+            Expression::Neg(operand) => {
+                let ty = match &operand.value {
+                    // the negated value has to fit, not the literal itself: `-2147483648`
+                    Expression::Literal(literal) if literal.as_integer().is_some() => {
+                        let value = -literal.as_integer().unwrap();
+                        let ty = self.integer_literal_type(value, start, span, hint)?;
+                        folded = Literal::from_integer(value, &ty);
+                        ty
+                    }
+                    _ => self.check_expression(operand, hint)?,
+                };
+                if ty.is_signed() {
+                    Some(ty)
+                } else {
+                    self.report_at(
+                        TypeError::InvalidOperand(String::from(operator), ty),
+                        start,
+                        span,
+                    );
+                    None
+                }
+            }
+            Expression::Ternary(condition, consequence, alternative) => {
+                self.check_expected(condition, &Type::Boolean);
+                let (ty, _) = self.check_operands(consequence, alternative, hint)?;
+                Some(ty)
+            }
+            Expression::FunctionCall(id, arguments) => {
+                match self.check_call(id, arguments, start, span)? {
+                    Some(ty) => Some(ty),
+                    None => {
+                        self.report_at(TypeError::NoReturnValue(id.clone()), start, span);
+                        None
+                    }
+                }
+            }
+            Expression::FunctionDef(_) => {
+                self.report_at(
+                    TypeError::Unsupported(String::from(
+                        "Anonymous functions not bound with `auto`",
+                    )),
+                    start,
+                    span,
+                );
+                None
+            }
+        };
+
+        if let Some(literal) = folded {
+            expression.value = Expression::Literal(literal);
+        }
+        ty
+    }
+
+    /// Checks the operands of a binary operator, which must have the same type.
+    /// An operand made of literals only is typed after the other one, so `1 + x` works for any `x`.
+    fn check_operands(
+        &mut self,
+        lhs: &mut ExpressionNode,
+        rhs: &mut ExpressionNode,
+        hint: Option<&Type>,
+    ) -> Option<(Type, Type)> {
+        let swapped = is_constant(&lhs.value) && !is_constant(&rhs.value);
+        let (first, second) = if swapped { (rhs, lhs) } else { (lhs, rhs) };
+
+        let first_ty = self.check_expression(first, hint);
+        let second_ty = match &first_ty {
+            Some(ty) => self.check_expression(second, Some(ty)),
+            None => self.check_expression(second, hint),
+        };
+        let (first_ty, second_ty) = (first_ty?, second_ty?);
+
+        if first_ty != second_ty {
+            self.report(
+                TypeError::Mismatch {
+                    expected: first_ty,
+                    found: second_ty,
+                },
+                second,
+            );
+            return None;
+        }
+        if swapped {
+            Some((second_ty, first_ty))
+        } else {
+            Some((first_ty, second_ty))
+        }
+    }
+
+    fn expect_integers(
+        &mut self,
+        operator: &str,
+        lhs: Type,
+        rhs: Type,
+        position: Position,
+        span: Span,
+    ) -> Option<Type> {
+        if lhs.is_integer() {
+            Some(lhs)
+        } else {
+            self.report_at(
+                TypeError::InvalidOperands(String::from(operator), lhs, rhs),
+                position,
+                span,
+            );
+            None
+        }
+    }
+
+    fn check_literal(
+        &mut self,
+        literal: &mut Literal,
+        position: Position,
+        span: Span,
+        hint: Option<&Type>,
+    ) -> Option<Type> {
+        match literal {
+            Literal::Array(_) => {
+                self.report_at(
+                    TypeError::Unsupported(String::from("Arrays")),
+                    position,
+                    span,
+                );
+                None
+            }
+            Literal::Null => {
+                self.report_at(
+                    TypeError::Unsupported(String::from("Null values")),
+                    position,
+                    span,
+                );
+                None
+            }
+            _ => match literal.as_integer() {
+                Some(value) => {
+                    let ty = self.integer_literal_type(value, position, span, hint)?;
+                    *literal = Literal::from_integer(value, &ty)?;
+                    Some(ty)
+                }
+                None => literal.default_type(),
+            },
+        }
+    }
+
+    /// An integer literal has the hinted integer type, or the smallest of `i32`, `i64` and `u64`
+    /// holding its value
+    fn integer_literal_type(
+        &mut self,
+        value: i128,
+        position: Position,
+        span: Span,
+        hint: Option<&Type>,
+    ) -> Option<Type> {
+        match hint {
+            Some(ty) if ty.is_integer() => {
+                if ty.fits(value) {
+                    Some(ty.clone())
+                } else {
+                    self.report_at(
+                        TypeError::LiteralOutOfRange(value.to_string(), ty.clone()),
+                        position,
+                        span,
+                    );
+                    None
+                }
+            }
+            _ => [Type::Int(32), Type::Int(64), Type::UInt(64)]
+                .iter()
+                .find(|ty| ty.fits(value))
+                .cloned(),
+        }
+    }
+
+    /// Checks a call against the signature of the function, returns its output type
+    fn check_call(
+        &mut self,
+        id: &str,
+        arguments: &mut [ExpressionNode],
+        position: Position,
+        span: Span,
+    ) -> Option<Option<Type>> {
+        let signature = match self.lookup_function(id) {
+            Some(signature) => signature,
+            None => {
+                self.report_at(TypeError::UndefinedFunction(id.to_string()), position, span);
+                for argument in arguments {
+                    self.check_expression(argument, None);
+                }
+                return None;
+            }
+        };
+
+        if signature.inputs.len() != arguments.len() {
+            self.report_at(
+                TypeError::ArgumentCount {
+                    function: id.to_string(),
+                    expected: signature.inputs.len(),
+                    found: arguments.len(),
+                },
+                position,
+                span,
+            );
+        }
+        for (i, argument) in arguments.iter_mut().enumerate() {
+            match signature.inputs.get(i) {
+                Some(ty) => self.check_expected(argument, ty),
+                None => {
+                    self.check_expression(argument, None);
+                }
+            }
+        }
+        Some(signature.output)
+    }
+}
+
+/// Whether an expression is made of integer literals only, its type then depends on the context
+fn is_constant(expression: &Expression) -> bool {
+    match expression {
+        Expression::Literal(literal) => literal.as_integer().is_some(),
+        Expression::Neg(operand) => is_constant(&operand.value),
+        Expression::Add(lhs, rhs)
+        | Expression::Sub(lhs, rhs)
+        | Expression::Mul(lhs, rhs)
+        | Expression::Div(lhs, rhs)
+        | Expression::Pow(lhs, rhs)
+        | Expression::Mod(lhs, rhs)
+        | Expression::BitAnd(lhs, rhs)
+        | Expression::BitOr(lhs, rhs)
+        | Expression::BitXor(lhs, rhs)
+        | Expression::Shl(lhs, rhs)
+        | Expression::Shr(lhs, rhs) => is_constant(&lhs.value) && is_constant(&rhs.value),
+        _ => false,
+    }
+}
+
+fn operator_symbol(expression: &Expression) -> &'static str {
+    match expression {
+        Expression::Add(..) => "+",
+        Expression::Sub(..) => "-",
+        Expression::Mul(..) => "*",
+        Expression::Div(..) => "/",
+        Expression::Pow(..) => "**",
+        Expression::Mod(..) => "%",
+        Expression::BitAnd(..) => "&",
+        Expression::BitOr(..) => "|",
+        Expression::BitXor(..) => "^",
+        Expression::Shl(..) => "<<",
+        Expression::Shr(..) => ">>",
+        Expression::Lt(..) => "<",
+        Expression::Le(..) => "<=",
+        Expression::Gt(..) => ">",
+        Expression::Ge(..) => ">=",
+        Expression::Neg(..) => "-",
+        _ => "?",
+    }
+}
+
+/// Whether every path through the statements ends with a `return`
+pub fn always_returns(statements: &[StatementNode]) -> bool {
+    statements.iter().any(|statement| match &statement.value {
+        Statement::Return(_) => true,
+        Statement::Condition(_, consequence, Some(alternative)) => {
+            always_returns(&consequence.value.0) && always_returns(&alternative.value.0)
+        }
+        Statement::Switch(_, cases, default) if is_exhaustive(cases, default) => {
+            default
+                .iter()
+                .all(|default| always_returns(&default.value.0))
+                && cases
+                    .iter()
+                    .all(|case| always_returns(&case.value.body.value.0))
+        }
+        statement => loops_forever(statement),
+    })
+}
+
+/// Whether a statement is a loop on the literal `true` which no `break` leaves
+pub fn loops_forever(statement: &Statement) -> bool {
+    match statement {
+        Statement::While(condition, body) | Statement::For(_, condition, _, body) => {
+            matches!(condition.value, Expression::Literal(Literal::Boolean(true)))
+                && !breaks(&body.value.0)
+        }
+        _ => false,
+    }
+}
+
+/// Whether a `break` in the statements leaves the loop around them, the ones in nested loops
+/// leave those instead
+fn breaks(statements: &[StatementNode]) -> bool {
+    statements.iter().any(|statement| match &statement.value {
+        Statement::Break => true,
+        Statement::Condition(_, consequence, alternative) => {
+            breaks(&consequence.value.0)
+                || alternative
+                    .iter()
+                    .any(|alternative| breaks(&alternative.value.0))
+        }
+        Statement::Switch(_, cases, default) => {
+            cases.iter().any(|case| breaks(&case.value.body.value.0))
+                || default.iter().any(|default| breaks(&default.value.0))
+        }
+        _ => false,
+    })
+}
+
+/// Whether a switch always runs one of its arms, with a `default` arm or with cases for both
+/// `true` and `false`
+pub fn is_exhaustive(cases: &[SwitchCaseNode], default: &Option<BlockStatementNode>) -> bool {
+    let covers = |value: bool| {
+        cases.iter().any(|case| {
+            matches!(case.value.value.value, Expression::Literal(Literal::Boolean(b)) if b == value)
+        })
+    };
+    default.is_some() || (covers(true) && covers(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn check(code: &str) -> Vec<String> {
+        let mut program = Parser::new(Lexer::new(code)).parse_program().unwrap();
+        match Checker::new().check_program(&mut program) {
+            Ok(()) => vec![],
+            Err(errors) => errors.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn well_typed_program() {
+        let errors = check(
+            r#"
+            typedef Id = u64;
+            func fib(n: u32): u32 {
+                if n < 2 {
+                    return n;
+                }
+                return fib(n - 1) + fib(n - 2);
+            }
+            func greet(name: string, id: Id): string {
+                auto greeting = "hello " + name;
+                return id == 0 ? greeting : "bye";
+            }
+            let mask: u64 = 1 << 32 - 1;
+            let small: i64 = -9223372036854775808;
+            auto total = fib(10) * 2;
+            auto square = func(x: i32): i32 { return x ** 2; };
+            for let i: i32 = 0; i < 10; i++ {
+                switch square(i) {
+                    case 4 { break; }
+                    default { continue; }
+                }
+            }
+            return greet("outer", mask) == "bye" && !(total > 100);
+        "#,
+        );
+        assert_eq!(errors, Vec::<String>::new());
+    }
+
+    #[test]
+    fn switch_cases() {
+        assert_eq!(
+            check("auto x = 2; switch x { case 1 + 1 { } case 2 { } case !true ? 3 : 4 { } }"),
+            vec!["Duplicate case value in switch at 1:44"]
+        );
+        let errors = [
+            (
+                "auto x = 1; switch x { case 1 {} case 1 {} }",
+                "Duplicate case value in switch at 1:39",
+            ),
+            (
+                "auto x = 1; switch x { case x {} }",
+                "Case value must be a constant at 1:29",
+            ),
+            (
+                "auto x = true; switch x { case true {} case false {} default {} }",
+                "Unreachable `default` arm, switch over bool covers `true` and `false` at 1:62",
+            ),
+            (
+                "auto x = 1; switch x < 2 { default {} }",
+                "Switch over bool covers neither `true` nor `false` at 1:13",
+            ),
+        ];
+        for (code, error) in errors {
+            assert_eq!(check(code), vec![error], "{}", code);
+        }
+        // reported by constant folding alone
+        assert_eq!(
+            crate::check("switch 1 { case 1 / 0 { } }")
+                .unwrap_err()
+                .to_string(),
+            "Attempt to divide by zero at 1:17"
+        );
+    }
+
+    #[test]
+    fn exhaustive_bool_switch() {
+        assert_eq!(
+            check(
+                "func f(b: bool): i32 { switch b { case true { return 1; } case false { return 0; } } }"
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            check("func f(b: bool): i32 { switch b { case !false { return 1; } case false { return 0; } } }"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            check("func f(b: bool): i32 { switch b { case true { return 1; } } }"),
+            vec!["Function `f` does not return a value on every path at 1:1"]
+        );
+    }
+
+    #[test]
+    fn infinite_loop_returns() {
+        assert_eq!(
+            check("func f(): i32 { while true { return 1; } } return f();"),
+            Vec::<String>::new()
+        );
+        // a `break` of a nested loop does not leave the outer one
+        assert_eq!(
+            check("func f(): i32 { while true { while true { break; } } }"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            check("func f(b: bool): i32 { while true { switch b { case true { break; } } } }"),
+            vec!["Function `f` does not return a value on every path at 1:1"]
+        );
+        assert_eq!(
+            check("func f(b: bool): i32 { while b { return 1; } }"),
+            vec!["Function `f` does not return a value on every path at 1:1"]
+        );
+    }
+
+    #[test]
+    fn type_errors() {
+        let errors = check(
+            r#"
+            let a: i32 = "x";
+            let b: u32 = -1;
+            let c: bool = 1 + true;
+            if a { }
+            a = a > 1 ? a : b;
+            func f(x: i32): bool {
+                if x > 0 {
+                    return x;
+                }
+            }
+            func g(x: u64) { return x; }
+            f(1, 2);
+            auto d = g(1);
+            while undefined < 1 { h(); }
+        "#,
+        );
+        assert_eq!(
+            errors,
+            vec![
+                "Mismatched types, expected `i32`, found `string` at 2:26",
+                "Literal `-1` does not fit in `u32` at 3:26",
+                "Mismatched types, expected `bool`, found `i32` at 4:27",
+                "Mismatched types, expected `bool`, found `i32` at 5:16",
+                "Mismatched types, expected `i32`, found `u32` at 6:29",
+                "Mismatched types, expected `bool`, found `i32` at 9:28",
+                "Function `f` does not return a value on every path at 7:13",
+                "Function `g` has no return type but returns a value at 12:37",
+                "Function `f` takes 1 argument(s) but 2 were supplied at 13:13",
+                "Function `g` does not return a value at 14:22",
+                "Undefined variable `undefined` at 15:19",
+                "Undefined function `h` at 15:35",
+            ]
+        );
+    }
+
+    #[test]
+    fn infer_auto_types() {
+        let mut program = Parser::new(Lexer::new(
+            r#"
+            func half(n: u64): u64 { return n / 2; }
+            auto a = 1;
+            auto b = 10000000000000000000;
+            auto c = half(b + 1);
+            auto d = -2147483648 < a;
+            auto e = "a" + "b";
+            let f: u32 = 7;
+        "#,
+        ))
+        .parse_program()
+        .unwrap();
+        Checker::new().check_program(&mut program).unwrap();
+
+        let definitions: Vec<_> = program
+            .0
+            .iter()
+            .filter_map(|statement| match &statement.value {
+                Statement::Definition(_, variable, expression) => {
+                    Some((variable.value.ty.clone(), expression.value.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        let types: Vec<_> = definitions.iter().map(|(ty, _)| ty.clone()).collect();
+        assert_eq!(
+            types,
+            vec![
+                Some(Type::Int(32)),
+                Some(Type::UInt(64)),
+                Some(Type::UInt(64)),
+                Some(Type::Boolean),
+                Some(Type::String),
+                Some(Type::UInt(32)),
+            ]
+        );
+
+        // literals take the type of their context
+        match &definitions[2].1 {
+            Expression::FunctionCall(_, arguments) => match &arguments[0].value {
+                Expression::Add(_, one) => {
+                    assert_eq!(one.value, Expression::Literal(Literal::UInt64(1)))
+                }
+                e => panic!("unexpected expression {:?}", e),
+            },
+            e => panic!("unexpected expression {:?}", e),
+        }
+        match &definitions[3].1 {
+            Expression::Lt(min, _) => {
+                assert_eq!(min.value, Expression::Literal(Literal::Int32(i32::MIN)))
+            }
+            e => panic!("unexpected expression {:?}", e),
+        }
+        assert_eq!(definitions[5].1, Expression::Literal(Literal::UInt32(7)));
+    }
+
+    #[test]
+    fn cannot_infer() {
+        let errors = check(
+            r#"
+            auto x = null;
+            auto y = x + 1;
+            auto z = undefined;
+        "#,
+        );
+        assert_eq!(
+            errors,
+            vec![
+                "Cannot infer the type of `x` at 2:22",
+                "Undefined variable `undefined` at 4:22",
+            ]
+        );
+    }
+}
@@ -0,0 +1,289 @@
+use crate::position::Span;
+use crate::source_map::SourceMap;
+use core::fmt;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    fn color(&self) -> &'static str {
+        match self {
+            Severity::Error => "\x1b[1;31m",
+            Severity::Warning => "\x1b[1;33m",
+            Severity::Note => "\x1b[1;32m",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// A source range pointed at by a diagnostic
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Label {
+    pub fn new(span: Span, message: String) -> Self {
+        Self { span, message }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub primary: Option<Label>,
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: String) -> Self {
+        Self {
+            severity,
+            message,
+            primary: None,
+            secondary: vec![],
+            notes: vec![],
+        }
+    }
+    pub fn error(message: String) -> Self {
+        Diagnostic::new(Severity::Error, message)
+    }
+    pub fn warning(message: String) -> Self {
+        Diagnostic::new(Severity::Warning, message)
+    }
+    pub fn with_label(mut self, span: Span, message: String) -> Self {
+        self.primary = Some(Label::new(span, message));
+        self
+    }
+    pub fn with_secondary_label(mut self, span: Span, message: String) -> Self {
+        self.secondary.push(Label::new(span, message));
+        self
+    }
+    pub fn with_note(mut self, note: String) -> Self {
+        self.notes.push(note);
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// Renders diagnostics rustc-style, with the offending source lines underlined
+pub struct Renderer<'a> {
+    source_map: &'a SourceMap,
+    colored: bool,
+}
+
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+impl<'a> Renderer<'a> {
+    pub fn new(source_map: &'a SourceMap) -> Self {
+        Self {
+            source_map,
+            colored: false,
+        }
+    }
+
+    pub fn colored(mut self, colored: bool) -> Self {
+        self.colored = colored;
+        self
+    }
+
+    fn paint(&self, color: &str, text: &str) -> String {
+        if self.colored {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            String::from(text)
+        }
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let mut out = String::new();
+        let severity = diagnostic.severity;
+        let _ = writeln!(
+            out,
+            "{}{}",
+            self.paint(severity.color(), &severity.to_string()),
+            self.paint(BOLD, &format!(": {}", diagnostic.message))
+        );
+
+        let mut labels: Vec<(&Label, bool)> = diagnostic
+            .secondary
+            .iter()
+            .map(|label| (label, false))
+            .chain(diagnostic.primary.iter().map(|label| (label, true)))
+            .collect();
+        // the file of the primary label comes first, so that its header points at the error
+        let primary_file = diagnostic.primary.as_ref().map(|label| label.span.file_id);
+        labels.sort_by_key(|(label, _)| {
+            let file_id = label.span.file_id;
+            (Some(file_id) != primary_file, file_id, label.span.lo)
+        });
+
+        let gutter_width = labels
+            .iter()
+            .map(|(label, _)| {
+                let position = self.source_map.position(label.span.file_id, label.span.lo);
+                (position.line + 1).to_string().len()
+            })
+            .max()
+            .unwrap_or(0);
+        let gutter = " ".repeat(gutter_width);
+        let pipe = self.paint(BLUE, "|");
+
+        let mut previous_file = None;
+        let mut previous_line = None;
+        for (label, is_primary) in labels {
+            let file = self.source_map.file(label.span.file_id);
+            let start = file.position(label.span.lo);
+            let end = file.position(label.span.hi);
+
+            if previous_file != Some(file.id) {
+                let header = match &diagnostic.primary {
+                    Some(primary) if primary.span.file_id == file.id => {
+                        file.position(primary.span.lo)
+                    }
+                    _ => start,
+                };
+                let _ = writeln!(
+                    out,
+                    "{}{} {}:{}",
+                    gutter,
+                    self.paint(BLUE, "-->"),
+                    file.name,
+                    header
+                );
+                let _ = writeln!(out, "{} {}", gutter, pipe);
+                previous_file = Some(file.id);
+                previous_line = None;
+            }
+
+            let text = file.line(start.line).unwrap_or("");
+            if matches!(previous_line, Some(line) if start.line > line + 1) {
+                let _ = writeln!(out, "{}", self.paint(BLUE, "..."));
+            }
+            if previous_line != Some(start.line) {
+                let line_number = format!("{:>width$}", start.line + 1, width = gutter_width);
+                let _ = writeln!(out, "{} {} {}", self.paint(BLUE, &line_number), pipe, text);
+                previous_line = Some(start.line);
+            }
+
+            // spans covering several lines are underlined up to the end of their first line
+            let end_col = if end.line == start.line {
+                end.col
+            } else {
+                text.chars().count()
+            };
+            let width = end_col.saturating_sub(start.col).max(1);
+            let (marker, color) = if is_primary {
+                ("^", severity.color())
+            } else {
+                ("-", BLUE)
+            };
+            let underline = format!("{} {}", marker.repeat(width), label.message);
+            let _ = writeln!(
+                out,
+                "{} {} {}{}",
+                gutter,
+                pipe,
+                " ".repeat(start.col),
+                self.paint(color, underline.trim_end())
+            );
+        }
+
+        for note in &diagnostic.notes {
+            let _ = writeln!(out, "{} {} note: {}", gutter, self.paint(BLUE, "="), note);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_diagnostic() {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file(
+            String::from("main.out"),
+            String::from("let a: i32 = 1;\r\nlet c: i32 = 2;\nlet b: bool = a;\n"),
+        );
+        let diagnostic = Diagnostic::error(String::from("Mismatched types"))
+            .with_label(Span::new(file, 47, 48), String::from("expected `bool`"))
+            .with_secondary_label(Span::new(file, 4, 10), String::from("`a` is defined here"))
+            .with_note(String::from("`i32` cannot be converted to `bool`"));
+
+        let rendered = Renderer::new(&source_map).render(&diagnostic);
+        let expected = r#"error: Mismatched types
+ --> main.out:3:15
+  |
+1 | let a: i32 = 1;
+  |     ------ `a` is defined here
+...
+3 | let b: bool = a;
+  |               ^ expected `bool`
+  = note: `i32` cannot be converted to `bool`
+"#;
+        assert_eq!(rendered, expected);
+    }
+
+    #[test]
+    fn render_diagnostic_across_files() {
+        let mut source_map = SourceMap::new();
+        // the file of the secondary label is added first, the primary one is still shown first
+        let lib = source_map.add_file(String::from("lib.out"), String::from("func f() {}"));
+        let main = source_map.add_file(String::from("main.out"), String::from("f(1);"));
+        let diagnostic = Diagnostic::error(String::from("Wrong number of arguments"))
+            .with_label(Span::new(main, 0, 4), String::new())
+            .with_secondary_label(Span::new(lib, 5, 6), String::from("defined here"));
+
+        let rendered = Renderer::new(&source_map).render(&diagnostic);
+        let expected = r#"error: Wrong number of arguments
+ --> main.out:1:1
+  |
+1 | f(1);
+  | ^^^^
+ --> lib.out:1:6
+  |
+1 | func f() {}
+  |      - defined here
+"#;
+        assert_eq!(rendered, expected);
+    }
+
+    #[test]
+    fn render_colored_diagnostic() {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file(String::from("main.out"), String::from("let a: i32;"));
+        let diagnostic = Diagnostic::warning(String::from("Unused variable"))
+            .with_label(Span::new(file, 4, 5), String::new());
+
+        let rendered = Renderer::new(&source_map).colored(true).render(&diagnostic);
+
+        assert!(rendered.starts_with("\x1b[1;33mwarning\x1b[0m"));
+        assert!(rendered.contains("    \x1b[1;33m^\x1b[0m"));
+    }
+}
@@ -0,0 +1,549 @@
+use crate::ast::node::Node;
+use crate::ast::{
+    Assignee, BlockStatementNode, Expression, ExpressionNode, Function, Program, Statement,
+    StatementNode,
+};
+use crate::diagnostics::Diagnostic;
+use crate::position::{Position, Span};
+use crate::resolver::{SymbolId, SymbolTable};
+use core::fmt;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+use value::{ArithmeticError, BinaryOperator, Value};
+
+pub mod value;
+
+/// Deepest nesting of function calls before a program is stopped
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+/// Stack reserved for each nested call, evaluating one takes several recursive Rust calls
+const STACK_PER_CALL: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeErrorKind {
+    Arithmetic(ArithmeticError),
+    UninitializedVariable(String),
+    NoReturnValue(String),
+    StackOverflow,
+    /// a name without a declaration, which the resolver reports before running
+    Unresolved(String),
+    /// bytecode the VM cannot run, which `bytecode::decode` rejects before running
+    InvalidBytecode(String),
+}
+
+impl fmt::Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeErrorKind::Arithmetic(error) => write!(f, "{}", error),
+            RuntimeErrorKind::UninitializedVariable(id) => {
+                write!(f, "Use of uninitialized variable `{}`", id)
+            }
+            RuntimeErrorKind::NoReturnValue(id) => {
+                write!(f, "Function `{}` did not return a value", id)
+            }
+            RuntimeErrorKind::StackOverflow => write!(
+                f,
+                "Stack overflow, more than {} nested function calls",
+                MAX_CALL_DEPTH
+            ),
+            RuntimeErrorKind::Unresolved(id) => write!(f, "Unresolved name `{}`", id),
+            RuntimeErrorKind::InvalidBytecode(reason) => write!(f, "Invalid bytecode, {}", reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    kind: RuntimeErrorKind,
+    position: Position,
+    span: Span,
+}
+
+impl RuntimeError {
+    pub fn new(kind: RuntimeErrorKind, position: Position, span: Span) -> Self {
+        Self {
+            kind,
+            position,
+            span,
+        }
+    }
+    pub fn kind(&self) -> &RuntimeErrorKind {
+        &self.kind
+    }
+    pub fn position(&self) -> Position {
+        self.position
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.kind, self.position)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(error: &RuntimeError) -> Self {
+        Diagnostic::error(error.kind.to_string()).with_label(error.span, String::new())
+    }
+}
+
+fn error<T>(kind: RuntimeErrorKind, node: &Node<T>) -> RuntimeError {
+    RuntimeError::new(kind, node.start, node.span)
+}
+
+/// How the execution of a statement ends
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(Value),
+}
+
+/// Evaluates a checked program by walking its AST.
+/// Names are looked up through the symbol table of the resolver, so every declaration
+/// has its own slot and function calls always reach the function in lexical scope.
+pub struct Interpreter<'a> {
+    table: &'a SymbolTable,
+    functions: HashMap<SymbolId, Arc<Function>>,
+    /// variables of the function calls being evaluated, the program itself is the first one
+    frames: Vec<HashMap<SymbolId, Value>>,
+}
+
+/// Functions and top level variables left by a run, to run more statements after it
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+    functions: HashMap<SymbolId, Arc<Function>>,
+    globals: HashMap<SymbolId, Value>,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(table: &'a SymbolTable) -> Self {
+        Self::with_environment(table, Environment::default())
+    }
+
+    /// Creates an interpreter continuing from a previous run, `table` must be the symbol table
+    /// of that run extended with the statements to run next
+    pub fn with_environment(table: &'a SymbolTable, environment: Environment) -> Self {
+        Self {
+            table,
+            functions: environment.functions,
+            frames: vec![environment.globals],
+        }
+    }
+
+    pub fn into_environment(mut self) -> Environment {
+        self.frames.truncate(1);
+        Environment {
+            functions: self.functions,
+            globals: self.frames.pop().unwrap(),
+        }
+    }
+
+    /// Runs a program, returns the value of its top level `return` if it reaches one.
+    /// The program runs on its own thread, with enough stack for `MAX_CALL_DEPTH` nested calls.
+    pub fn run(&mut self, program: &Program) -> Result<Option<Value>, RuntimeError> {
+        thread::scope(|scope| {
+            let handle = thread::Builder::new()
+                .stack_size(MAX_CALL_DEPTH * STACK_PER_CALL)
+                .spawn_scoped(scope, || self.run_program(program))
+                .expect("failed to spawn the interpreter thread");
+            match handle.join() {
+                Ok(result) => result,
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        })
+    }
+
+    fn run_program(&mut self, program: &Program) -> Result<Option<Value>, RuntimeError> {
+        self.collect_functions(&program.0);
+        match self.execute_statements(&program.0)? {
+            Flow::Return(value) => Ok(Some(value)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Functions cannot capture variables, so every function of the program can be
+    /// registered up front, whatever block it is defined in
+    fn collect_functions(&mut self, statements: &[StatementNode]) {
+        for statement in statements {
+            match &statement.value {
+                Statement::FunctionDefinition(function) => {
+                    if let Some(id) = self.table.symbol_id(function.span) {
+                        self.functions.insert(id, Arc::new(function.value.clone()));
+                    }
+                    self.collect_functions(&function.value.statements);
+                }
+                Statement::Definition(_, variable, expression) => {
+                    if let Expression::FunctionDef(function) = &expression.value {
+                        if let Some(id) = self.table.symbol_id(variable.span) {
+                            self.functions.insert(id, Arc::new(function.value.clone()));
+                        }
+                        self.collect_functions(&function.value.statements);
+                    }
+                }
+                Statement::Condition(_, consequence, alternative) => {
+                    self.collect_functions(&consequence.value.0);
+                    if let Some(alternative) = alternative {
+                        self.collect_functions(&alternative.value.0);
+                    }
+                }
+                Statement::While(_, body) => self.collect_functions(&body.value.0),
+                Statement::For(_, _, _, body) => self.collect_functions(&body.value.0),
+                Statement::Switch(_, cases, default) => {
+                    for case in cases {
+                        self.collect_functions(&case.value.body.value.0);
+                    }
+                    if let Some(default) = default {
+                        self.collect_functions(&default.value.0);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn frame(&mut self) -> &mut HashMap<SymbolId, Value> {
+        self.frames.last_mut().unwrap()
+    }
+
+    fn symbol<T>(&self, id: &str, node: &Node<T>) -> Result<SymbolId, RuntimeError> {
+        self.table
+            .symbol_id(node.span)
+            .ok_or_else(|| error(RuntimeErrorKind::Unresolved(id.to_string()), node))
+    }
+
+    fn execute_statements(&mut self, statements: &[StatementNode]) -> Result<Flow, RuntimeError> {
+        for statement in statements {
+            match self.execute_statement(statement)? {
+                Flow::Next => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn execute_block(&mut self, block: &BlockStatementNode) -> Result<Flow, RuntimeError> {
+        self.execute_statements(&block.value.0)
+    }
+
+    fn execute_statement(&mut self, statement: &StatementNode) -> Result<Flow, RuntimeError> {
+        match &statement.value {
+            Statement::Declaration(variable) => {
+                // a declaration executed again, in a loop, starts uninitialized again
+                let id = self.symbol(&variable.value.id, variable)?;
+                self.frame().remove(&id);
+            }
+            Statement::Definition(_, _, expression)
+                if matches!(expression.value, Expression::FunctionDef(_)) => {}
+            Statement::Definition(_, variable, expression) => {
+                let value = self.evaluate(expression)?;
+                let id = self.symbol(&variable.value.id, variable)?;
+                self.frame().insert(id, value);
+            }
+            Statement::Assignment(assignee, expression) => {
+                let value = self.evaluate(expression)?;
+                let id = match &assignee.value {
+                    Assignee::Identifier(id) => self.symbol(id, assignee)?,
+                };
+                self.frame().insert(id, value);
+            }
+            Statement::Condition(condition, consequence, alternative) => {
+                if self.evaluate_condition(condition)? {
+                    return self.execute_block(consequence);
+                } else if let Some(alternative) = alternative {
+                    return self.execute_block(alternative);
+                }
+            }
+            Statement::While(condition, body) => {
+                while self.evaluate_condition(condition)? {
+                    match self.execute_block(body)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Next | Flow::Continue => {}
+                    }
+                }
+            }
+            Statement::For(init, condition, step, body) => {
+                if let Some(init) = init {
+                    self.execute_statement(init)?;
+                }
+                while self.evaluate_condition(condition)? {
+                    match self.execute_block(body)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Next | Flow::Continue => {}
+                    }
+                    if let Some(step) = step {
+                        self.execute_statement(step)?;
+                    }
+                }
+            }
+            Statement::Switch(scrutinee, cases, default) => {
+                let value = self.evaluate(scrutinee)?;
+                for case in cases {
+                    if self.evaluate(&case.value.value)? == value {
+                        return self.execute_block(&case.value.body);
+                    }
+                }
+                if let Some(default) = default {
+                    return self.execute_block(default);
+                }
+            }
+            Statement::Break => return Ok(Flow::Break),
+            Statement::Continue => return Ok(Flow::Continue),
+            Statement::FunctionCall(id, arguments) => {
+                self.call(id, arguments, statement)?;
+            }
+            Statement::Return(expression) => return Ok(Flow::Return(self.evaluate(expression)?)),
+            Statement::TypeDefinition(..) | Statement::FunctionDefinition(_) | Statement::Error => {
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn evaluate_condition(&mut self, condition: &ExpressionNode) -> Result<bool, RuntimeError> {
+        let value = self.evaluate(condition)?;
+        Ok(value
+            .as_bool()
+            .expect("conditions are checked to be `bool`"))
+    }
+
+    fn call<T>(
+        &mut self,
+        id: &str,
+        arguments: &[ExpressionNode],
+        node: &Node<T>,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let symbol = self.symbol(id, node)?;
+        let function = match self.functions.get(&symbol) {
+            Some(function) => Arc::clone(function),
+            None => return Err(error(RuntimeErrorKind::Unresolved(id.to_string()), node)),
+        };
+        if self.frames.len() > MAX_CALL_DEPTH {
+            return Err(error(RuntimeErrorKind::StackOverflow, node));
+        }
+
+        let mut frame = HashMap::new();
+        for (parameter, argument) in function.parameters.iter().zip(arguments) {
+            let value = self.evaluate(argument)?;
+            frame.insert(self.symbol(&parameter.value.id, parameter)?, value);
+        }
+
+        self.frames.push(frame);
+        let flow = self.execute_statements(&function.statements);
+        self.frames.pop();
+
+        match flow? {
+            Flow::Return(value) => Ok(Some(value)),
+            _ => Ok(None),
+        }
+    }
+
+    fn evaluate(&mut self, expression: &ExpressionNode) -> Result<Value, RuntimeError> {
+        match &expression.value {
+            Expression::Identifier(id) => {
+                let symbol = self.symbol(id, expression)?;
+                match self.frame().get(&symbol) {
+                    Some(value) => Ok(value.clone()),
+                    None => Err(error(
+                        RuntimeErrorKind::UninitializedVariable(id.clone()),
+                        expression,
+                    )),
+                }
+            }
+            Expression::Literal(literal) => {
+                Ok(Value::from_literal(literal).expect("only checked literals are evaluated"))
+            }
+            Expression::Add(lhs, rhs) => self.binary(BinaryOperator::Add, lhs, rhs, expression),
+            Expression::Sub(lhs, rhs) => self.binary(BinaryOperator::Sub, lhs, rhs, expression),
+            Expression::Mul(lhs, rhs) => self.binary(BinaryOperator::Mul, lhs, rhs, expression),
+            Expression::Div(lhs, rhs) => self.binary(BinaryOperator::Div, lhs, rhs, expression),
+            Expression::Pow(lhs, rhs) => self.binary(BinaryOperator::Pow, lhs, rhs, expression),
+            Expression::Mod(lhs, rhs) => self.binary(BinaryOperator::Mod, lhs, rhs, expression),
+            Expression::BitAnd(lhs, rhs) => {
+                self.binary(BinaryOperator::BitAnd, lhs, rhs, expression)
+            }
+            Expression::BitOr(lhs, rhs) => self.binary(BinaryOperator::BitOr, lhs, rhs, expression),
+            Expression::BitXor(lhs, rhs) => {
+                self.binary(BinaryOperator::BitXor, lhs, rhs, expression)
+            }
+            Expression::Shl(lhs, rhs) => self.binary(BinaryOperator::Shl, lhs, rhs, expression),
+            Expression::Shr(lhs, rhs) => self.binary(BinaryOperator::Shr, lhs, rhs, expression),
+            Expression::Eq(lhs, rhs) => self.compare(lhs, rhs, |a, b| a == b),
+            Expression::Neq(lhs, rhs) => self.compare(lhs, rhs, |a, b| a != b),
+            Expression::Lt(lhs, rhs) => self.compare(lhs, rhs, |a, b| a < b),
+            Expression::Le(lhs, rhs) => self.compare(lhs, rhs, |a, b| a <= b),
+            Expression::Gt(lhs, rhs) => self.compare(lhs, rhs, |a, b| a > b),
+            Expression::Ge(lhs, rhs) => self.compare(lhs, rhs, |a, b| a >= b),
+            // `&&` and `||` only evaluate their right operand when needed
+            Expression::And(lhs, rhs) => Ok(Value::Boolean(
+                self.evaluate_condition(lhs)? && self.evaluate_condition(rhs)?,
+            )),
+            Expression::Or(lhs, rhs) => Ok(Value::Boolean(
+                self.evaluate_condition(lhs)? || self.evaluate_condition(rhs)?,
+            )),
+            Expression::Not(operand) => Ok(Value::Boolean(!self.evaluate_condition(operand)?)),
+            Expression::Neg(operand) => self
+                .evaluate(operand)?
+                .neg()
+                .map_err(|e| error(RuntimeErrorKind::Arithmetic(e), expression)),
+            Expression::Ternary(condition, consequence, alternative) => {
+                if self.evaluate_condition(condition)? {
+                    self.evaluate(consequence)
+                } else {
+                    self.evaluate(alternative)
+                }
+            }
+            Expression::FunctionCall(id, arguments) => {
+                self.evaluate_call(id, arguments, expression)
+            }
+            Expression::FunctionDef(_) => {
+                panic!("anonymous functions are only evaluated when bound with `auto`")
+            }
+        }
+    }
+
+    /// Kept out of `evaluate` so that recursive calls use as little stack as possible
+    fn evaluate_call(
+        &mut self,
+        id: &str,
+        arguments: &[ExpressionNode],
+        expression: &ExpressionNode,
+    ) -> Result<Value, RuntimeError> {
+        match self.call(id, arguments, expression)? {
+            Some(value) => Ok(value),
+            None => Err(error(
+                RuntimeErrorKind::NoReturnValue(id.to_string()),
+                expression,
+            )),
+        }
+    }
+
+    fn compare(
+        &mut self,
+        lhs: &ExpressionNode,
+        rhs: &ExpressionNode,
+        predicate: fn(&Value, &Value) -> bool,
+    ) -> Result<Value, RuntimeError> {
+        let lhs = self.evaluate(lhs)?;
+        let rhs = self.evaluate(rhs)?;
+        Ok(Value::Boolean(predicate(&lhs, &rhs)))
+    }
+
+    fn binary(
+        &mut self,
+        operator: BinaryOperator,
+        lhs: &ExpressionNode,
+        rhs: &ExpressionNode,
+        expression: &ExpressionNode,
+    ) -> Result<Value, RuntimeError> {
+        let lhs = self.evaluate(lhs)?;
+        let rhs = self.evaluate(rhs)?;
+        Value::binary(operator, &lhs, &rhs)
+            .map_err(|e| error(RuntimeErrorKind::Arithmetic(e), expression))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::Checker;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::resolver::Resolver;
+
+    fn run(code: &str) -> Result<Option<Value>, String> {
+        let mut program = Parser::new(Lexer::new(code)).parse_program().unwrap();
+        let (table, errors) = Resolver::new().resolve_program(&program);
+        assert!(errors.iter().all(|e| !e.is_error()), "{:?}", errors);
+        Checker::new().check_program(&mut program).unwrap();
+        Interpreter::new(&table)
+            .run(&program)
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn run_program() {
+        let result = run(r#"
+            func fib(n: u64): u64 {
+                if n < 2 {
+                    return n;
+                }
+                return fib(n - 1) + fib(n - 2);
+            }
+            func collatz(n: i64): i32 {
+                let steps: i32 = 0;
+                while n != 1 {
+                    n = n % 2 == 0 ? n / 2 : 3 * n + 1;
+                    steps++;
+                }
+                return steps;
+            }
+            auto sum = 0;
+            for let i: i32 = 0; i < 100; i++ {
+                if i % 3 == 0 {
+                    continue;
+                }
+                switch i {
+                    case 50 { break; }
+                    default { sum += i; }
+                }
+            }
+            auto square = func(x: i32): i32 { return x ** 2; };
+            auto bits = (1 << 4 | 3) ^ 1;
+            auto greeting = "fib=" == "fib" || !(fib(20) == 6765) ? "wrong" : "ok";
+            return greeting == "ok" && collatz(27) == 111 && sum == 817 && square(-9) == 81
+                && bits == 18 && -sum == 0 - 817;
+        "#);
+        assert_eq!(result, Ok(Some(Value::Boolean(true))));
+    }
+
+    #[test]
+    fn scopes() {
+        // a call reaches the function in lexical scope, not the one of the caller
+        let result = run(r#"
+            func value(): i32 { return 1; }
+            func call(): i32 { return value(); }
+            func outer(): i32 {
+                func value(): i32 { return 100; }
+                return call() + value();
+            }
+            let x: i32 = 1;
+            if true {
+                let x: i32 = 10;
+                x = x + 1;
+            }
+            return outer() + x;
+        "#);
+        assert_eq!(result, Ok(Some(Value::Int32(102))));
+    }
+
+    #[test]
+    fn runtime_errors() {
+        assert_eq!(
+            run("auto zero = 0; return 1 / zero;"),
+            Err(String::from("Attempt to divide by zero at 1:23"))
+        );
+        assert_eq!(
+            run("let x: u32 = 4294967295; x++;"),
+            Err(String::from("Overflow in `+` on `u32` at 1:26"))
+        );
+        assert_eq!(
+            run("let x: i32; return x;"),
+            Err(String::from("Use of uninitialized variable `x` at 1:20"))
+        );
+        assert_eq!(
+            run("func f(n: i32): i32 { return f(n + 1); } return f(0);"),
+            Err(format!(
+                "Stack overflow, more than {} nested function calls at 1:30",
+                MAX_CALL_DEPTH
+            ))
+        );
+    }
+}
@@ -0,0 +1,324 @@
+use crate::ast::types::Type;
+use crate::ast::Literal;
+use core::fmt;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+
+/// A runtime value, integers keep the width and signedness of their type
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int32(i32),
+    Int64(i64),
+    UInt32(u32),
+    UInt64(u64),
+    Boolean(bool),
+    String(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Mod,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            BinaryOperator::Add => "+",
+            BinaryOperator::Sub => "-",
+            BinaryOperator::Mul => "*",
+            BinaryOperator::Div => "/",
+            BinaryOperator::Pow => "**",
+            BinaryOperator::Mod => "%",
+            BinaryOperator::BitAnd => "&",
+            BinaryOperator::BitOr => "|",
+            BinaryOperator::BitXor => "^",
+            BinaryOperator::Shl => "<<",
+            BinaryOperator::Shr => ">>",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArithmeticError {
+    DivisionByZero,
+    /// the result of the operator does not fit in the type of its operands
+    Overflow(String, Type),
+    NegativeExponent,
+}
+
+impl fmt::Display for ArithmeticError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArithmeticError::DivisionByZero => write!(f, "Attempt to divide by zero"),
+            ArithmeticError::Overflow(operator, ty) => {
+                write!(f, "Overflow in `{}` on `{}`", operator, ty)
+            }
+            ArithmeticError::NegativeExponent => write!(f, "Negative exponent in `**`"),
+        }
+    }
+}
+
+/// Applies an operator on two integers of the same type, the closure gets both as `(a, b)`
+/// and returns `None` on overflow
+macro_rules! integer_operation {
+    ($lhs:expr, $rhs:expr, |$a:ident, $b:ident| $body:expr) => {
+        match ($lhs, $rhs) {
+            (Value::Int32($a), Value::Int32($b)) => Some($body.map(Value::Int32)),
+            (Value::Int64($a), Value::Int64($b)) => Some($body.map(Value::Int64)),
+            (Value::UInt32($a), Value::UInt32($b)) => Some($body.map(Value::UInt32)),
+            (Value::UInt64($a), Value::UInt64($b)) => Some($body.map(Value::UInt64)),
+            _ => None,
+        }
+    };
+}
+
+impl Value {
+    pub fn from_literal(literal: &Literal) -> Option<Value> {
+        match literal {
+            Literal::Int32(v) => Some(Value::Int32(*v)),
+            Literal::Int64(v) => Some(Value::Int64(*v)),
+            Literal::UInt32(v) => Some(Value::UInt32(*v)),
+            Literal::UInt64(v) => Some(Value::UInt64(*v)),
+            Literal::Boolean(v) => Some(Value::Boolean(*v)),
+            Literal::String(v) => Some(Value::String(v.clone())),
+            Literal::Array(_) | Literal::Null => None,
+        }
+    }
+
+    pub fn into_literal(self) -> Literal {
+        match self {
+            Value::Int32(v) => Literal::Int32(v),
+            Value::Int64(v) => Literal::Int64(v),
+            Value::UInt32(v) => Literal::UInt32(v),
+            Value::UInt64(v) => Literal::UInt64(v),
+            Value::Boolean(v) => Literal::Boolean(v),
+            Value::String(v) => Literal::String(v),
+        }
+    }
+
+    pub fn ty(&self) -> Type {
+        match self {
+            Value::Int32(_) => Type::Int(32),
+            Value::Int64(_) => Type::Int(64),
+            Value::UInt32(_) => Type::UInt(32),
+            Value::UInt64(_) => Type::UInt(64),
+            Value::Boolean(_) => Type::Boolean,
+            Value::String(_) => Type::String,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// Value of an integer, wide enough for every integer type
+    pub fn as_integer(&self) -> Option<i128> {
+        match self {
+            Value::Int32(v) => Some(*v as i128),
+            Value::Int64(v) => Some(*v as i128),
+            Value::UInt32(v) => Some(*v as i128),
+            Value::UInt64(v) => Some(*v as i128),
+            _ => None,
+        }
+    }
+
+    /// Applies a binary operator with the fixed-width semantics of the operand type.
+    /// Overflowing results are errors, shifts by the width of the type or more overflow too.
+    /// Panics if the operands do not have the same integer type (or are not strings for `+`),
+    /// which the checker guarantees.
+    pub fn binary(
+        operator: BinaryOperator,
+        lhs: &Value,
+        rhs: &Value,
+    ) -> Result<Value, ArithmeticError> {
+        if let (BinaryOperator::Add, Value::String(a), Value::String(b)) = (operator, lhs, rhs) {
+            return Ok(Value::String(format!("{}{}", a, b)));
+        }
+        let is_zero = rhs.as_integer() == Some(0);
+        if is_zero && matches!(operator, BinaryOperator::Div | BinaryOperator::Mod) {
+            return Err(ArithmeticError::DivisionByZero);
+        }
+        if operator == BinaryOperator::Pow && rhs.as_integer().is_some_and(|v| v < 0) {
+            return Err(ArithmeticError::NegativeExponent);
+        }
+
+        // exponent or shift amount
+        let amount = rhs.as_integer().and_then(|v| u32::try_from(v).ok());
+        let result = match operator {
+            BinaryOperator::Add => integer_operation!(lhs, rhs, |a, b| a.checked_add(*b)),
+            BinaryOperator::Sub => integer_operation!(lhs, rhs, |a, b| a.checked_sub(*b)),
+            BinaryOperator::Mul => integer_operation!(lhs, rhs, |a, b| a.checked_mul(*b)),
+            BinaryOperator::Div => integer_operation!(lhs, rhs, |a, b| a.checked_div(*b)),
+            BinaryOperator::Mod => integer_operation!(lhs, rhs, |a, b| a.checked_rem(*b)),
+            BinaryOperator::Pow => {
+                integer_operation!(lhs, rhs, |a, _b| amount.and_then(|b| a.checked_pow(b)))
+            }
+            BinaryOperator::BitAnd => integer_operation!(lhs, rhs, |a, b| Some(a & b)),
+            BinaryOperator::BitOr => integer_operation!(lhs, rhs, |a, b| Some(a | b)),
+            BinaryOperator::BitXor => integer_operation!(lhs, rhs, |a, b| Some(a ^ b)),
+            // bits shifted out, or into the sign bit, overflow like any other operator
+            BinaryOperator::Shl => integer_operation!(lhs, rhs, |a, _b| amount
+                .and_then(|b| a.checked_shl(b))
+                .filter(|r| amount.and_then(|b| r.checked_shr(b)) == Some(*a))),
+            BinaryOperator::Shr => {
+                integer_operation!(lhs, rhs, |a, _b| amount.and_then(|b| a.checked_shr(b)))
+            }
+        };
+
+        match result {
+            Some(Some(value)) => Ok(value),
+            Some(None) => Err(ArithmeticError::Overflow(operator.to_string(), lhs.ty())),
+            None => panic!(
+                "invalid operands `{}` and `{}` for `{}`",
+                lhs.ty(),
+                rhs.ty(),
+                operator
+            ),
+        }
+    }
+
+    pub fn neg(&self) -> Result<Value, ArithmeticError> {
+        let result = match self {
+            Value::Int32(v) => v.checked_neg().map(Value::Int32),
+            Value::Int64(v) => v.checked_neg().map(Value::Int64),
+            _ => panic!("invalid operand `{}` for `-`", self.ty()),
+        };
+        result.ok_or_else(|| ArithmeticError::Overflow(String::from("-"), self.ty()))
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Int32(a), Value::Int32(b)) => a.partial_cmp(b),
+            (Value::Int64(a), Value::Int64(b)) => a.partial_cmp(b),
+            (Value::UInt32(a), Value::UInt32(b)) => a.partial_cmp(b),
+            (Value::UInt64(a), Value::UInt64(b)) => a.partial_cmp(b),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int32(v) => write!(f, "{}", v),
+            Value::Int64(v) => write!(f, "{}", v),
+            Value::UInt32(v) => write!(f, "{}", v),
+            Value::UInt64(v) => write!(f, "{}", v),
+            Value::Boolean(v) => write!(f, "{}", v),
+            Value::String(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_width_arithmetic() {
+        let binary = |operator, lhs, rhs| Value::binary(operator, &lhs, &rhs);
+
+        assert_eq!(
+            binary(
+                BinaryOperator::Add,
+                Value::UInt32(u32::MAX - 1),
+                Value::UInt32(1)
+            ),
+            Ok(Value::UInt32(u32::MAX))
+        );
+        assert_eq!(
+            binary(BinaryOperator::Add, Value::Int32(i32::MAX), Value::Int32(1)),
+            Err(ArithmeticError::Overflow(String::from("+"), Type::Int(32)))
+        );
+        assert_eq!(
+            binary(BinaryOperator::Sub, Value::UInt64(0), Value::UInt64(1)),
+            Err(ArithmeticError::Overflow(String::from("-"), Type::UInt(64)))
+        );
+        assert_eq!(
+            binary(
+                BinaryOperator::Div,
+                Value::Int32(i32::MIN),
+                Value::Int32(-1)
+            ),
+            Err(ArithmeticError::Overflow(String::from("/"), Type::Int(32)))
+        );
+        assert_eq!(
+            binary(BinaryOperator::Mod, Value::Int64(7), Value::Int64(0)),
+            Err(ArithmeticError::DivisionByZero)
+        );
+        assert_eq!(
+            binary(BinaryOperator::Pow, Value::Int64(2), Value::Int64(62)),
+            Ok(Value::Int64(1 << 62))
+        );
+        assert_eq!(
+            binary(BinaryOperator::Pow, Value::Int32(2), Value::Int32(-1)),
+            Err(ArithmeticError::NegativeExponent)
+        );
+        assert_eq!(
+            binary(BinaryOperator::Shl, Value::UInt32(1), Value::UInt32(31)),
+            Ok(Value::UInt32(1 << 31))
+        );
+        assert_eq!(
+            binary(BinaryOperator::Shl, Value::UInt32(1), Value::UInt32(32)),
+            Err(ArithmeticError::Overflow(
+                String::from("<<"),
+                Type::UInt(32)
+            ))
+        );
+        assert_eq!(
+            binary(BinaryOperator::Shl, Value::Int32(3), Value::Int32(30)),
+            Err(ArithmeticError::Overflow(String::from("<<"), Type::Int(32)))
+        );
+        assert_eq!(
+            binary(BinaryOperator::Shl, Value::Int32(1), Value::Int32(31)),
+            Err(ArithmeticError::Overflow(String::from("<<"), Type::Int(32)))
+        );
+        assert_eq!(
+            binary(BinaryOperator::Shl, Value::Int64(-3), Value::Int64(61)),
+            Ok(Value::Int64(-3 << 61))
+        );
+        assert_eq!(
+            binary(
+                BinaryOperator::Shl,
+                Value::UInt32(u32::MAX),
+                Value::UInt32(4)
+            ),
+            Err(ArithmeticError::Overflow(
+                String::from("<<"),
+                Type::UInt(32)
+            ))
+        );
+        assert_eq!(
+            binary(BinaryOperator::Shr, Value::Int32(-8), Value::Int32(1)),
+            Ok(Value::Int32(-4))
+        );
+        assert_eq!(
+            binary(
+                BinaryOperator::Add,
+                Value::String(String::from("out")),
+                Value::String(String::from("er"))
+            ),
+            Ok(Value::String(String::from("outer")))
+        );
+        assert_eq!(
+            Value::Int32(i32::MIN).neg(),
+            Err(ArithmeticError::Overflow(String::from("-"), Type::Int(32)))
+        );
+    }
+}
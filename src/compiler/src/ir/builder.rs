@@ -0,0 +1,682 @@
+//! Lowering of the syntax tree to SSA, following "Simple and Efficient Construction of Static
+//! Single Assignment Form" (Braun et al.): the value of a variable is looked up through the
+//! predecessors of the block reading it, with phis added where paths meet. Phis of blocks
+//! whose predecessors are not all known yet are completed once the block is sealed.
+
+use super::{
+    Block, BlockId, Comparison, Definition, Function, Instruction, Module, Terminator, ValueId,
+};
+use crate::ast::node::Node;
+use crate::ast::types::Type;
+use crate::ast::{
+    Assignee, BlockStatementNode, Expression, ExpressionNode, ParameterNode, Program, Statement,
+    StatementNode,
+};
+use crate::bytecode::compiler::collect_functions;
+use crate::interpreter::value::{BinaryOperator, Value};
+use crate::resolver::{SymbolId, SymbolTable};
+use std::collections::{HashMap, HashSet};
+
+/// Lowers a checked program, `table` is the symbol table the resolver built for it
+pub fn lower(program: &Program, table: &SymbolTable) -> Module {
+    let mut functions = vec![];
+    collect_functions(table, &program.0, &mut functions);
+    let signatures: HashMap<SymbolId, (u32, Option<Type>)> = functions
+        .iter()
+        .enumerate()
+        .map(|(index, (id, function))| (*id, (index as u32 + 1, function.return_type.clone())))
+        .collect();
+
+    let mut module = Module { functions: vec![] };
+    let program =
+        Builder::new(table, &signatures, String::from("<program>"), None).function(&[], &program.0);
+    module.functions.push(program);
+    for (id, function) in functions {
+        let name = table.symbol(id).name.clone();
+        let builder = Builder::new(table, &signatures, name, function.return_type.clone());
+        module
+            .functions
+            .push(builder.function(&function.parameters, &function.statements));
+    }
+    module
+}
+
+/// Targets of the `continue` and `break` statements of a loop
+struct Loop {
+    next: BlockId,
+    exit: BlockId,
+}
+
+struct Builder<'a> {
+    table: &'a SymbolTable,
+    /// index and output of every function
+    signatures: &'a HashMap<SymbolId, (u32, Option<Type>)>,
+    function: Function,
+    predecessors: Vec<Vec<BlockId>>,
+    sealed: Vec<bool>,
+    /// the block instructions are added to, `None` after a terminator until the next block
+    current: Option<BlockId>,
+    /// the value of each variable at the end of each block, as far as it is known
+    definitions: HashMap<(SymbolId, BlockId), ValueId>,
+    /// phis of unsealed blocks, to complete once their predecessors are known
+    incomplete: HashMap<BlockId, Vec<(SymbolId, ValueId)>>,
+    variables: HashMap<SymbolId, Type>,
+    /// variables declared without a value, whose reads check that they are initialized
+    declared: HashSet<SymbolId>,
+    loops: Vec<Loop>,
+}
+
+impl<'a> Builder<'a> {
+    fn new(
+        table: &'a SymbolTable,
+        signatures: &'a HashMap<SymbolId, (u32, Option<Type>)>,
+        name: String,
+        output: Option<Type>,
+    ) -> Self {
+        Self {
+            table,
+            signatures,
+            function: Function {
+                name,
+                parameters: vec![],
+                output,
+                values: vec![],
+                blocks: vec![],
+            },
+            predecessors: vec![],
+            sealed: vec![],
+            current: None,
+            definitions: HashMap::new(),
+            incomplete: HashMap::new(),
+            variables: HashMap::new(),
+            declared: HashSet::new(),
+            loops: vec![],
+        }
+    }
+
+    fn function(mut self, parameters: &[ParameterNode], statements: &[StatementNode]) -> Function {
+        let entry = self.new_block();
+        self.seal(entry);
+        self.current = Some(entry);
+        for (index, parameter) in parameters.iter().enumerate() {
+            let ty = parameter.value.ty.clone().expect("parameters are typed");
+            self.function.parameters.push(ty.clone());
+            let value = self.emit(
+                Instruction::Parameter(index as u32),
+                Some(ty.clone()),
+                parameter,
+            );
+            self.define(parameter, ty, value);
+        }
+        self.statements(statements);
+        if self.current.is_some() {
+            let terminator = match &self.function.output {
+                // the checker makes sure every path of a function with an output returns
+                Some(_) => Terminator::Unreachable,
+                None => Terminator::Return(None),
+            };
+            self.terminate(terminator);
+        }
+        self.finish()
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.function.blocks.push(Block {
+            instructions: vec![],
+            terminator: Terminator::Unreachable,
+        });
+        self.predecessors.push(vec![]);
+        self.sealed.push(false);
+        BlockId(self.function.blocks.len() as u32 - 1)
+    }
+
+    /// Continues in `block`, or in no block if nothing jumps to it
+    fn switch_to(&mut self, block: BlockId) {
+        self.current = match self.predecessors[block.0 as usize].is_empty() {
+            true => None,
+            false => Some(block),
+        };
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        let block = self.current.take().expect("terminating a block");
+        for successor in terminator.successors() {
+            self.predecessors[successor.0 as usize].push(block);
+        }
+        self.function.blocks[block.0 as usize].terminator = terminator;
+    }
+
+    /// Adds an instruction at the end of the current block
+    fn emit<T>(&mut self, instruction: Instruction, ty: Option<Type>, node: &Node<T>) -> ValueId {
+        let block = self.current.expect("emitting in a block");
+        let value = self.add_value(instruction, ty, node);
+        self.function.blocks[block.0 as usize]
+            .instructions
+            .push(value);
+        value
+    }
+
+    fn add_value<T>(
+        &mut self,
+        instruction: Instruction,
+        ty: Option<Type>,
+        node: &Node<T>,
+    ) -> ValueId {
+        self.function.values.push(Definition {
+            instruction,
+            ty,
+            position: node.start,
+            span: node.span,
+        });
+        ValueId(self.function.values.len() as u32 - 1)
+    }
+
+    fn ty(&self, value: ValueId) -> Type {
+        self.function
+            .value(value)
+            .ty
+            .clone()
+            .expect("values used as operands are typed")
+    }
+
+    /// Adds a phi after the other phis of `block`, `node` is the expression it is the value
+    /// of, if it is not the value of a variable
+    fn phi(
+        &mut self,
+        block: BlockId,
+        ty: Type,
+        operands: Vec<(BlockId, ValueId)>,
+        node: Option<&ExpressionNode>,
+    ) -> ValueId {
+        self.function.values.push(Definition {
+            instruction: Instruction::Phi(operands),
+            ty: Some(ty),
+            position: node.map(|node| node.start).unwrap_or_default(),
+            span: node.map(|node| node.span).unwrap_or_default(),
+        });
+        let value = ValueId(self.function.values.len() as u32 - 1);
+        let values = &self.function.values;
+        let instructions = &mut self.function.blocks[block.0 as usize].instructions;
+        let index = instructions
+            .iter()
+            .take_while(|id| matches!(values[id.0 as usize].instruction, Instruction::Phi(_)))
+            .count();
+        instructions.insert(index, value);
+        value
+    }
+
+    fn symbol<T>(&self, node: &Node<T>) -> SymbolId {
+        self.table
+            .symbol_id(node.span)
+            .expect("names are resolved before lowering")
+    }
+
+    fn define<T>(&mut self, node: &Node<T>, ty: Type, value: ValueId) {
+        let id = self.symbol(node);
+        self.variables.insert(id, ty);
+        self.write(id, value);
+    }
+
+    fn write(&mut self, variable: SymbolId, value: ValueId) {
+        let block = self.current.expect("assigning in a block");
+        self.definitions.insert((variable, block), value);
+    }
+
+    fn read(&mut self, variable: SymbolId, block: BlockId) -> ValueId {
+        if let Some(value) = self.definitions.get(&(variable, block)) {
+            return *value;
+        }
+        let ty = self.variables[&variable].clone();
+        let predecessors = self.predecessors[block.0 as usize].clone();
+        let value = if !self.sealed[block.0 as usize] {
+            let phi = self.phi(block, ty, vec![], None);
+            self.incomplete
+                .entry(block)
+                .or_default()
+                .push((variable, phi));
+            phi
+        } else if predecessors.len() == 1 {
+            self.read(variable, predecessors[0])
+        } else {
+            // written first to end the search in loops
+            let phi = self.phi(block, ty, vec![], None);
+            self.definitions.insert((variable, block), phi);
+            self.complete_phi(variable, phi, block);
+            phi
+        };
+        self.definitions.insert((variable, block), value);
+        value
+    }
+
+    fn complete_phi(&mut self, variable: SymbolId, phi: ValueId, block: BlockId) {
+        let mut operands = vec![];
+        for predecessor in self.predecessors[block.0 as usize].clone() {
+            operands.push((predecessor, self.read(variable, predecessor)));
+        }
+        self.function.values[phi.0 as usize].instruction = Instruction::Phi(operands);
+    }
+
+    /// Marks a block whose predecessors are all known
+    fn seal(&mut self, block: BlockId) {
+        for (variable, phi) in self.incomplete.remove(&block).unwrap_or_default() {
+            self.complete_phi(variable, phi, block);
+        }
+        self.sealed[block.0 as usize] = true;
+    }
+
+    fn statements(&mut self, statements: &[StatementNode]) {
+        for statement in statements {
+            // the rest of the block cannot be reached
+            if self.current.is_none() {
+                return;
+            }
+            self.statement(statement);
+        }
+    }
+
+    fn block(&mut self, block: &BlockStatementNode) {
+        self.statements(&block.value.0);
+    }
+
+    /// Continues in `block` once the current one jumps to it, if it is still open
+    fn jump(&mut self, block: BlockId) {
+        if self.current.is_some() {
+            self.terminate(Terminator::Jump(block));
+        }
+    }
+
+    fn statement(&mut self, statement: &StatementNode) {
+        match &statement.value {
+            Statement::Declaration(variable) => {
+                let ty = variable.value.ty.clone().expect("declarations are typed");
+                let value = self.emit(Instruction::Undefined, Some(ty.clone()), variable);
+                self.define(variable, ty, value);
+                self.declared.insert(self.symbol(variable));
+            }
+            Statement::Definition(_, _, expression)
+                if matches!(expression.value, Expression::FunctionDef(_)) => {}
+            Statement::Definition(_, variable, expression) => {
+                let value = self.expression(expression);
+                let ty = variable.value.ty.clone().unwrap_or_else(|| self.ty(value));
+                self.define(variable, ty, value);
+            }
+            Statement::Assignment(assignee, expression) => {
+                let value = self.expression(expression);
+                let id = match &assignee.value {
+                    Assignee::Identifier(_) => self.symbol(assignee),
+                };
+                self.write(id, value);
+            }
+            Statement::Condition(condition, consequence, alternative) => {
+                let condition = self.expression(condition);
+                let (then, merge) = (self.new_block(), self.new_block());
+                let otherwise = match alternative {
+                    Some(_) => self.new_block(),
+                    None => merge,
+                };
+                self.terminate(Terminator::Branch(condition, then, otherwise));
+                self.seal(then);
+                self.switch_to(then);
+                self.block(consequence);
+                self.jump(merge);
+                if let Some(alternative) = alternative {
+                    self.seal(otherwise);
+                    self.switch_to(otherwise);
+                    self.block(alternative);
+                    self.jump(merge);
+                }
+                self.seal(merge);
+                self.switch_to(merge);
+            }
+            Statement::While(condition, body) => {
+                let header = self.new_block();
+                self.jump(header);
+                self.switch_to(header);
+                self.lower_loop(header, condition, None, body);
+            }
+            Statement::For(init, condition, step, body) => {
+                if let Some(init) = init {
+                    self.statement(init);
+                }
+                let header = self.new_block();
+                self.jump(header);
+                self.switch_to(header);
+                self.lower_loop(header, condition, step.as_deref(), body);
+            }
+            Statement::Switch(scrutinee, cases, default) => {
+                let value = self.expression(scrutinee);
+                let end = self.new_block();
+                for case in cases {
+                    let case_value = self.expression(&case.value.value);
+                    let matches = self.emit(
+                        Instruction::Compare(Comparison::Eq, value, case_value),
+                        Some(Type::Boolean),
+                        case,
+                    );
+                    let (body, next) = (self.new_block(), self.new_block());
+                    self.terminate(Terminator::Branch(matches, body, next));
+                    self.seal(body);
+                    self.switch_to(body);
+                    self.block(&case.value.body);
+                    self.jump(end);
+                    self.seal(next);
+                    self.switch_to(next);
+                }
+                if let Some(default) = default {
+                    self.block(default);
+                }
+                self.jump(end);
+                self.seal(end);
+                self.switch_to(end);
+            }
+            Statement::Break | Statement::Continue => {
+                let target = self.loops.last().map(|labels| match statement.value {
+                    Statement::Break => labels.exit,
+                    _ => labels.next,
+                });
+                match target {
+                    Some(target) => self.terminate(Terminator::Jump(target)),
+                    // like the interpreter, outside of a loop it leaves the function, which
+                    // is an error if the function has to return a value
+                    None if self.function.output.is_some() => {
+                        self.terminate(Terminator::Unreachable)
+                    }
+                    None => self.terminate(Terminator::Return(None)),
+                }
+            }
+            Statement::FunctionCall(_, arguments) => {
+                self.call(arguments, statement);
+            }
+            Statement::Return(expression) => {
+                let value = self.expression(expression);
+                self.terminate(Terminator::Return(Some(value)));
+            }
+            Statement::TypeDefinition(..) | Statement::FunctionDefinition(_) | Statement::Error => {
+            }
+        }
+    }
+
+    /// Lowers a loop whose condition is evaluated in `header`, the current block, `continue`
+    /// goes to the `step` statement of a `for` loop if there is one
+    fn lower_loop(
+        &mut self,
+        header: BlockId,
+        condition: &ExpressionNode,
+        step: Option<&StatementNode>,
+        body: &BlockStatementNode,
+    ) {
+        let condition = self.expression(condition);
+        let (entry, exit) = (self.new_block(), self.new_block());
+        let next = match step {
+            Some(_) => self.new_block(),
+            None => header,
+        };
+        self.terminate(Terminator::Branch(condition, entry, exit));
+        self.seal(entry);
+        self.switch_to(entry);
+
+        self.loops.push(Loop { next, exit });
+        self.block(body);
+        self.loops.pop();
+        self.jump(next);
+        if let Some(step) = step {
+            self.seal(next);
+            self.switch_to(next);
+            if self.current.is_some() {
+                self.statement(step);
+                self.jump(header);
+            }
+        }
+        self.seal(header);
+        self.seal(exit);
+        self.switch_to(exit);
+    }
+
+    fn call<T>(&mut self, arguments: &[ExpressionNode], node: &Node<T>) -> ValueId {
+        let arguments = arguments
+            .iter()
+            .map(|argument| self.expression(argument))
+            .collect();
+        let (index, output) = self.signatures[&self.symbol(node)].clone();
+        self.emit(Instruction::Call(index, arguments), output, node)
+    }
+
+    fn expression(&mut self, expression: &ExpressionNode) -> ValueId {
+        match &expression.value {
+            Expression::Identifier(id) => {
+                let variable = self.symbol(expression);
+                let block = self.current.expect("reading in a block");
+                let value = self.read(variable, block);
+                match self.declared.contains(&variable) {
+                    true => {
+                        let ty = self.ty(value);
+                        self.emit(
+                            Instruction::Initialized(value, id.clone()),
+                            Some(ty),
+                            expression,
+                        )
+                    }
+                    false => value,
+                }
+            }
+            Expression::Literal(literal) => {
+                let value =
+                    Value::from_literal(literal).expect("only checked literals are lowered");
+                let ty = value.ty();
+                self.emit(Instruction::Constant(value), Some(ty), expression)
+            }
+            Expression::Add(lhs, rhs) => self.binary(BinaryOperator::Add, lhs, rhs, expression),
+            Expression::Sub(lhs, rhs) => self.binary(BinaryOperator::Sub, lhs, rhs, expression),
+            Expression::Mul(lhs, rhs) => self.binary(BinaryOperator::Mul, lhs, rhs, expression),
+            Expression::Div(lhs, rhs) => self.binary(BinaryOperator::Div, lhs, rhs, expression),
+            Expression::Pow(lhs, rhs) => self.binary(BinaryOperator::Pow, lhs, rhs, expression),
+            Expression::Mod(lhs, rhs) => self.binary(BinaryOperator::Mod, lhs, rhs, expression),
+            Expression::BitAnd(lhs, rhs) => {
+                self.binary(BinaryOperator::BitAnd, lhs, rhs, expression)
+            }
+            Expression::BitOr(lhs, rhs) => self.binary(BinaryOperator::BitOr, lhs, rhs, expression),
+            Expression::BitXor(lhs, rhs) => {
+                self.binary(BinaryOperator::BitXor, lhs, rhs, expression)
+            }
+            Expression::Shl(lhs, rhs) => self.binary(BinaryOperator::Shl, lhs, rhs, expression),
+            Expression::Shr(lhs, rhs) => self.binary(BinaryOperator::Shr, lhs, rhs, expression),
+            Expression::Eq(lhs, rhs) => self.compare(Comparison::Eq, lhs, rhs, expression),
+            Expression::Neq(lhs, rhs) => self.compare(Comparison::Neq, lhs, rhs, expression),
+            Expression::Lt(lhs, rhs) => self.compare(Comparison::Lt, lhs, rhs, expression),
+            Expression::Le(lhs, rhs) => self.compare(Comparison::Le, lhs, rhs, expression),
+            Expression::Gt(lhs, rhs) => self.compare(Comparison::Gt, lhs, rhs, expression),
+            Expression::Ge(lhs, rhs) => self.compare(Comparison::Ge, lhs, rhs, expression),
+            // `&&` and `||` only evaluate their right operand when needed, otherwise their
+            // value is the one of the left operand
+            Expression::And(lhs, rhs) | Expression::Or(lhs, rhs) => {
+                let is_and = matches!(expression.value, Expression::And(..));
+                let lhs = self.expression(lhs);
+                let (evaluate_rhs, merge) = (self.new_block(), self.new_block());
+                let short = self.current.unwrap();
+                let branch = match is_and {
+                    true => Terminator::Branch(lhs, evaluate_rhs, merge),
+                    false => Terminator::Branch(lhs, merge, evaluate_rhs),
+                };
+                self.terminate(branch);
+                self.seal(evaluate_rhs);
+                self.switch_to(evaluate_rhs);
+                let rhs = self.expression(rhs);
+                let rhs_end = self.current.unwrap();
+                self.jump(merge);
+                self.seal(merge);
+                self.switch_to(merge);
+                self.phi(
+                    merge,
+                    Type::Boolean,
+                    vec![(short, lhs), (rhs_end, rhs)],
+                    Some(expression),
+                )
+            }
+            Expression::Not(operand) => {
+                let operand = self.expression(operand);
+                self.emit(Instruction::Not(operand), Some(Type::Boolean), expression)
+            }
+            Expression::Neg(operand) => {
+                let operand = self.expression(operand);
+                let ty = self.ty(operand);
+                self.emit(Instruction::Neg(operand), Some(ty), expression)
+            }
+            Expression::Ternary(condition, consequence, alternative) => {
+                let condition = self.expression(condition);
+                let (then, otherwise, merge) =
+                    (self.new_block(), self.new_block(), self.new_block());
+                self.terminate(Terminator::Branch(condition, then, otherwise));
+                let mut operands = vec![];
+                for (block, value) in [(then, consequence), (otherwise, alternative)] {
+                    self.seal(block);
+                    self.switch_to(block);
+                    let value = self.expression(value);
+                    operands.push((self.current.unwrap(), value));
+                    self.jump(merge);
+                }
+                self.seal(merge);
+                self.switch_to(merge);
+                let ty = self.ty(operands[0].1);
+                self.phi(merge, ty, operands, Some(expression))
+            }
+            Expression::FunctionCall(_, arguments) => self.call(arguments, expression),
+            Expression::FunctionDef(_) => {
+                panic!("anonymous functions are only lowered when bound with `auto`")
+            }
+        }
+    }
+
+    fn binary(
+        &mut self,
+        operator: BinaryOperator,
+        lhs: &ExpressionNode,
+        rhs: &ExpressionNode,
+        expression: &ExpressionNode,
+    ) -> ValueId {
+        let lhs = self.expression(lhs);
+        let rhs = self.expression(rhs);
+        let ty = self.ty(lhs);
+        self.emit(
+            Instruction::Binary(operator, lhs, rhs),
+            Some(ty),
+            expression,
+        )
+    }
+
+    fn compare(
+        &mut self,
+        comparison: Comparison,
+        lhs: &ExpressionNode,
+        rhs: &ExpressionNode,
+        expression: &ExpressionNode,
+    ) -> ValueId {
+        let lhs = self.expression(lhs);
+        let rhs = self.expression(rhs);
+        self.emit(
+            Instruction::Compare(comparison, lhs, rhs),
+            Some(Type::Boolean),
+            expression,
+        )
+    }
+
+    /// Removes the blocks nothing jumps to and the phis which always have the same value,
+    /// then numbers the blocks and values in order
+    fn finish(mut self) -> Function {
+        let mut function = std::mem::replace(
+            &mut self.function,
+            Function {
+                name: String::new(),
+                parameters: vec![],
+                output: None,
+                values: vec![],
+                blocks: vec![],
+            },
+        );
+
+        // a phi is trivial if it only merges itself and one other value, which replaces it
+        let mut replacements: HashMap<ValueId, ValueId> = HashMap::new();
+        let resolve = |replacements: &HashMap<ValueId, ValueId>, mut value: ValueId| {
+            while let Some(replacement) = replacements.get(&value) {
+                value = *replacement;
+            }
+            value
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (index, definition) in function.values.iter().enumerate() {
+                let phi = ValueId(index as u32);
+                if replacements.contains_key(&phi) {
+                    continue;
+                }
+                if let Instruction::Phi(operands) = &definition.instruction {
+                    let distinct: HashSet<ValueId> = operands
+                        .iter()
+                        .map(|(_, value)| resolve(&replacements, *value))
+                        .filter(|value| *value != phi)
+                        .collect();
+                    if distinct.len() == 1 {
+                        replacements.insert(phi, *distinct.iter().next().unwrap());
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        // new numbers, in the order of the blocks
+        let kept: Vec<usize> = (0..function.blocks.len())
+            .filter(|index| *index == 0 || !self.predecessors[*index].is_empty())
+            .collect();
+        let block_numbers: HashMap<BlockId, BlockId> = kept
+            .iter()
+            .enumerate()
+            .map(|(new, old)| (BlockId(*old as u32), BlockId(new as u32)))
+            .collect();
+        let mut value_numbers: HashMap<ValueId, ValueId> = HashMap::new();
+        let mut values = vec![];
+        let mut blocks = vec![];
+        for index in kept {
+            let block = &function.blocks[index];
+            let mut instructions = vec![];
+            for id in &block.instructions {
+                if replacements.contains_key(id) {
+                    continue;
+                }
+                value_numbers.insert(*id, ValueId(values.len() as u32));
+                instructions.push(ValueId(values.len() as u32));
+                values.push(function.values[id.0 as usize].clone());
+            }
+            blocks.push(Block {
+                instructions,
+                terminator: block.terminator.clone(),
+            });
+        }
+        let renumber =
+            |value: &mut ValueId| *value = value_numbers[&resolve(&replacements, *value)];
+        for definition in &mut values {
+            for operand in definition.instruction.operands_mut() {
+                renumber(operand);
+            }
+            if let Instruction::Phi(operands) = &mut definition.instruction {
+                for (block, _) in operands {
+                    *block = block_numbers[block];
+                }
+            }
+        }
+        for block in &mut blocks {
+            match &mut block.terminator {
+                Terminator::Jump(target) => *target = block_numbers[target],
+                Terminator::Branch(condition, consequence, alternative) => {
+                    renumber(condition);
+                    *consequence = block_numbers[consequence];
+                    *alternative = block_numbers[alternative];
+                }
+                Terminator::Return(Some(value)) => renumber(value),
+                Terminator::Return(None) | Terminator::Unreachable => {}
+            }
+        }
+        function.values = values;
+        function.blocks = blocks;
+        function
+    }
+}
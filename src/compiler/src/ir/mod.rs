@@ -0,0 +1,485 @@
+//! Intermediate representation in SSA form.
+//!
+//! Each function is a control flow graph of basic blocks, entered at its first block. Blocks
+//! hold instructions, each defining one typed value, and end with a terminator. Variables are
+//! gone: every value is defined once, and `phi` instructions at the start of a block pick a
+//! value depending on the predecessor the block is entered from. Function 0 holds the top level
+//! statements of the program, like in the bytecode.
+
+pub mod builder;
+pub mod verify;
+
+pub use builder::lower;
+pub use verify::{verify, VerifyError};
+
+use crate::ast::types::Type;
+use crate::interpreter::value::{BinaryOperator, Value};
+use crate::position::{Position, Span};
+use core::fmt;
+
+/// Index of the function holding the top level statements
+pub const PROGRAM: u32 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ValueId(pub u32);
+
+impl fmt::Display for ValueId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Neq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Comparison::Eq => "eq",
+            Comparison::Neq => "ne",
+            Comparison::Lt => "lt",
+            Comparison::Le => "le",
+            Comparison::Gt => "gt",
+            Comparison::Ge => "ge",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    Constant(Value),
+    /// the value of a parameter of the function
+    Parameter(u32),
+    /// the value of a variable declared without one, any use of it is an error
+    Undefined,
+    Binary(BinaryOperator, ValueId, ValueId),
+    Compare(Comparison, ValueId, ValueId),
+    Not(ValueId),
+    Neg(ValueId),
+    /// the value of the named variable, an error if it is `Undefined`
+    Initialized(ValueId, String),
+    Call(u32, Vec<ValueId>),
+    /// the value coming from each predecessor of the block
+    Phi(Vec<(BlockId, ValueId)>),
+}
+
+impl Instruction {
+    /// The values the instruction uses
+    pub fn operands(&self) -> Vec<ValueId> {
+        match self {
+            Instruction::Constant(_) | Instruction::Parameter(_) | Instruction::Undefined => {
+                vec![]
+            }
+            Instruction::Binary(_, lhs, rhs) | Instruction::Compare(_, lhs, rhs) => {
+                vec![*lhs, *rhs]
+            }
+            Instruction::Not(value)
+            | Instruction::Neg(value)
+            | Instruction::Initialized(value, _) => {
+                vec![*value]
+            }
+            Instruction::Call(_, arguments) => arguments.clone(),
+            Instruction::Phi(operands) => operands.iter().map(|(_, value)| *value).collect(),
+        }
+    }
+
+    fn operands_mut(&mut self) -> Vec<&mut ValueId> {
+        match self {
+            Instruction::Constant(_) | Instruction::Parameter(_) | Instruction::Undefined => {
+                vec![]
+            }
+            Instruction::Binary(_, lhs, rhs) | Instruction::Compare(_, lhs, rhs) => {
+                vec![lhs, rhs]
+            }
+            Instruction::Not(value)
+            | Instruction::Neg(value)
+            | Instruction::Initialized(value, _) => {
+                vec![value]
+            }
+            Instruction::Call(_, arguments) => arguments.iter_mut().collect(),
+            Instruction::Phi(operands) => operands.iter_mut().map(|(_, value)| value).collect(),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Constant(Value::String(value)) => write!(f, "const {:?}", value),
+            Instruction::Constant(value) => write!(f, "const {}", value),
+            Instruction::Parameter(index) => write!(f, "param {}", index),
+            Instruction::Undefined => write!(f, "undefined"),
+            Instruction::Binary(operator, lhs, rhs) => {
+                write!(f, "{} {}, {}", operator_name(*operator), lhs, rhs)
+            }
+            Instruction::Compare(comparison, lhs, rhs) => {
+                write!(f, "{} {}, {}", comparison, lhs, rhs)
+            }
+            Instruction::Not(value) => write!(f, "not {}", value),
+            Instruction::Neg(value) => write!(f, "neg {}", value),
+            Instruction::Initialized(value, name) => write!(f, "initialized {}, `{}`", value, name),
+            Instruction::Call(function, arguments) => {
+                let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+                write!(f, "call @{}({})", function, arguments.join(", "))
+            }
+            Instruction::Phi(operands) => {
+                let operands: Vec<String> = operands
+                    .iter()
+                    .map(|(block, value)| format!("[{}: {}]", block, value))
+                    .collect();
+                write!(f, "phi {}", operands.join(", "))
+            }
+        }
+    }
+}
+
+fn operator_name(operator: BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::Add => "add",
+        BinaryOperator::Sub => "sub",
+        BinaryOperator::Mul => "mul",
+        BinaryOperator::Div => "div",
+        BinaryOperator::Pow => "pow",
+        BinaryOperator::Mod => "mod",
+        BinaryOperator::BitAnd => "and",
+        BinaryOperator::BitOr => "or",
+        BinaryOperator::BitXor => "xor",
+        BinaryOperator::Shl => "shl",
+        BinaryOperator::Shr => "shr",
+    }
+}
+
+/// An instruction and the value it defines
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub instruction: Instruction,
+    /// `None` for calls of functions without an output
+    pub ty: Option<Type>,
+    /// the expression the instruction comes from, where its runtime errors are reported
+    pub position: Position,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    /// goes to the first block if the condition is true, to the second one otherwise
+    Branch(ValueId, BlockId, BlockId),
+    Return(Option<ValueId>),
+    /// the end of a function with an output, which the checker proves cannot be reached
+    Unreachable,
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch(_, consequence, alternative) => vec![*consequence, *alternative],
+            Terminator::Return(_) | Terminator::Unreachable => vec![],
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Terminator::Jump(target) => write!(f, "jump {}", target),
+            Terminator::Branch(condition, consequence, alternative) => {
+                write!(f, "branch {}, {}, {}", condition, consequence, alternative)
+            }
+            Terminator::Return(Some(value)) => write!(f, "return {}", value),
+            Terminator::Return(None) => write!(f, "return"),
+            Terminator::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    /// the values defined in the block, in order, starting with its phis
+    pub instructions: Vec<ValueId>,
+    pub terminator: Terminator,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub parameters: Vec<Type>,
+    /// `None` for functions without an output, and for the program, whose `return`s can
+    /// have any type
+    pub output: Option<Type>,
+    /// every value, indexed by its id
+    pub values: Vec<Definition>,
+    /// every block, the first one is the entry
+    pub blocks: Vec<Block>,
+}
+
+impl Function {
+    pub fn value(&self, id: ValueId) -> &Definition {
+        &self.values[id.0 as usize]
+    }
+
+    pub fn block(&self, id: BlockId) -> &Block {
+        &self.blocks[id.0 as usize]
+    }
+
+    /// The predecessors of every block, in the order of the blocks
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut predecessors = vec![vec![]; self.blocks.len()];
+        for (index, block) in self.blocks.iter().enumerate() {
+            for successor in block.terminator.successors() {
+                predecessors[successor.0 as usize].push(BlockId(index as u32));
+            }
+        }
+        predecessors
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    pub functions: Vec<Function>,
+}
+
+/// Prints the module in the textual IR format
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, function) in self.functions.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            let parameters: Vec<String> =
+                function.parameters.iter().map(|t| t.to_string()).collect();
+            write!(
+                f,
+                "function @{} {}({})",
+                index,
+                function.name,
+                parameters.join(", ")
+            )?;
+            if let Some(output) = &function.output {
+                write!(f, ": {}", output)?;
+            }
+            writeln!(f, " {{")?;
+
+            let predecessors = function.predecessors();
+            for (index, block) in function.blocks.iter().enumerate() {
+                write!(f, "{}:", BlockId(index as u32))?;
+                if !predecessors[index].is_empty() {
+                    let names: Vec<String> =
+                        predecessors[index].iter().map(|b| b.to_string()).collect();
+                    write!(f, " ; preds {}", names.join(", "))?;
+                }
+                writeln!(f)?;
+                for id in &block.instructions {
+                    let definition = function.value(*id);
+                    match &definition.ty {
+                        Some(ty) => writeln!(f, "    {}: {} = {}", id, ty, definition.instruction)?,
+                        None => writeln!(f, "    {}", definition.instruction)?,
+                    }
+                }
+                writeln!(f, "    {}", block.terminator)?;
+            }
+            writeln!(f, "}}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::MAX_CALL_DEPTH;
+    use crate::testing::{self, PROGRAMS};
+    use std::cmp::Ordering;
+    use std::thread;
+
+    /// Runs a function of the module, errors are formatted like the ones of the interpreter
+    fn call(
+        module: &Module,
+        index: u32,
+        arguments: &[Value],
+        depth: usize,
+    ) -> Result<Option<Value>, String> {
+        let function = &module.functions[index as usize];
+        // `None` for undefined values and calls without an output
+        let mut values: Vec<Option<Value>> = vec![None; function.values.len()];
+        let mut previous = BlockId(0);
+        let mut current = BlockId(0);
+        loop {
+            let block = function.block(current);
+            // the phis of a block are evaluated together, from the values of the predecessor
+            let phis: Vec<(ValueId, Option<Value>)> = block
+                .instructions
+                .iter()
+                .filter_map(|id| match &function.value(*id).instruction {
+                    Instruction::Phi(operands) => {
+                        let (_, value) = operands.iter().find(|(b, _)| *b == previous).unwrap();
+                        Some((*id, values[value.0 as usize].clone()))
+                    }
+                    _ => None,
+                })
+                .collect();
+            for (id, value) in phis {
+                values[id.0 as usize] = value;
+            }
+
+            for id in &block.instructions {
+                let definition = function.value(*id);
+                let at = |message: String| format!("{} at {}", message, definition.position);
+                let get = |value: &ValueId| values[value.0 as usize].clone().unwrap();
+                let value = match &definition.instruction {
+                    Instruction::Phi(_) => continue,
+                    Instruction::Constant(value) => Some(value.clone()),
+                    Instruction::Parameter(index) => Some(arguments[*index as usize].clone()),
+                    Instruction::Undefined => None,
+                    Instruction::Binary(operator, lhs, rhs) => Some(
+                        Value::binary(*operator, &get(lhs), &get(rhs))
+                            .map_err(|e| at(e.to_string()))?,
+                    ),
+                    Instruction::Compare(comparison, lhs, rhs) => {
+                        let ordering = get(lhs).partial_cmp(&get(rhs));
+                        let result = match comparison {
+                            Comparison::Eq => get(lhs) == get(rhs),
+                            Comparison::Neq => get(lhs) != get(rhs),
+                            Comparison::Lt => ordering == Some(Ordering::Less),
+                            Comparison::Le => ordering != Some(Ordering::Greater),
+                            Comparison::Gt => ordering == Some(Ordering::Greater),
+                            Comparison::Ge => ordering != Some(Ordering::Less),
+                        };
+                        Some(Value::Boolean(result))
+                    }
+                    Instruction::Not(value) => Some(Value::Boolean(!get(value).as_bool().unwrap())),
+                    Instruction::Neg(value) => {
+                        Some(get(value).neg().map_err(|e| at(e.to_string()))?)
+                    }
+                    Instruction::Initialized(value, name) => match &values[value.0 as usize] {
+                        Some(value) => Some(value.clone()),
+                        None => {
+                            return Err(at(format!("Use of uninitialized variable `{}`", name)))
+                        }
+                    },
+                    Instruction::Call(callee, arguments) => {
+                        if depth > MAX_CALL_DEPTH {
+                            return Err(at(format!(
+                                "Stack overflow, more than {} nested function calls",
+                                MAX_CALL_DEPTH
+                            )));
+                        }
+                        let arguments: Vec<Value> = arguments.iter().map(get).collect();
+                        call(module, *callee, &arguments, depth + 1)?
+                    }
+                };
+                values[id.0 as usize] = value;
+            }
+
+            let next = match &block.terminator {
+                Terminator::Jump(target) => *target,
+                Terminator::Branch(condition, consequence, alternative) => {
+                    match values[condition.0 as usize]
+                        .as_ref()
+                        .unwrap()
+                        .as_bool()
+                        .unwrap()
+                    {
+                        true => *consequence,
+                        false => *alternative,
+                    }
+                }
+                Terminator::Return(value) => {
+                    return Ok(value.map(|value| values[value.0 as usize].clone().unwrap()))
+                }
+                Terminator::Unreachable => panic!("reached `unreachable` in {}", function.name),
+            };
+            previous = current;
+            current = next;
+        }
+    }
+
+    /// The harness: every program lowers to valid IR, which evaluates to the same result, or
+    /// the same error at the same position, as the interpreter
+    #[test]
+    fn same_results_as_the_interpreter() {
+        for program in PROGRAMS {
+            let checked = testing::check(program);
+            let module = checked.to_ir();
+            if let Err(error) = verify(&module) {
+                panic!("{}\n{}\n{}", error, module, program);
+            }
+            let result = thread::Builder::new()
+                .stack_size(256 * 1024 * 1024)
+                .spawn(move || call(&module, PROGRAM, &[], 1))
+                .unwrap()
+                .join()
+                .unwrap();
+            assert_eq!(result, testing::interpret(&checked), "{}", program);
+        }
+    }
+
+    #[test]
+    fn text_format() {
+        let checked = testing::check(
+            "func max(a: u32, b: u32): u32 {\n  return a > b ? a : b;\n}\nlet n: u32 = 0;\nwhile n < 10 && max(n, 3) != 5 {\n  n++;\n}\nreturn n;",
+        );
+        assert_eq!(
+            checked.to_ir().to_string(),
+            r#"function @0 <program>() {
+bb0:
+    %0: u32 = const 0
+    jump bb1
+bb1: ; preds bb0, bb4
+    %1: u32 = phi [bb0: %0], [bb4: %10]
+    %2: u32 = const 10
+    %3: bool = lt %1, %2
+    branch %3, bb2, bb3
+bb2: ; preds bb1
+    %4: u32 = const 3
+    %5: u32 = call @1(%1, %4)
+    %6: u32 = const 5
+    %7: bool = ne %5, %6
+    jump bb3
+bb3: ; preds bb1, bb2
+    %8: bool = phi [bb1: %3], [bb2: %7]
+    branch %8, bb4, bb5
+bb4: ; preds bb3
+    %9: u32 = const 1
+    %10: u32 = add %1, %9
+    jump bb1
+bb5: ; preds bb3
+    return %1
+}
+
+function @1 max(u32, u32): u32 {
+bb0:
+    %0: u32 = param 0
+    %1: u32 = param 1
+    %2: bool = gt %0, %1
+    branch %2, bb1, bb2
+bb1: ; preds bb0
+    jump bb3
+bb2: ; preds bb0
+    jump bb3
+bb3: ; preds bb1, bb2
+    %3: u32 = phi [bb1: %0], [bb2: %1]
+    return %3
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,480 @@
+//! Checks of the invariants of the IR: every use of a value is dominated by its definition,
+//! phis have one operand per predecessor, and operands have the types instructions expect.
+
+use super::{BlockId, Comparison, Function, Instruction, Module, Terminator, ValueId, PROGRAM};
+use crate::ast::types::Type;
+use crate::interpreter::value::BinaryOperator;
+use core::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyError {
+    /// the name of the invalid function
+    pub function: String,
+    pub message: String,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid IR in `{}`: {}", self.function, self.message)
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Verifies every function of a module, returns the first error found
+pub fn verify(module: &Module) -> Result<(), VerifyError> {
+    for (index, function) in module.functions.iter().enumerate() {
+        let verifier = Verifier {
+            module,
+            function,
+            is_program: index as u32 == PROGRAM,
+            definitions: vec![],
+            dominators: vec![],
+        };
+        verifier.verify().map_err(|message| VerifyError {
+            function: function.name.clone(),
+            message,
+        })?;
+    }
+    Ok(())
+}
+
+struct Verifier<'a> {
+    module: &'a Module,
+    function: &'a Function,
+    is_program: bool,
+    /// block and index of the instruction defining each value
+    definitions: Vec<Option<(BlockId, usize)>>,
+    /// immediate dominator of each block, the entry is its own
+    dominators: Vec<Option<BlockId>>,
+}
+
+impl<'a> Verifier<'a> {
+    fn verify(mut self) -> Result<(), String> {
+        let function = self.function;
+        if function.blocks.is_empty() {
+            return Err(String::from("the function has no entry block"));
+        }
+
+        self.definitions = vec![None; function.values.len()];
+        for (index, block) in function.blocks.iter().enumerate() {
+            let block_id = BlockId(index as u32);
+            for (position, value) in block.instructions.iter().enumerate() {
+                match self.definitions.get_mut(value.0 as usize) {
+                    Some(Some(_)) => return Err(format!("{} is defined twice", value)),
+                    Some(definition) => *definition = Some((block_id, position)),
+                    None => return Err(format!("{} in {} does not exist", value, block_id)),
+                }
+            }
+            for successor in block.terminator.successors() {
+                if successor.0 as usize >= function.blocks.len() {
+                    return Err(format!(
+                        "{} jumps to {}, which does not exist",
+                        block_id, successor
+                    ));
+                }
+            }
+        }
+        if let Some(index) = self.definitions.iter().position(Option::is_none) {
+            return Err(format!("%{} is not in any block", index));
+        }
+
+        let predecessors = function.predecessors();
+        if !predecessors[0].is_empty() {
+            return Err(String::from("the entry block has predecessors"));
+        }
+        self.dominators = self.compute_dominators(&predecessors);
+        if let Some(index) = self.dominators.iter().position(Option::is_none) {
+            return Err(format!("{} cannot be reached", BlockId(index as u32)));
+        }
+
+        for (index, block) in function.blocks.iter().enumerate() {
+            let block_id = BlockId(index as u32);
+            for (position, value) in block.instructions.iter().enumerate() {
+                let instruction = &function.value(*value).instruction;
+                if let Instruction::Phi(operands) = instruction {
+                    if position > 0 && !self.is_phi(block.instructions[position - 1]) {
+                        return Err(format!("{} is a phi after other instructions", value));
+                    }
+                    let mut incoming: Vec<BlockId> = operands.iter().map(|(b, _)| *b).collect();
+                    let mut expected = predecessors[index].clone();
+                    incoming.sort();
+                    expected.sort();
+                    if incoming != expected {
+                        return Err(format!(
+                            "{} does not have one operand for each predecessor of {}",
+                            value, block_id
+                        ));
+                    }
+                    // the value has to be available at the end of the predecessor
+                    for (predecessor, operand) in operands {
+                        let (definition, _) = self.definition(*operand)?;
+                        if !self.dominates(definition, *predecessor) {
+                            return Err(format!(
+                                "{} takes {} from {}, which it does not dominate",
+                                value, operand, predecessor
+                            ));
+                        }
+                    }
+                } else {
+                    for operand in instruction.operands() {
+                        let (definition, at) = self.definition(operand)?;
+                        let available = match definition == block_id {
+                            true => at < position,
+                            false => self.dominates(definition, block_id),
+                        };
+                        if !available {
+                            return Err(format!(
+                                "{} uses {}, whose definition does not dominate it",
+                                value, operand
+                            ));
+                        }
+                    }
+                }
+                self.check_types(*value)?;
+            }
+
+            let operand = match &block.terminator {
+                Terminator::Branch(condition, _, _) => Some(*condition),
+                Terminator::Return(value) => *value,
+                Terminator::Jump(_) | Terminator::Unreachable => None,
+            };
+            if let Some(operand) = operand {
+                let (definition, _) = self.definition(operand)?;
+                if !self.dominates(definition, block_id) {
+                    return Err(format!(
+                        "the terminator of {} uses {}, whose definition does not dominate it",
+                        block_id, operand
+                    ));
+                }
+            }
+            self.check_terminator(block_id, &block.terminator)?;
+        }
+        Ok(())
+    }
+
+    fn is_phi(&self, value: ValueId) -> bool {
+        matches!(self.function.value(value).instruction, Instruction::Phi(_))
+    }
+
+    fn definition(&self, value: ValueId) -> Result<(BlockId, usize), String> {
+        self.definitions
+            .get(value.0 as usize)
+            .copied()
+            .flatten()
+            .ok_or_else(|| format!("{} does not exist", value))
+    }
+
+    /// Immediate dominators, with the iterative algorithm of Cooper, Harvey and Kennedy, over
+    /// the blocks in reverse postorder. Blocks which cannot be reached have none.
+    fn compute_dominators(&self, predecessors: &[Vec<BlockId>]) -> Vec<Option<BlockId>> {
+        let count = self.function.blocks.len();
+        let mut postorder = vec![];
+        let mut visited = vec![false; count];
+        // depth first search with an explicit stack of (block, next successor to visit)
+        let mut stack = vec![(BlockId(0), 0)];
+        visited[0] = true;
+        while let Some((block, next)) = stack.pop() {
+            let successors = self.function.block(block).terminator.successors();
+            match successors.get(next) {
+                Some(successor) => {
+                    stack.push((block, next + 1));
+                    if !visited[successor.0 as usize] {
+                        visited[successor.0 as usize] = true;
+                        stack.push((*successor, 0));
+                    }
+                }
+                None => postorder.push(block),
+            }
+        }
+        let mut order = vec![usize::MAX; count];
+        for (index, block) in postorder.iter().enumerate() {
+            order[block.0 as usize] = index;
+        }
+
+        let mut dominators: Vec<Option<BlockId>> = vec![None; count];
+        dominators[0] = Some(BlockId(0));
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in postorder.iter().rev().skip(1) {
+                let mut dominator: Option<BlockId> = None;
+                for predecessor in &predecessors[block.0 as usize] {
+                    if dominators[predecessor.0 as usize].is_none() {
+                        continue;
+                    }
+                    dominator = Some(match dominator {
+                        None => *predecessor,
+                        Some(mut a) => {
+                            let mut b = *predecessor;
+                            while a != b {
+                                while order[a.0 as usize] < order[b.0 as usize] {
+                                    a = dominators[a.0 as usize].unwrap();
+                                }
+                                while order[b.0 as usize] < order[a.0 as usize] {
+                                    b = dominators[b.0 as usize].unwrap();
+                                }
+                            }
+                            a
+                        }
+                    });
+                }
+                if dominators[block.0 as usize] != dominator {
+                    dominators[block.0 as usize] = dominator;
+                    changed = true;
+                }
+            }
+        }
+        dominators
+    }
+
+    fn dominates(&self, dominator: BlockId, mut block: BlockId) -> bool {
+        loop {
+            if block == dominator {
+                return true;
+            }
+            match self.dominators[block.0 as usize] {
+                Some(parent) if parent != block => block = parent,
+                _ => return false,
+            }
+        }
+    }
+
+    fn operand_type(&self, value: ValueId) -> Result<&'a Type, String> {
+        self.function
+            .value(value)
+            .ty
+            .as_ref()
+            .ok_or_else(|| format!("{} has no value to use", value))
+    }
+
+    fn check_types(&self, value: ValueId) -> Result<(), String> {
+        let definition = self.function.value(value);
+        let ty = definition.ty.as_ref();
+        let mismatch = |expected: &str| {
+            let found = ty.map_or(String::from("nothing"), |ty| format!("`{}`", ty));
+            Err(format!("{} should be {}, found {}", value, expected, found))
+        };
+        let same = |operands: &[ValueId]| -> Result<&Type, String> {
+            let first = self.operand_type(operands[0])?;
+            for operand in &operands[1..] {
+                if self.operand_type(*operand)? != first {
+                    return Err(format!("the operands of {} have different types", value));
+                }
+            }
+            Ok(first)
+        };
+
+        match &definition.instruction {
+            Instruction::Constant(constant) if ty != Some(&constant.ty()) => {
+                mismatch(&format!("`{}`", constant.ty()))
+            }
+            Instruction::Constant(_) => Ok(()),
+            Instruction::Parameter(index) => match self.function.parameters.get(*index as usize) {
+                Some(parameter) if Some(parameter) == ty => Ok(()),
+                Some(parameter) => mismatch(&format!("`{}`", parameter)),
+                None => Err(format!("{} is a parameter which does not exist", value)),
+            },
+            Instruction::Undefined if ty.is_none() => mismatch("typed"),
+            Instruction::Undefined => Ok(()),
+            Instruction::Binary(operator, lhs, rhs) => {
+                let operand = same(&[*lhs, *rhs])?;
+                let valid = operand.is_integer()
+                    || (*operator == BinaryOperator::Add && *operand == Type::String);
+                if !valid {
+                    return Err(format!("`{}` is not an operand of {}", operand, value));
+                }
+                match ty == Some(operand) {
+                    true => Ok(()),
+                    false => mismatch(&format!("`{}`", operand)),
+                }
+            }
+            Instruction::Compare(comparison, lhs, rhs) => {
+                let operand = same(&[*lhs, *rhs])?;
+                let ordering = !matches!(comparison, Comparison::Eq | Comparison::Neq);
+                if ordering && !operand.is_integer() {
+                    return Err(format!("`{}` is not an operand of {}", operand, value));
+                }
+                match ty == Some(&Type::Boolean) {
+                    true => Ok(()),
+                    false => mismatch("`bool`"),
+                }
+            }
+            Instruction::Not(operand) => {
+                if self.operand_type(*operand)? != &Type::Boolean {
+                    return Err(format!("the operand of {} is not a `bool`", value));
+                }
+                match ty == Some(&Type::Boolean) {
+                    true => Ok(()),
+                    false => mismatch("`bool`"),
+                }
+            }
+            Instruction::Neg(operand) => {
+                let operand = self.operand_type(*operand)?;
+                if !operand.is_signed() {
+                    return Err(format!("the operand of {} is not a signed integer", value));
+                }
+                match ty == Some(operand) {
+                    true => Ok(()),
+                    false => mismatch(&format!("`{}`", operand)),
+                }
+            }
+            Instruction::Initialized(..) | Instruction::Phi(_) if ty.is_none() => mismatch("typed"),
+            Instruction::Initialized(operand, _) => {
+                let operand = self.operand_type(*operand)?;
+                match ty == Some(operand) {
+                    true => Ok(()),
+                    false => mismatch(&format!("`{}`", operand)),
+                }
+            }
+            Instruction::Call(index, arguments) => {
+                let callee = match self.module.functions.get(*index as usize) {
+                    Some(callee) if *index != PROGRAM => callee,
+                    _ => {
+                        return Err(format!(
+                            "{} calls @{}, which is not a function",
+                            value, index
+                        ))
+                    }
+                };
+                if arguments.len() != callee.parameters.len() {
+                    return Err(format!(
+                        "{} passes {} arguments to `{}`, which takes {}",
+                        value,
+                        arguments.len(),
+                        callee.name,
+                        callee.parameters.len()
+                    ));
+                }
+                for (argument, parameter) in arguments.iter().zip(&callee.parameters) {
+                    if self.operand_type(*argument)? != parameter {
+                        return Err(format!(
+                            "{} passes {} to a parameter of type `{}`",
+                            value, argument, parameter
+                        ));
+                    }
+                }
+                match ty == callee.output.as_ref() {
+                    true => Ok(()),
+                    false => mismatch(
+                        &callee
+                            .output
+                            .as_ref()
+                            .map_or(String::from("nothing"), |ty| format!("`{}`", ty)),
+                    ),
+                }
+            }
+            Instruction::Phi(operands) => {
+                for (_, operand) in operands {
+                    if Some(self.operand_type(*operand)?) != ty {
+                        return Err(format!("{} takes {} of another type", value, operand));
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn check_terminator(&self, block: BlockId, terminator: &Terminator) -> Result<(), String> {
+        match terminator {
+            Terminator::Branch(condition, _, _) => {
+                if self.operand_type(*condition)? != &Type::Boolean {
+                    return Err(format!("the condition of {} is not a `bool`", block));
+                }
+            }
+            // the program can return values of any type
+            Terminator::Return(_) if self.is_program => {}
+            Terminator::Return(value) => {
+                let ty = match value {
+                    Some(value) => Some(self.operand_type(*value)?),
+                    None => None,
+                };
+                if ty != self.function.output.as_ref() {
+                    return Err(format!("{} returns a value of the wrong type", block));
+                }
+            }
+            Terminator::Jump(_) | Terminator::Unreachable => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::value::Value;
+    use crate::testing;
+
+    /// The module of `max` from the text format test of the IR, broken by `edit`
+    fn broken(edit: impl FnOnce(&mut Function)) -> String {
+        let checked = testing::check("func max(a: u32, b: u32): u32 { return a > b ? a : b; }");
+        let mut module = checked.to_ir();
+        assert_eq!(verify(&module), Ok(()));
+        edit(&mut module.functions[1]);
+        verify(&module).unwrap_err().to_string()
+    }
+
+    fn phi(operands: &[(u32, u32)]) -> Instruction {
+        Instruction::Phi(
+            operands
+                .iter()
+                .map(|(block, value)| (BlockId(*block), ValueId(*value)))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn dominance() {
+        assert_eq!(
+            broken(|max| max.blocks[0].instructions.swap(1, 2)),
+            "Invalid IR in `max`: %2 uses %1, whose definition does not dominate it"
+        );
+        assert_eq!(
+            broken(|max| max.values[3].instruction = phi(&[(1, 0), (2, 3)])),
+            "Invalid IR in `max`: %3 takes %3 from bb2, which it does not dominate"
+        );
+        assert_eq!(
+            broken(|max| {
+                let phi = max.blocks[3].instructions.pop().unwrap();
+                max.blocks[0].instructions.push(phi);
+            }),
+            "Invalid IR in `max`: %3 is a phi after other instructions"
+        );
+        assert_eq!(
+            broken(|max| max.blocks[1].terminator = Terminator::Return(Some(ValueId(0)))),
+            "Invalid IR in `max`: %3 does not have one operand for each predecessor of bb3"
+        );
+        assert_eq!(
+            broken(|max| max.blocks[2].terminator = Terminator::Jump(BlockId(2))),
+            "Invalid IR in `max`: %3 does not have one operand for each predecessor of bb3"
+        );
+        assert_eq!(
+            broken(|max| max.blocks[0].terminator = Terminator::Jump(BlockId(1))),
+            "Invalid IR in `max`: bb2 cannot be reached"
+        );
+    }
+
+    #[test]
+    fn types() {
+        assert_eq!(
+            broken(|max| max.values[2].ty = Some(Type::UInt(32))),
+            "Invalid IR in `max`: %2 should be `bool`, found `u32`"
+        );
+        assert_eq!(
+            broken(|max| max.values[1].instruction = Instruction::Constant(Value::Int32(1))),
+            "Invalid IR in `max`: %1 should be `i32`, found `u32`"
+        );
+        assert_eq!(
+            broken(|max| max.blocks[3].terminator = Terminator::Return(Some(ValueId(2)))),
+            "Invalid IR in `max`: bb3 returns a value of the wrong type"
+        );
+        assert_eq!(
+            broken(|max| {
+                max.values[2].instruction =
+                    Instruction::Binary(BinaryOperator::Add, ValueId(0), ValueId(1));
+                max.values[2].ty = Some(Type::Boolean);
+            }),
+            "Invalid IR in `max`: %2 should be `u32`, found `bool`"
+        );
+    }
+}
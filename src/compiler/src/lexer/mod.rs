@@ -1,7 +1,7 @@
 pub mod tokens;
 
-use crate::lexer::tokens::{Token, TokenType};
-use crate::position::Position;
+use crate::lexer::tokens::{LexError, Token, TokenType};
+use crate::position::{FileId, Position, Span};
 use std::iter::Peekable;
 use std::str::Chars;
 
@@ -9,19 +9,33 @@ pub struct Lexer<'a> {
     input: Peekable<Chars<'a>>,
     line: usize,
     col: usize,
+    offset: usize,
+    file_id: FileId,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(code: &'a str) -> Self {
+        Lexer::with_file(code, 0)
+    }
+
+    /// Creates a lexer for a file of a `SourceMap`, the spans of its tokens refer to `file_id`
+    pub fn with_file(code: &'a str, file_id: FileId) -> Self {
         Self {
             input: code.chars().peekable(),
             line: 0,
             col: 0,
+            offset: 0,
+            file_id,
         }
     }
 
+    pub fn file_id(&self) -> FileId {
+        self.file_id
+    }
+
     fn next_char(&mut self) -> Option<char> {
         if let Some(c) = self.input.next() {
+            self.offset += c.len_utf8();
             if self.is_linebreak(&c) {
                 self.line += 1;
                 self.col = 0;
@@ -66,9 +80,13 @@ impl<'a> Lexer<'a> {
                 }
             }
             '+' => {
-                if let Some(&'+') = self.peek_char() {
+                let next_char = self.peek_char();
+                if let Some(&'+') = next_char {
                     value.push(self.next_char().unwrap());
                     Some(TokenType::Increment)
+                } else if let Some(&'=') = next_char {
+                    value.push(self.next_char().unwrap());
+                    Some(TokenType::PlusAssign)
                 } else {
                     Some(TokenType::Plus)
                 }
@@ -78,6 +96,9 @@ impl<'a> Lexer<'a> {
                     if *n == '-' {
                         value.push(self.next_char().unwrap());
                         Some(TokenType::Decrement)
+                    } else if *n == '=' {
+                        value.push(self.next_char().unwrap());
+                        Some(TokenType::MinusAssign)
                     } else if n.is_ascii_digit() {
                         self.read_number(&mut value);
                         Some(TokenType::IntLiteral)
@@ -89,9 +110,13 @@ impl<'a> Lexer<'a> {
                 }
             }
             '*' => {
-                if let Some(&'*') = self.peek_char() {
+                let next_char = self.peek_char();
+                if let Some(&'*') = next_char {
+                    value.push(self.next_char().unwrap());
+                    self.with_assign(&mut value, TokenType::Power, TokenType::PowerAssign)
+                } else if let Some(&'=') = next_char {
                     value.push(self.next_char().unwrap());
-                    Some(TokenType::Power)
+                    Some(TokenType::AsteriskAssign)
                 } else {
                     Some(TokenType::Asterisk)
                 }
@@ -106,17 +131,17 @@ impl<'a> Lexer<'a> {
                     }
                     Some(TokenType::Comment)
                 } else {
-                    Some(TokenType::Slash)
+                    self.with_assign(&mut value, TokenType::Slash, TokenType::SlashAssign)
                 }
             }
-            '%' => Some(TokenType::Percent),
+            '%' => self.with_assign(&mut value, TokenType::Percent, TokenType::PercentAssign),
             '?' => Some(TokenType::QuestionMark),
             '&' => {
                 if let Some(&'&') = self.peek_char() {
                     value.push(self.next_char().unwrap());
                     Some(TokenType::And)
                 } else {
-                    Some(TokenType::BitAnd)
+                    self.with_assign(&mut value, TokenType::BitAnd, TokenType::BitAndAssign)
                 }
             }
             '|' => {
@@ -124,10 +149,10 @@ impl<'a> Lexer<'a> {
                     value.push(self.next_char().unwrap());
                     Some(TokenType::Or)
                 } else {
-                    Some(TokenType::BitOr)
+                    self.with_assign(&mut value, TokenType::BitOr, TokenType::BitOrAssign)
                 }
             }
-            '^' => Some(TokenType::BitXor),
+            '^' => self.with_assign(&mut value, TokenType::BitXor, TokenType::BitXorAssign),
             '<' => {
                 let next_char = self.peek_char();
                 if let Some(&'=') = next_char {
@@ -135,7 +160,11 @@ impl<'a> Lexer<'a> {
                     Some(TokenType::Lte)
                 } else if let Some(&'<') = next_char {
                     value.push(self.next_char().unwrap());
-                    Some(TokenType::BitLeftShift)
+                    self.with_assign(
+                        &mut value,
+                        TokenType::BitLeftShift,
+                        TokenType::BitLeftShiftAssign,
+                    )
                 } else {
                     Some(TokenType::Lt)
                 }
@@ -147,7 +176,11 @@ impl<'a> Lexer<'a> {
                     Some(TokenType::Gte)
                 } else if let Some(&'>') = next_char {
                     value.push(self.next_char().unwrap());
-                    Some(TokenType::BitRightShift)
+                    self.with_assign(
+                        &mut value,
+                        TokenType::BitRightShift,
+                        TokenType::BitRightShiftAssign,
+                    )
                 } else {
                     Some(TokenType::Gt)
                 }
@@ -171,18 +204,24 @@ impl<'a> Lexer<'a> {
             ']' => Some(TokenType::RBracket),
             '"' => {
                 value.pop(); // pop first quotation mark
+                let mut terminated = false;
                 while let Some(n) = self.next_char() {
-                    if n == '\\' && self.peek_char()? == &'"' {
+                    if n == '\\' && self.peek_char() == Some(&'"') {
                         // allow escaped quotation marks
                         value.push(n);
-                        value.push(self.next_char()?);
+                        value.push(self.next_char().unwrap());
                     } else if n == '"' {
-                        break; // end of string literal
+                        terminated = true; // end of string literal
+                        break;
                     } else {
                         value.push(n);
                     }
                 }
-                Some(TokenType::StringLiteral)
+                if terminated {
+                    Some(TokenType::StringLiteral)
+                } else {
+                    Some(TokenType::Error(LexError::UnterminatedString))
+                }
             }
             _ => {
                 if c == '_' || c.is_ascii_alphabetic() {
@@ -195,17 +234,34 @@ impl<'a> Lexer<'a> {
                     self.read_number(&mut value);
                     Some(TokenType::IntLiteral)
                 } else {
-                    None
+                    Some(TokenType::Error(LexError::UnexpectedCharacter))
                 }
             }
         }?;
-        Some(Token::new(token_type, value, position))
+        let span = Span::new(self.file_id, position.offset, self.offset);
+        Some(Token::new(token_type, value, position, span))
+    }
+
+    /// Returns `compound` and consumes the `=` if the operator is followed by one, `operator` otherwise
+    fn with_assign(
+        &mut self,
+        value: &mut String,
+        operator: TokenType,
+        compound: TokenType,
+    ) -> Option<TokenType> {
+        if let Some(&'=') = self.peek_char() {
+            value.push(self.next_char().unwrap());
+            Some(compound)
+        } else {
+            Some(operator)
+        }
     }
 
     fn get_position(&self) -> Position {
         Position {
             line: self.line,
             col: self.col,
+            offset: self.offset,
         }
     }
 
@@ -248,6 +304,7 @@ impl<'a> Lexer<'a> {
             "default" => Some(TokenType::Default),
             "true" => Some(TokenType::BooleanLiteral),
             "false" => Some(TokenType::BooleanLiteral),
+            "null" => Some(TokenType::NullLiteral),
             _ => None,
         }
     }
@@ -273,77 +330,90 @@ mod tests {
         let lexer = Lexer::new(
             r#"
             // single line comment
-            _var0 var1 123 -123 true false
+            _var0 var1 123 -123 true false null
             = + - * / % ** ++ -- == != < <= > >= ! ? & ^ | << >> && || , ; : ( ) [ ] { }
             "string literal" "escaped \"string literal\""
             typedef func let auto return if else for while continue break switch case default
             i32 u32 i64 u64 string bool
+            += -= *= /= %= **= &= |= ^= <<= >>=
         "#,
         );
 
         let expected: Vec<TokenType> = vec![
-            TokenType::Comment,        // // single line comment
-            TokenType::Identifier,     // _var0
-            TokenType::Identifier,     // var1
-            TokenType::IntLiteral,     // 123
-            TokenType::IntLiteral,     // -123
-            TokenType::BooleanLiteral, // true
-            TokenType::BooleanLiteral, // false
-            TokenType::Assign,         // =
-            TokenType::Plus,           // +
-            TokenType::Minus,          // -
-            TokenType::Asterisk,       // *
-            TokenType::Slash,          // /
-            TokenType::Percent,        // %
-            TokenType::Power,          // **
-            TokenType::Increment,      // ++
-            TokenType::Decrement,      // --
-            TokenType::Eq,             // ==
-            TokenType::Neq,            // !=
-            TokenType::Lt,             // <
-            TokenType::Lte,            // <=
-            TokenType::Gt,             // >
-            TokenType::Gte,            // >=
-            TokenType::Not,            // !
-            TokenType::QuestionMark,   // ?
-            TokenType::BitAnd,         // &
-            TokenType::BitXor,         // ^
-            TokenType::BitOr,          // |
-            TokenType::BitLeftShift,   // <<
-            TokenType::BitRightShift,  // >>
-            TokenType::And,            // &&
-            TokenType::Or,             // ||
-            TokenType::Comma,          // ,
-            TokenType::Semicolon,      // ;
-            TokenType::Colon,          // :
-            TokenType::LParen,         // (
-            TokenType::RParen,         // )
-            TokenType::LBracket,       // [
-            TokenType::RBracket,       // ]
-            TokenType::LBrace,         // {
-            TokenType::RBrace,         // }
-            TokenType::StringLiteral,  // "string literal"
-            TokenType::StringLiteral,  // "escaped \"string literal\""
-            TokenType::Typedef,        // typedef
-            TokenType::Function,       // func
-            TokenType::Let,            // let
-            TokenType::Auto,           // auto
-            TokenType::Return,         // return
-            TokenType::If,             // if
-            TokenType::Else,           // else
-            TokenType::For,            // for
-            TokenType::While,          // while
-            TokenType::Continue,       // continue
-            TokenType::Break,          // break
-            TokenType::Switch,         // switch
-            TokenType::Case,           // case
-            TokenType::Default,        // default
-            TokenType::VarType,        // i32
-            TokenType::VarType,        // u32
-            TokenType::VarType,        // i64
-            TokenType::VarType,        // u64
-            TokenType::VarType,        // string
-            TokenType::VarType,        // bool
+            TokenType::Comment,             // // single line comment
+            TokenType::Identifier,          // _var0
+            TokenType::Identifier,          // var1
+            TokenType::IntLiteral,          // 123
+            TokenType::IntLiteral,          // -123
+            TokenType::BooleanLiteral,      // true
+            TokenType::BooleanLiteral,      // false
+            TokenType::NullLiteral,         // null
+            TokenType::Assign,              // =
+            TokenType::Plus,                // +
+            TokenType::Minus,               // -
+            TokenType::Asterisk,            // *
+            TokenType::Slash,               // /
+            TokenType::Percent,             // %
+            TokenType::Power,               // **
+            TokenType::Increment,           // ++
+            TokenType::Decrement,           // --
+            TokenType::Eq,                  // ==
+            TokenType::Neq,                 // !=
+            TokenType::Lt,                  // <
+            TokenType::Lte,                 // <=
+            TokenType::Gt,                  // >
+            TokenType::Gte,                 // >=
+            TokenType::Not,                 // !
+            TokenType::QuestionMark,        // ?
+            TokenType::BitAnd,              // &
+            TokenType::BitXor,              // ^
+            TokenType::BitOr,               // |
+            TokenType::BitLeftShift,        // <<
+            TokenType::BitRightShift,       // >>
+            TokenType::And,                 // &&
+            TokenType::Or,                  // ||
+            TokenType::Comma,               // ,
+            TokenType::Semicolon,           // ;
+            TokenType::Colon,               // :
+            TokenType::LParen,              // (
+            TokenType::RParen,              // )
+            TokenType::LBracket,            // [
+            TokenType::RBracket,            // ]
+            TokenType::LBrace,              // {
+            TokenType::RBrace,              // }
+            TokenType::StringLiteral,       // "string literal"
+            TokenType::StringLiteral,       // "escaped \"string literal\""
+            TokenType::Typedef,             // typedef
+            TokenType::Function,            // func
+            TokenType::Let,                 // let
+            TokenType::Auto,                // auto
+            TokenType::Return,              // return
+            TokenType::If,                  // if
+            TokenType::Else,                // else
+            TokenType::For,                 // for
+            TokenType::While,               // while
+            TokenType::Continue,            // continue
+            TokenType::Break,               // break
+            TokenType::Switch,              // switch
+            TokenType::Case,                // case
+            TokenType::Default,             // default
+            TokenType::VarType,             // i32
+            TokenType::VarType,             // u32
+            TokenType::VarType,             // i64
+            TokenType::VarType,             // u64
+            TokenType::VarType,             // string
+            TokenType::VarType,             // bool
+            TokenType::PlusAssign,          // +=
+            TokenType::MinusAssign,         // -=
+            TokenType::AsteriskAssign,      // *=
+            TokenType::SlashAssign,         // /=
+            TokenType::PercentAssign,       // %=
+            TokenType::PowerAssign,         // **=
+            TokenType::BitAndAssign,        // &=
+            TokenType::BitOrAssign,         // |=
+            TokenType::BitXorAssign,        // ^=
+            TokenType::BitLeftShiftAssign,  // <<=
+            TokenType::BitRightShiftAssign, // >>=
         ];
 
         let tokens: Vec<(Token, TokenType)> = lexer.into_iter().zip(expected).collect();
@@ -352,4 +422,30 @@ mod tests {
             assert_eq!(token.ty, token_type);
         }
     }
+
+    #[test]
+    fn test_lexer_errors() {
+        let lexer = Lexer::new("a @ é 1 \"unterminated");
+        let tokens: Vec<(TokenType, String)> = lexer.map(|t| (t.ty, t.value)).collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenType::Identifier, String::from("a")),
+                (
+                    TokenType::Error(LexError::UnexpectedCharacter),
+                    String::from("@")
+                ),
+                (
+                    TokenType::Error(LexError::UnexpectedCharacter),
+                    String::from("é")
+                ),
+                (TokenType::IntLiteral, String::from("1")),
+                (
+                    TokenType::Error(LexError::UnterminatedString),
+                    String::from("unterminated")
+                ),
+            ]
+        );
+    }
 }
@@ -1,4 +1,4 @@
-use crate::position::Position;
+use crate::position::{Position, Span};
 use core::fmt;
 use std::fmt::Formatter;
 
@@ -7,14 +7,16 @@ pub struct Token {
     pub ty: TokenType,
     pub value: String,
     pub position: Position,
+    pub span: Span,
 }
 
 impl Token {
-    pub fn new(ty: TokenType, value: String, position: Position) -> Token {
+    pub fn new(ty: TokenType, value: String, position: Position, span: Span) -> Token {
         Token {
             ty,
             value,
             position,
+            span,
         }
     }
 }
@@ -25,6 +27,21 @@ impl fmt::Display for Token {
     }
 }
 
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum LexError {
+    UnexpectedCharacter,
+    UnterminatedString,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LexError::UnexpectedCharacter => write!(f, "Unexpected character"),
+            LexError::UnterminatedString => write!(f, "Unterminated string literal"),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum TokenType {
     Identifier, // ^([_a-zA-Z][_a-zA-Z0-9]*)
@@ -50,6 +67,7 @@ pub enum TokenType {
     IntLiteral,     // -?[0-9]+
     StringLiteral,  // ^"([^"\\]|\\.)*"
     BooleanLiteral, // ^(true|false)
+    NullLiteral,    // null
 
     // Operators
     Assign,       // =
@@ -63,6 +81,19 @@ pub enum TokenType {
     Decrement,    // --
     Power,        // **
 
+    // Compound assignment operators
+    PlusAssign,          // +=
+    MinusAssign,         // -=
+    AsteriskAssign,      // *=
+    SlashAssign,         // /=
+    PercentAssign,       // %=
+    PowerAssign,         // **=
+    BitAndAssign,        // &=
+    BitOrAssign,         // |=
+    BitXorAssign,        // ^=
+    BitLeftShiftAssign,  // <<=
+    BitRightShiftAssign, // >>=
+
     // Bitwise operators
     BitAnd,        // &
     BitOr,         // |
@@ -95,4 +126,6 @@ pub enum TokenType {
     RBracket, // ]
 
     Comment, // single line comment -> //.*\n?
+
+    Error(LexError), // input that does not form a valid token
 }
@@ -1,6 +1,247 @@
-#![allow(dead_code)] // TODO: remove
+//! Compiler for the outer programming language.
+//!
+//! The top level functions run the whole pipeline on a source string:
+//!
+//! ```
+//! let value = outer_compiler::run("func double(n: i32): i32 { return n * 2; } return double(21);")?;
+//! assert_eq!(value, Some(outer_compiler::Value::Int32(42)));
+//! # Ok::<(), outer_compiler::Error>(())
+//! ```
+//!
+//! Each pass is also available on its own through the modules, from the `lexer` to the
+//! `interpreter`, the `optimizer`, the SSA `ir` and the `bytecode`, `c` and `wasm` backends.
 
-mod ast;
-mod lexer;
-mod parser;
-mod position;
+pub mod ast;
+pub mod bytecode;
+pub mod c;
+pub mod checker;
+pub mod diagnostics;
+pub mod interpreter;
+pub mod ir;
+pub mod lexer;
+pub mod optimizer;
+pub mod parser;
+pub mod position;
+pub mod resolver;
+pub mod source_map;
+#[cfg(test)]
+mod testing;
+pub mod wasm;
+
+pub use ast::types::Type;
+pub use ast::{Expression, Function, Literal, Program, Statement};
+pub use diagnostics::{Diagnostic, Renderer, Severity};
+pub use interpreter::value::Value;
+pub use position::{Position, Span};
+pub use source_map::SourceMap;
+
+use checker::initialization::{self, InitializationError};
+use checker::{CheckError, Checker};
+use core::fmt;
+use interpreter::{Interpreter, RuntimeError};
+use lexer::Lexer;
+use optimizer::ConstantError;
+use parser::{Parser, ParserError};
+use resolver::{ResolveError, Resolver, SymbolTable};
+
+/// Errors of the compilation or the execution of a program, from the pass that found them
+#[derive(Debug, Clone)]
+pub enum Error {
+    Parse(Vec<ParserError>),
+    /// Names that cannot be resolved, along with the warnings of the resolver
+    Resolve(Vec<ResolveError>),
+    Type(Vec<CheckError>),
+    /// Operations on constants which always fail
+    Constant(Vec<ConstantError>),
+    /// Variables which may be read before they are assigned
+    Initialization(Vec<InitializationError>),
+    Runtime(RuntimeError),
+}
+
+impl Error {
+    /// The errors as diagnostics, ready to be rendered
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            Error::Parse(errors) => errors.iter().map(Diagnostic::from).collect(),
+            Error::Resolve(errors) => errors.iter().map(Diagnostic::from).collect(),
+            Error::Type(errors) => errors.iter().map(Diagnostic::from).collect(),
+            Error::Constant(errors) => errors.iter().map(Diagnostic::from).collect(),
+            Error::Initialization(errors) => errors.iter().map(Diagnostic::from).collect(),
+            Error::Runtime(error) => vec![Diagnostic::from(error)],
+        }
+    }
+
+    /// Renders every diagnostic against the sources of `source_map`
+    ///
+    /// ```
+    /// use outer_compiler::SourceMap;
+    ///
+    /// let source = "let a: i32 = true;";
+    /// let mut source_map = SourceMap::new();
+    /// source_map.add_file(String::from("main.out"), String::from(source));
+    ///
+    /// let error = outer_compiler::check(source).unwrap_err();
+    /// assert!(error
+    ///     .render(&source_map)
+    ///     .starts_with("error: Mismatched types, expected `i32`, found `bool`\n --> main.out:1:14"));
+    /// ```
+    pub fn render(&self, source_map: &SourceMap) -> String {
+        let renderer = Renderer::new(source_map);
+        self.diagnostics()
+            .iter()
+            .map(|diagnostic| renderer.render(diagnostic))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn lines<T: fmt::Display>(f: &mut fmt::Formatter, errors: &[T]) -> fmt::Result {
+            let lines: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            write!(f, "{}", lines.join("\n"))
+        }
+        match self {
+            Error::Parse(errors) => lines(f, errors),
+            Error::Resolve(errors) => lines(f, errors),
+            Error::Type(errors) => lines(f, errors),
+            Error::Constant(errors) => lines(f, errors),
+            Error::Initialization(errors) => lines(f, errors),
+            Error::Runtime(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Runtime(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// A program that passed every static check
+#[derive(Debug, Clone)]
+pub struct CheckedProgram {
+    /// The syntax tree, with the types of `auto` variables and integer literals filled in and
+    /// its constant expressions folded
+    pub program: Program,
+    pub symbols: SymbolTable,
+    /// Warnings found while checking, which do not prevent running the program
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl CheckedProgram {
+    /// The program without its dead code, which the backends compile
+    fn optimized(&self) -> Program {
+        let mut program = self.program.clone();
+        optimizer::eliminate_dead_code(&mut program, &self.symbols);
+        program
+    }
+
+    /// Compiles the program to bytecode
+    pub fn compile(&self) -> bytecode::Module {
+        bytecode::compile(&self.optimized(), &self.symbols)
+    }
+
+    /// Lowers the program to a single C99 file
+    pub fn to_c(&self) -> String {
+        c::transpile(&self.optimized(), &self.symbols)
+    }
+
+    /// Lowers the program to the SSA intermediate representation
+    pub fn to_ir(&self) -> ir::Module {
+        ir::lower(&self.optimized(), &self.symbols)
+    }
+
+    /// Compiles the functions of the program to a WebAssembly module
+    pub fn to_wasm(&self) -> Result<wasm::Module, wasm::UnsupportedError> {
+        wasm::compile(&self.optimized(), &self.symbols)
+    }
+
+    /// Executes the program on the bytecode VM, returns the value of its top level `return`
+    /// if it reaches one
+    pub fn run(&self) -> Result<Option<Value>, Error> {
+        bytecode::Vm::new(&self.compile())
+            .run()
+            .map_err(Error::Runtime)
+    }
+
+    /// Executes the program with the tree-walking interpreter, the reference for the VM
+    pub fn interpret(&self) -> Result<Option<Value>, Error> {
+        Interpreter::new(&self.symbols)
+            .run(&self.program)
+            .map_err(Error::Runtime)
+    }
+}
+
+/// Parses a program
+///
+/// ```
+/// use outer_compiler::{Statement, Type};
+///
+/// let program = outer_compiler::parse("let answer: u64 = 42;")?;
+/// match &program.0[0].value {
+///     Statement::Definition(_, variable, _) => {
+///         assert_eq!(variable.value.id, "answer");
+///         assert_eq!(variable.value.ty, Some(Type::UInt(64)));
+///     }
+///     _ => unreachable!(),
+/// }
+/// # Ok::<(), outer_compiler::Error>(())
+/// ```
+pub fn parse(source: &str) -> Result<Program, Error> {
+    Parser::new(Lexer::new(source))
+        .parse_program()
+        .map_err(Error::Parse)
+}
+
+/// Parses a program and runs the static passes on it: name resolution, type checking, constant
+/// folding, definite assignment and the search for dead code
+///
+/// ```
+/// let checked = outer_compiler::check("auto a = 1; if true { auto a = 2; }")?;
+/// assert_eq!(checked.warnings[0].message, "`a` shadows a previous declaration");
+///
+/// let error = outer_compiler::check("auto a = b;").unwrap_err();
+/// assert_eq!(error.to_string(), "Undefined variable `b` at 1:10");
+/// # Ok::<(), outer_compiler::Error>(())
+/// ```
+pub fn check(source: &str) -> Result<CheckedProgram, Error> {
+    let mut program = parse(source)?;
+
+    let (symbols, resolve_errors) = Resolver::new().resolve_program(&program);
+    if resolve_errors.iter().any(|e| e.is_error()) {
+        return Err(Error::Resolve(resolve_errors));
+    }
+    Checker::new()
+        .check_program(&mut program)
+        .map_err(Error::Type)?;
+    optimizer::fold_program(&mut program).map_err(Error::Constant)?;
+    initialization::check_initialization(&program, &symbols).map_err(Error::Initialization)?;
+
+    let mut warnings: Vec<Diagnostic> = resolve_errors.iter().map(Diagnostic::from).collect();
+    warnings.extend(optimizer::dead_code_warnings(&program, &symbols));
+    Ok(CheckedProgram {
+        program,
+        symbols,
+        warnings,
+    })
+}
+
+/// Checks and executes a program, returns the value of its top level `return` if it reaches one
+///
+/// ```
+/// use outer_compiler::{Error, Value};
+///
+/// assert_eq!(outer_compiler::run("return 1 + 2;")?, Some(Value::Int32(3)));
+///
+/// let error = outer_compiler::run("let a: u32 = 0; a--;").unwrap_err();
+/// assert!(matches!(error, Error::Runtime(_)));
+/// assert_eq!(error.to_string(), "Overflow in `-` on `u32` at 1:17");
+/// # Ok::<(), outer_compiler::Error>(())
+/// ```
+pub fn run(source: &str) -> Result<Option<Value>, Error> {
+    check(source)?.run()
+}
@@ -0,0 +1,404 @@
+//! Dead code elimination: the statements after a `return`, `break` or `continue`, the branch of
+//! an `if` whose condition is constant, and the variables which are never used are removed
+//! before code generation. The same walk gives the warnings about them.
+
+use crate::ast::{
+    Assignee, BlockStatementNode, Expression, ExpressionNode, Literal, Program, Statement,
+    StatementNode, VariableNode,
+};
+use crate::checker::{is_exhaustive, loops_forever};
+use crate::diagnostics::Diagnostic;
+use crate::resolver::{SymbolId, SymbolTable};
+use std::collections::HashSet;
+
+/// Removes the dead code of a checked program, whose constants are folded
+pub fn eliminate_dead_code(program: &mut Program, table: &SymbolTable) {
+    let mut eliminator = Eliminator::new(program, table);
+    eliminator.statements(&mut program.0);
+}
+
+/// Warnings about the code `eliminate_dead_code` removes, and about the variables which are
+/// never read
+pub fn dead_code_warnings(program: &Program, table: &SymbolTable) -> Vec<Diagnostic> {
+    let mut program = program.clone();
+    let mut eliminator = Eliminator::new(&program, table);
+    eliminator.statements(&mut program.0);
+    eliminator.warnings
+}
+
+/// Whether the execution never continues after a statement
+fn diverges(statement: &StatementNode) -> bool {
+    let block_diverges = |block: &BlockStatementNode| block.value.0.iter().any(diverges);
+    match &statement.value {
+        Statement::Return(_) | Statement::Break | Statement::Continue => true,
+        Statement::Condition(_, consequence, Some(alternative)) => {
+            block_diverges(consequence) && block_diverges(alternative)
+        }
+        Statement::Switch(_, cases, default) if is_exhaustive(cases, default) => {
+            default.iter().all(block_diverges)
+                && cases.iter().all(|case| block_diverges(&case.value.body))
+        }
+        statement => loops_forever(statement),
+    }
+}
+
+/// Whether evaluating an expression can neither fail nor call a function, reading a variable
+/// fails if it is not initialized
+fn is_pure(expression: &Expression) -> bool {
+    match expression {
+        Expression::Literal(_) => true,
+        Expression::Eq(lhs, rhs)
+        | Expression::Neq(lhs, rhs)
+        | Expression::Lt(lhs, rhs)
+        | Expression::Le(lhs, rhs)
+        | Expression::Gt(lhs, rhs)
+        | Expression::Ge(lhs, rhs)
+        | Expression::And(lhs, rhs)
+        | Expression::Or(lhs, rhs) => is_pure(&lhs.value) && is_pure(&rhs.value),
+        Expression::Not(operand) => is_pure(&operand.value),
+        Expression::Ternary(condition, consequence, alternative) => {
+            is_pure(&condition.value) && is_pure(&consequence.value) && is_pure(&alternative.value)
+        }
+        _ => false,
+    }
+}
+
+/// The variables read and assigned anywhere in a program
+#[derive(Default)]
+struct Uses {
+    read: HashSet<SymbolId>,
+    assigned: HashSet<SymbolId>,
+}
+
+impl Uses {
+    fn statements(&mut self, table: &SymbolTable, statements: &[StatementNode]) {
+        for statement in statements {
+            self.statement(table, statement);
+        }
+    }
+
+    fn statement(&mut self, table: &SymbolTable, statement: &StatementNode) {
+        match &statement.value {
+            Statement::Definition(_, _, expression) | Statement::Return(expression) => {
+                self.expression(table, expression)
+            }
+            Statement::Assignment(assignee, expression) => {
+                match &assignee.value {
+                    Assignee::Identifier(_) => self.assigned.extend(table.symbol_id(assignee.span)),
+                }
+                self.expression(table, expression);
+            }
+            Statement::Condition(condition, consequence, alternative) => {
+                self.expression(table, condition);
+                self.statements(table, &consequence.value.0);
+                if let Some(alternative) = alternative {
+                    self.statements(table, &alternative.value.0);
+                }
+            }
+            Statement::While(condition, body) => {
+                self.expression(table, condition);
+                self.statements(table, &body.value.0);
+            }
+            Statement::For(init, condition, step, body) => {
+                for statement in init.iter().chain(step) {
+                    self.statement(table, statement);
+                }
+                self.expression(table, condition);
+                self.statements(table, &body.value.0);
+            }
+            Statement::Switch(scrutinee, cases, default) => {
+                self.expression(table, scrutinee);
+                for case in cases {
+                    self.expression(table, &case.value.value);
+                    self.statements(table, &case.value.body.value.0);
+                }
+                if let Some(default) = default {
+                    self.statements(table, &default.value.0);
+                }
+            }
+            Statement::FunctionCall(_, arguments) => {
+                for argument in arguments {
+                    self.expression(table, argument);
+                }
+            }
+            Statement::FunctionDefinition(function) => {
+                self.statements(table, &function.value.statements)
+            }
+            Statement::Declaration(_)
+            | Statement::TypeDefinition(..)
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Error => {}
+        }
+    }
+
+    fn expression(&mut self, table: &SymbolTable, expression: &ExpressionNode) {
+        match &expression.value {
+            Expression::Identifier(_) => self.read.extend(table.symbol_id(expression.span)),
+            Expression::Literal(_) => {}
+            Expression::Add(lhs, rhs)
+            | Expression::Sub(lhs, rhs)
+            | Expression::Mul(lhs, rhs)
+            | Expression::Div(lhs, rhs)
+            | Expression::Pow(lhs, rhs)
+            | Expression::Mod(lhs, rhs)
+            | Expression::Eq(lhs, rhs)
+            | Expression::Neq(lhs, rhs)
+            | Expression::Lt(lhs, rhs)
+            | Expression::Le(lhs, rhs)
+            | Expression::Ge(lhs, rhs)
+            | Expression::Gt(lhs, rhs)
+            | Expression::And(lhs, rhs)
+            | Expression::Or(lhs, rhs)
+            | Expression::BitAnd(lhs, rhs)
+            | Expression::BitOr(lhs, rhs)
+            | Expression::BitXor(lhs, rhs)
+            | Expression::Shl(lhs, rhs)
+            | Expression::Shr(lhs, rhs) => {
+                self.expression(table, lhs);
+                self.expression(table, rhs);
+            }
+            Expression::Not(operand) | Expression::Neg(operand) => self.expression(table, operand),
+            Expression::Ternary(condition, consequence, alternative) => {
+                self.expression(table, condition);
+                self.expression(table, consequence);
+                self.expression(table, alternative);
+            }
+            Expression::FunctionCall(_, arguments) => {
+                for argument in arguments {
+                    self.expression(table, argument);
+                }
+            }
+            Expression::FunctionDef(function) => self.statements(table, &function.value.statements),
+        }
+    }
+}
+
+struct Eliminator<'a> {
+    table: &'a SymbolTable,
+    uses: Uses,
+    warnings: Vec<Diagnostic>,
+}
+
+impl<'a> Eliminator<'a> {
+    fn new(program: &Program, table: &'a SymbolTable) -> Self {
+        let mut uses = Uses::default();
+        uses.statements(table, &program.0);
+        Self {
+            table,
+            uses,
+            warnings: vec![],
+        }
+    }
+
+    fn statements(&mut self, statements: &mut Vec<StatementNode>) {
+        let mut kept: Vec<StatementNode> = vec![];
+        let mut rest = std::mem::take(statements).into_iter();
+        while let Some(statement) = rest.next() {
+            if let Some(last) = kept.last().filter(|last| diverges(last)) {
+                let span = match rest.as_slice().last() {
+                    Some(end) => statement.span.to(end.span),
+                    None => statement.span,
+                };
+                self.warnings.push(
+                    Diagnostic::warning(String::from("Unreachable code"))
+                        .with_label(span, String::new())
+                        .with_secondary_label(
+                            last.span,
+                            String::from("any code after this is unreachable"),
+                        ),
+                );
+                break;
+            }
+            self.statement(statement, &mut kept);
+        }
+        *statements = kept;
+    }
+
+    fn block(&mut self, block: &mut BlockStatementNode) {
+        self.statements(&mut block.value.0);
+    }
+
+    /// Adds what is left of a statement to `kept`, the statements of the branch taken by an
+    /// `if` with a constant condition replace it
+    fn statement(&mut self, mut statement: StatementNode, kept: &mut Vec<StatementNode>) {
+        match &mut statement.value {
+            Statement::Condition(condition, consequence, alternative) => {
+                self.block(consequence);
+                if let Some(alternative) = alternative {
+                    self.block(alternative);
+                }
+                if let Expression::Literal(Literal::Boolean(value)) = condition.value {
+                    let (taken, dead) = match value {
+                        true => (Some(consequence), alternative.as_ref()),
+                        false => (alternative.as_mut(), Some(&*consequence)),
+                    };
+                    if let Some(dead) = dead.filter(|dead| !dead.value.0.is_empty()) {
+                        self.warnings.push(
+                            Diagnostic::warning(String::from("Unreachable code"))
+                                .with_label(dead.span, String::new())
+                                .with_secondary_label(
+                                    condition.span,
+                                    format!("this condition is always `{}`", value),
+                                ),
+                        );
+                    }
+                    if let Some(taken) = taken {
+                        kept.append(&mut taken.value.0);
+                    }
+                    return;
+                }
+            }
+            Statement::While(_, body) | Statement::For(_, _, _, body) => self.block(body),
+            Statement::Switch(_, cases, default) => {
+                for case in cases {
+                    self.block(&mut case.value.body);
+                }
+                if let Some(default) = default {
+                    self.block(default);
+                }
+            }
+            Statement::FunctionDefinition(function) => {
+                self.statements(&mut function.value.statements)
+            }
+            Statement::Definition(_, _, expression)
+                if matches!(expression.value, Expression::FunctionDef(_)) =>
+            {
+                if let Expression::FunctionDef(function) = &mut expression.value {
+                    self.statements(&mut function.value.statements);
+                }
+            }
+            // unused variables are removed if nothing is lost with them
+            Statement::Definition(_, variable, expression)
+                if self.unused(variable) && is_pure(&expression.value) =>
+            {
+                return
+            }
+            Statement::Declaration(variable) if self.unused(variable) => return,
+            _ => {}
+        }
+        kept.push(statement);
+    }
+
+    /// Warns about a variable which is never read, returns whether it is not assigned either,
+    /// then its declaration can be removed
+    fn unused(&mut self, variable: &VariableNode) -> bool {
+        let id = match self.table.symbol_id(variable.span) {
+            Some(id) => id,
+            None => return false,
+        };
+        if self.uses.read.contains(&id) {
+            return false;
+        }
+        let name = &variable.value.id;
+        if !name.starts_with('_') {
+            self.warnings.push(
+                Diagnostic::warning(format!("Unused variable `{}`", name))
+                    .with_label(variable.span, String::new())
+                    .with_note(format!("rename it `_{}` if this is on purpose", name)),
+            );
+        }
+        !self.uses.assigned.contains(&id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The warnings of a program, with the source they point at
+    fn warnings(code: &str) -> Vec<(String, String)> {
+        let checked = crate::check(code).unwrap();
+        checked
+            .warnings
+            .iter()
+            .map(|warning| {
+                let span = warning.primary.as_ref().unwrap().span;
+                (warning.message.clone(), code[span.lo..span.hi].to_string())
+            })
+            .collect()
+    }
+
+    fn eliminated(code: &str) -> Vec<Statement> {
+        let checked = crate::check(code).unwrap();
+        let mut program = checked.program;
+        eliminate_dead_code(&mut program, &checked.symbols);
+        program
+            .0
+            .into_iter()
+            .map(|statement| statement.value)
+            .collect()
+    }
+
+    fn without_dead_code(code: &str) -> Vec<Statement> {
+        crate::check(code)
+            .unwrap()
+            .program
+            .0
+            .into_iter()
+            .map(|statement| statement.value)
+            .collect()
+    }
+
+    #[test]
+    fn unreachable_code() {
+        let code = "func f(n: i32): i32 {\n  return n;\n  n = 2;\n  return n;\n}\nreturn f(1);";
+        assert_eq!(
+            warnings(code),
+            vec![(
+                String::from("Unreachable code"),
+                String::from("n = 2;\n  return n;")
+            )]
+        );
+        assert_eq!(
+            warnings("while true { if false { continue; } else { break; } }"),
+            vec![(
+                String::from("Unreachable code"),
+                String::from("{ continue; }")
+            )]
+        );
+        assert_eq!(
+            warnings(
+                "for let i: i32 = 0; i < 3; i++ { if i == 1 { break; } else { continue; } i--; }"
+            ),
+            vec![(String::from("Unreachable code"), String::from("i--;"))]
+        );
+        assert_eq!(
+            eliminated("auto a = 1; if 2 > 1 { a = 2; } else { a = 3; } return a; a = 4;"),
+            without_dead_code("auto a = 1; a = 2; return a;")
+        );
+        assert_eq!(
+            eliminated("func f() { if 1 > 2 { f(); } f(); }"),
+            without_dead_code("func f() { f(); }")
+        );
+    }
+
+    #[test]
+    fn unused_variables() {
+        assert_eq!(
+            warnings("let a: i32 = 1; auto b = 2; let c: i32; auto _d = 3; b = 4; return a;"),
+            vec![
+                (String::from("Unused variable `b`"), String::from("b")),
+                (String::from("Unused variable `c`"), String::from("c: i32")),
+            ]
+        );
+        // unused variables are removed when nothing is lost with them
+        assert_eq!(
+            eliminated(
+                "func f(): i32 { return 1; } auto a = 1 > 2; auto b = f(); auto c = 1; let d: i32; c = 2;"
+            ),
+            without_dead_code("func f(): i32 { return 1; } auto b = f(); auto c = 1; c = 2;")
+        );
+    }
+
+    /// Removing dead code does not change what programs do
+    #[test]
+    fn same_results() {
+        for program in crate::testing::PROGRAMS {
+            let mut checked = crate::testing::check(program);
+            let expected = crate::testing::interpret(&checked);
+            eliminate_dead_code(&mut checked.program, &checked.symbols);
+            assert_eq!(crate::testing::interpret(&checked), expected, "{}", program);
+        }
+    }
+}
@@ -0,0 +1,410 @@
+//! Constant folding: operators whose operands are literals are evaluated at compile time, with
+//! the fixed-width semantics of the interpreter, and replaced by the literal they give.
+//!
+//! An operation on literals which always fails, like a division by zero or an overflow, is an
+//! error wherever it is, even in code which may not run.
+
+use crate::ast::node::Node;
+use crate::ast::{
+    BlockStatementNode, Expression, ExpressionNode, Program, Statement, StatementNode,
+};
+use crate::diagnostics::Diagnostic;
+use crate::interpreter::value::{ArithmeticError, BinaryOperator, Value};
+use crate::position::{Position, Span};
+use core::fmt;
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone)]
+pub struct ConstantError {
+    error: ArithmeticError,
+    position: Position,
+    span: Span,
+}
+
+impl ConstantError {
+    pub fn new(error: ArithmeticError, position: Position, span: Span) -> Self {
+        Self {
+            error,
+            position,
+            span,
+        }
+    }
+    pub fn error(&self) -> &ArithmeticError {
+        &self.error
+    }
+    pub fn position(&self) -> Position {
+        self.position
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl fmt::Display for ConstantError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.error, self.position)
+    }
+}
+
+impl std::error::Error for ConstantError {}
+
+impl From<&ConstantError> for Diagnostic {
+    fn from(error: &ConstantError) -> Self {
+        Diagnostic::error(error.error.to_string())
+            .with_label(error.span, String::from("this expression always fails"))
+    }
+}
+
+/// Folds the constant expressions of a checked program, whose integer literals have the type
+/// of their context
+pub fn fold_program(program: &mut Program) -> Result<(), Vec<ConstantError>> {
+    let mut folder = Folder { errors: vec![] };
+    folder.statements(&mut program.0);
+    folder.finish()
+}
+
+/// Folds a single checked expression, such as the value of a `case`
+pub fn fold_expression(expression: &mut ExpressionNode) -> Result<(), Vec<ConstantError>> {
+    let mut folder = Folder { errors: vec![] };
+    folder.expression(expression);
+    folder.finish()
+}
+
+/// What an expression folds to
+enum Folded {
+    Value(Result<Value, ArithmeticError>),
+    /// one of its operands, the only one evaluated
+    Operand(Box<ExpressionNode>),
+}
+
+struct Folder {
+    errors: Vec<ConstantError>,
+}
+
+impl Folder {
+    fn finish(self) -> Result<(), Vec<ConstantError>> {
+        match self.errors.is_empty() {
+            true => Ok(()),
+            false => Err(self.errors),
+        }
+    }
+
+    fn statements(&mut self, statements: &mut [StatementNode]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn block(&mut self, block: &mut BlockStatementNode) {
+        self.statements(&mut block.value.0);
+    }
+
+    fn statement(&mut self, statement: &mut StatementNode) {
+        match &mut statement.value {
+            Statement::Definition(_, _, expression)
+            | Statement::Assignment(_, expression)
+            | Statement::Return(expression) => self.expression(expression),
+            Statement::Condition(condition, consequence, alternative) => {
+                self.expression(condition);
+                self.block(consequence);
+                if let Some(alternative) = alternative {
+                    self.block(alternative);
+                }
+            }
+            Statement::While(condition, body) => {
+                self.expression(condition);
+                self.block(body);
+            }
+            Statement::For(init, condition, step, body) => {
+                if let Some(init) = init {
+                    self.statement(init);
+                }
+                self.expression(condition);
+                if let Some(step) = step {
+                    self.statement(step);
+                }
+                self.block(body);
+            }
+            Statement::Switch(scrutinee, cases, default) => {
+                self.expression(scrutinee);
+                for case in cases {
+                    self.expression(&mut case.value.value);
+                    self.block(&mut case.value.body);
+                }
+                if let Some(default) = default {
+                    self.block(default);
+                }
+            }
+            Statement::FunctionCall(_, arguments) => {
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            Statement::FunctionDefinition(function) => {
+                self.statements(&mut function.value.statements)
+            }
+            Statement::Declaration(_)
+            | Statement::TypeDefinition(..)
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Error => {}
+        }
+    }
+
+    fn expression(&mut self, expression: &mut ExpressionNode) {
+        // the operands first, so that nested constant expressions fold from the bottom up
+        match &mut expression.value {
+            Expression::Identifier(_) | Expression::Literal(_) => return,
+            Expression::Not(operand) | Expression::Neg(operand) => self.expression(operand),
+            Expression::Ternary(condition, consequence, alternative) => {
+                self.expression(condition);
+                self.expression(consequence);
+                self.expression(alternative);
+            }
+            Expression::FunctionCall(_, arguments) => {
+                for argument in arguments {
+                    self.expression(argument);
+                }
+                return;
+            }
+            Expression::FunctionDef(function) => {
+                self.statements(&mut function.value.statements);
+                return;
+            }
+            Expression::Add(lhs, rhs)
+            | Expression::Sub(lhs, rhs)
+            | Expression::Mul(lhs, rhs)
+            | Expression::Div(lhs, rhs)
+            | Expression::Pow(lhs, rhs)
+            | Expression::Mod(lhs, rhs)
+            | Expression::Eq(lhs, rhs)
+            | Expression::Neq(lhs, rhs)
+            | Expression::Lt(lhs, rhs)
+            | Expression::Le(lhs, rhs)
+            | Expression::Ge(lhs, rhs)
+            | Expression::Gt(lhs, rhs)
+            | Expression::And(lhs, rhs)
+            | Expression::Or(lhs, rhs)
+            | Expression::BitAnd(lhs, rhs)
+            | Expression::BitOr(lhs, rhs)
+            | Expression::BitXor(lhs, rhs)
+            | Expression::Shl(lhs, rhs)
+            | Expression::Shr(lhs, rhs) => {
+                self.expression(lhs);
+                self.expression(rhs);
+            }
+        }
+
+        match fold(&expression.value) {
+            Some(Folded::Value(Ok(value))) => {
+                expression.value = Expression::Literal(value.into_literal())
+            }
+            Some(Folded::Value(Err(error))) => {
+                self.errors
+                    .push(ConstantError::new(error, expression.start, expression.span));
+            }
+            // the operand keeps its own position, names are resolved by their span
+            Some(Folded::Operand(operand)) => *expression = *operand,
+            None => {}
+        }
+    }
+}
+
+fn literal(expression: &Node<Expression>) -> Option<Value> {
+    match &expression.value {
+        Expression::Literal(literal) => Value::from_literal(literal),
+        _ => None,
+    }
+}
+
+/// Folds an expression whose operands are folded already, `None` if they are not all literals
+fn fold(expression: &Expression) -> Option<Folded> {
+    let folded = match expression {
+        // `&&` and `||` only need their left operand to be constant
+        Expression::And(lhs, rhs) | Expression::Or(lhs, rhs) => {
+            let is_and = matches!(expression, Expression::And(..));
+            match literal(lhs)?.as_bool()? == is_and {
+                true => Folded::Operand(rhs.clone()),
+                false => Folded::Value(Ok(Value::Boolean(!is_and))),
+            }
+        }
+        Expression::Ternary(condition, consequence, alternative) => {
+            match literal(condition)?.as_bool()? {
+                true => Folded::Operand(consequence.clone()),
+                false => Folded::Operand(alternative.clone()),
+            }
+        }
+        Expression::Not(operand) => {
+            Folded::Value(Ok(Value::Boolean(!literal(operand)?.as_bool()?)))
+        }
+        Expression::Neg(operand) => Folded::Value(literal(operand)?.neg()),
+        Expression::Eq(lhs, rhs) => {
+            Folded::Value(Ok(Value::Boolean(literal(lhs)? == literal(rhs)?)))
+        }
+        Expression::Neq(lhs, rhs) => {
+            Folded::Value(Ok(Value::Boolean(literal(lhs)? != literal(rhs)?)))
+        }
+        Expression::Lt(lhs, rhs)
+        | Expression::Le(lhs, rhs)
+        | Expression::Gt(lhs, rhs)
+        | Expression::Ge(lhs, rhs) => {
+            let ordering = literal(lhs)?.partial_cmp(&literal(rhs)?)?;
+            let result = match expression {
+                Expression::Lt(..) => ordering == Ordering::Less,
+                Expression::Le(..) => ordering != Ordering::Greater,
+                Expression::Gt(..) => ordering == Ordering::Greater,
+                _ => ordering != Ordering::Less,
+            };
+            Folded::Value(Ok(Value::Boolean(result)))
+        }
+        Expression::Add(lhs, rhs)
+        | Expression::Sub(lhs, rhs)
+        | Expression::Mul(lhs, rhs)
+        | Expression::Div(lhs, rhs)
+        | Expression::Pow(lhs, rhs)
+        | Expression::Mod(lhs, rhs)
+        | Expression::BitAnd(lhs, rhs)
+        | Expression::BitOr(lhs, rhs)
+        | Expression::BitXor(lhs, rhs)
+        | Expression::Shl(lhs, rhs)
+        | Expression::Shr(lhs, rhs) => {
+            let operator = binary_operator(expression)?;
+            Folded::Value(Value::binary(operator, &literal(lhs)?, &literal(rhs)?))
+        }
+        Expression::Identifier(_)
+        | Expression::Literal(_)
+        | Expression::FunctionCall(..)
+        | Expression::FunctionDef(_) => return None,
+    };
+    Some(folded)
+}
+
+fn binary_operator(expression: &Expression) -> Option<BinaryOperator> {
+    let operator = match expression {
+        Expression::Add(..) => BinaryOperator::Add,
+        Expression::Sub(..) => BinaryOperator::Sub,
+        Expression::Mul(..) => BinaryOperator::Mul,
+        Expression::Div(..) => BinaryOperator::Div,
+        Expression::Pow(..) => BinaryOperator::Pow,
+        Expression::Mod(..) => BinaryOperator::Mod,
+        Expression::BitAnd(..) => BinaryOperator::BitAnd,
+        Expression::BitOr(..) => BinaryOperator::BitOr,
+        Expression::BitXor(..) => BinaryOperator::BitXor,
+        Expression::Shl(..) => BinaryOperator::Shl,
+        Expression::Shr(..) => BinaryOperator::Shr,
+        _ => return None,
+    };
+    Some(operator)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{Expression, Literal, Statement};
+
+    /// The expression of each top level definition and `return` of a checked program
+    fn folded(code: &str) -> Vec<Expression> {
+        let checked = crate::check(code).unwrap();
+        checked
+            .program
+            .0
+            .into_iter()
+            .filter_map(|statement| match statement.value {
+                Statement::Definition(_, _, expression) | Statement::Return(expression) => {
+                    Some(expression.value)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn errors(code: &str) -> Vec<String> {
+        match crate::check(code) {
+            Ok(_) => vec![],
+            Err(error) => error.to_string().lines().map(String::from).collect(),
+        }
+    }
+
+    #[test]
+    fn fold_constants() {
+        let literal = |literal| Expression::Literal(literal);
+        assert_eq!(
+            folded(
+                r#"
+                let mask: u64 = 1 << 32 - 1;
+                auto big = 2 ** 30 - 1 > 1000 * 1000;
+                auto name = "out" + "er";
+                let small: i64 = -4611686018427387904 * 2;
+                auto flag = !(1 != 1) && ((7 & 3 | 8) ^ 1) == (10 % 3 + 9 >> 1);
+                "#
+            ),
+            vec![
+                literal(Literal::UInt64(1 << 31)),
+                literal(Literal::Boolean(true)),
+                literal(Literal::String(String::from("outer"))),
+                literal(Literal::Int64(i64::MIN)),
+                literal(Literal::Boolean(false)),
+            ]
+        );
+
+        // the operands which are not literals are kept
+        let expressions = folded("auto x = 1; auto a = true && x > 2 * 3; return false ? 1 : x;");
+        assert!(matches!(&expressions[1], Expression::Gt(_, rhs)
+            if rhs.value == literal(Literal::Int32(6))));
+        assert_eq!(expressions[2], Expression::Identifier(String::from("x")));
+        assert!(matches!(
+            folded("func f(): bool { return true; } auto a = f() || 1 > 2;")[0],
+            Expression::Or(..)
+        ));
+        assert_eq!(
+            folded("func f(): bool { return true; } auto a = false && f();")[0],
+            literal(Literal::Boolean(false))
+        );
+    }
+
+    #[test]
+    fn constant_errors() {
+        assert_eq!(
+            errors("return 1 / 0;"),
+            vec!["Attempt to divide by zero at 1:8"]
+        );
+        assert_eq!(
+            errors("let x: i32 = 2147483647 + 1;"),
+            vec!["Overflow in `+` on `i32` at 1:14"]
+        );
+        assert_eq!(
+            errors("let x: u32 = 1 << 32; let y: u64 = 0 - 1;"),
+            vec![
+                "Overflow in `<<` on `u32` at 1:14",
+                "Overflow in `-` on `u64` at 1:36"
+            ]
+        );
+        // a shift in range still overflows when it loses bits
+        assert_eq!(
+            errors("let a: u32 = 4294967295 << 4; let b: i32 = 3 << 30;"),
+            vec![
+                "Overflow in `<<` on `u32` at 1:14",
+                "Overflow in `<<` on `i32` at 1:44"
+            ]
+        );
+        assert!(errors("let a: i32 = -3 << 29;").is_empty());
+        assert_eq!(
+            errors("auto x = -(-2147483647 - 1);"),
+            vec!["Overflow in `-` on `i32` at 1:10"]
+        );
+        assert_eq!(
+            errors("return 2 ** -1;"),
+            vec!["Negative exponent in `**` at 1:8"]
+        );
+        // only the failing operation is reported, not the expressions containing it
+        assert_eq!(
+            errors("func f() { if 5 % 0 + 1 > 0 { f(); } }"),
+            vec!["Attempt to divide by zero at 1:15"]
+        );
+        // even where it cannot run
+        assert_eq!(
+            errors("auto a = false && 1 / 0 == 0;"),
+            vec!["Attempt to divide by zero at 1:19"]
+        );
+        assert!(errors("auto zero = 0; return 1 / zero;").is_empty());
+    }
+}
@@ -0,0 +1,8 @@
+//! Passes rewriting a checked program into a simpler one which behaves the same, they run on
+//! the syntax tree so that every backend benefits from them.
+
+pub mod dead_code;
+pub mod fold;
+
+pub use dead_code::{dead_code_warnings, eliminate_dead_code};
+pub use fold::{fold_expression, fold_program, ConstantError};
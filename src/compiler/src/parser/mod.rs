@@ -1,18 +1,96 @@
+use crate::ast::types::{Type, TypeNode};
 use crate::ast::{
-    BlockStatement, BlockStatementNode, DefinitionType, ExpressionNode, Program, Statement,
-    StatementNode, Variable, VariableNode,
+    Assignee, AssigneeNode, BlockStatement, BlockStatementNode, DefinitionType, Expression,
+    ExpressionNode, Function, FunctionNode, Identifier, Literal, Program, Statement, StatementNode,
+    SwitchCase, SwitchCaseNode, Variable, VariableNode,
 };
-use crate::lexer::tokens::{Token, TokenType};
+use crate::diagnostics::Diagnostic;
+use crate::lexer::tokens::{LexError, Token, TokenType};
 use crate::lexer::Lexer;
-use crate::position::Position;
+use crate::position::{Position, Span};
+use core::fmt;
+use std::collections::HashMap;
 use std::convert::TryInto;
-use std::iter::Peekable;
+
+/// Binding power of prefix operators (`!`, `-`), tighter than `*` but looser than `**`
+const PREFIX_BINDING_POWER: u8 = 23;
+
+/// Binding powers of the ternary operator, right associative
+const TERNARY_BINDING_POWER: (u8, u8) = (2, 1);
+
+/// Returns the left and right binding power of a binary operator.
+/// Left associative operators bind tighter on the right, right associative ones on the left.
+fn infix_binding_power(ty: &TokenType) -> Option<(u8, u8)> {
+    let bp = match ty {
+        TokenType::Or => (3, 4),
+        TokenType::And => (5, 6),
+        TokenType::BitOr => (7, 8),
+        TokenType::BitXor => (9, 10),
+        TokenType::BitAnd => (11, 12),
+        TokenType::Eq | TokenType::Neq => (13, 14),
+        TokenType::Lt | TokenType::Lte | TokenType::Gt | TokenType::Gte => (15, 16),
+        TokenType::BitLeftShift | TokenType::BitRightShift => (17, 18),
+        TokenType::Plus | TokenType::Minus => (19, 20),
+        TokenType::Asterisk | TokenType::Slash | TokenType::Percent => (21, 22),
+        TokenType::Power => (24, 23),
+        _ => return None,
+    };
+    Some(bp)
+}
+
+/// Maps a compound assignment operator such as `+=` to its binary operator
+fn compound_assignment_operator(ty: &TokenType) -> Option<TokenType> {
+    let operator = match ty {
+        TokenType::PlusAssign => TokenType::Plus,
+        TokenType::MinusAssign => TokenType::Minus,
+        TokenType::AsteriskAssign => TokenType::Asterisk,
+        TokenType::SlashAssign => TokenType::Slash,
+        TokenType::PercentAssign => TokenType::Percent,
+        TokenType::PowerAssign => TokenType::Power,
+        TokenType::BitAndAssign => TokenType::BitAnd,
+        TokenType::BitOrAssign => TokenType::BitOr,
+        TokenType::BitXorAssign => TokenType::BitXor,
+        TokenType::BitLeftShiftAssign => TokenType::BitLeftShift,
+        TokenType::BitRightShiftAssign => TokenType::BitRightShift,
+        _ => return None,
+    };
+    Some(operator)
+}
+
+fn binary_expression(ty: &TokenType, lhs: ExpressionNode, rhs: ExpressionNode) -> Expression {
+    let (lhs, rhs) = (Box::new(lhs), Box::new(rhs));
+    match ty {
+        TokenType::Or => Expression::Or(lhs, rhs),
+        TokenType::And => Expression::And(lhs, rhs),
+        TokenType::BitOr => Expression::BitOr(lhs, rhs),
+        TokenType::BitXor => Expression::BitXor(lhs, rhs),
+        TokenType::BitAnd => Expression::BitAnd(lhs, rhs),
+        TokenType::Eq => Expression::Eq(lhs, rhs),
+        TokenType::Neq => Expression::Neq(lhs, rhs),
+        TokenType::Lt => Expression::Lt(lhs, rhs),
+        TokenType::Lte => Expression::Le(lhs, rhs),
+        TokenType::Gt => Expression::Gt(lhs, rhs),
+        TokenType::Gte => Expression::Ge(lhs, rhs),
+        TokenType::BitLeftShift => Expression::Shl(lhs, rhs),
+        TokenType::BitRightShift => Expression::Shr(lhs, rhs),
+        TokenType::Plus => Expression::Add(lhs, rhs),
+        TokenType::Minus => Expression::Sub(lhs, rhs),
+        TokenType::Asterisk => Expression::Mul(lhs, rhs),
+        TokenType::Slash => Expression::Div(lhs, rhs),
+        TokenType::Percent => Expression::Mod(lhs, rhs),
+        TokenType::Power => Expression::Pow(lhs, rhs),
+        _ => unreachable!("{:?} is not a binary operator", ty),
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum ParserErrorKind {
     UnexpectedToken,
     UnexpectedEOF,
     InvalidType,
+    InvalidLiteral,
+    InvalidStatement,
+    InvalidToken,
 }
 
 #[derive(Debug, Clone)]
@@ -20,6 +98,7 @@ pub struct ParserError {
     kind: ParserErrorKind,
     message: String,
     position: Option<Position>,
+    span: Option<Span>,
 }
 
 impl ParserError {
@@ -28,37 +107,200 @@ impl ParserError {
             kind,
             message,
             position: None,
+            span: None,
         }
     }
     pub fn set_position(mut self, position: Position) -> Self {
         self.position = Some(position);
         self
     }
+    pub fn set_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
     pub fn eof() -> Self {
         ParserError::new(
             ParserErrorKind::UnexpectedEOF,
-            format!("Unexpected end of file (EOF)"),
+            String::from("Unexpected end of file (EOF)"),
         )
     }
+    pub fn unexpected(token: &Token) -> Self {
+        ParserError::new(
+            ParserErrorKind::UnexpectedToken,
+            format!("Unexpected token {:?} `{}`", token.ty, token.value),
+        )
+        .set_position(token.position)
+        .set_span(token.span)
+    }
+    pub fn kind(&self) -> &ParserErrorKind {
+        &self.kind
+    }
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+    pub fn position(&self) -> Option<Position> {
+        self.position
+    }
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+}
+
+impl fmt::Display for ParserError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.position {
+            Some(position) => write!(f, "{} at {}", self.message, position),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ParserError {}
+
+impl From<&ParserError> for Diagnostic {
+    fn from(error: &ParserError) -> Self {
+        let diagnostic = Diagnostic::error(error.message.clone());
+        match error.span {
+            Some(span) => diagnostic.with_label(span, String::new()),
+            None => diagnostic,
+        }
+    }
 }
 
 pub struct Parser<'ast> {
-    lexer: Peekable<Lexer<'ast>>,
+    lexer: Lexer<'ast>,
+    lookahead: Option<Token>,
+    loop_depth: usize,
+    type_scopes: Vec<HashMap<Identifier, Type>>,
+    errors: Vec<ParserError>,
+    previous: Option<TokenType>,
+    /// end of the span of the last consumed token
+    previous_hi: usize,
 }
 
 impl<'ast> Parser<'ast> {
     pub fn new(lexer: Lexer<'ast>) -> Self {
         Self {
-            lexer: lexer.into_iter().peekable(),
+            lexer,
+            lookahead: None,
+            loop_depth: 0,
+            type_scopes: vec![HashMap::new()],
+            errors: vec![],
+            previous: None,
+            previous_hi: 0,
+        }
+    }
+
+    /// Parses the whole program, failing with every error found if any
+    pub fn parse_program(&mut self) -> Result<Program, Vec<ParserError>> {
+        let (program, errors) = self.parse_partial_program();
+        if errors.is_empty() {
+            Ok(program)
+        } else {
+            Err(errors)
         }
     }
 
-    pub fn parse_program(&mut self) -> Result<Program, ParserError> {
+    /// Parses the whole program, recovering from errors. Statements that failed to parse
+    /// are replaced by `Statement::Error` placeholders in the returned program.
+    pub fn parse_partial_program(&mut self) -> (Program, Vec<ParserError>) {
         let mut statements: Vec<StatementNode> = vec![];
-        while let Some(s) = self.consume_statement()? {
+        while let Some(s) = self.consume_statement_or_recover() {
             statements.push(s);
         }
-        Ok(Program(statements))
+        let mut errors = std::mem::take(&mut self.errors);
+        errors.sort_by_key(|e| e.position.map_or((usize::MAX, 0), |p| (p.line, p.col)));
+        (Program(statements), errors)
+    }
+
+    /// Parses a single expression spanning the whole input
+    pub fn parse_expression(&mut self) -> Result<ExpressionNode, Vec<ParserError>> {
+        let result = self
+            .consume_expression()
+            .and_then(|expression| match self.peek_unchecked() {
+                Some(token) => Err(ParserError::unexpected(&token.clone())),
+                None => Ok(expression),
+            });
+        let mut errors = std::mem::take(&mut self.errors);
+        match result {
+            Ok(expression) if errors.is_empty() => Ok(expression),
+            Ok(_) => Err(errors),
+            Err(error) => {
+                errors.push(error);
+                Err(errors)
+            }
+        }
+    }
+
+    fn report(&mut self, error: ParserError) {
+        self.errors.push(error);
+    }
+
+    /// Consumes a statement, on error the error is reported and the parser skips ahead
+    /// to the next statement boundary (panic mode recovery)
+    fn consume_statement_or_recover(&mut self) -> Option<StatementNode> {
+        let start = self.peek_unchecked()?.position;
+        match self.consume_statement() {
+            Ok(statement) => statement,
+            Err(error) => {
+                self.report(error);
+                let end = self.synchronize().unwrap_or(start);
+                Some(
+                    StatementNode::from(Statement::Error)
+                        .set_start(start)
+                        .set_end(end)
+                        .set_span(self.span_from(start)),
+                )
+            }
+        }
+    }
+
+    /// Skips tokens up to and including the next `;` or the `}` closing a block opened
+    /// while skipping, or up to the `}` of the enclosing block or the next keyword starting
+    /// a statement. Returns the position of the last skipped token.
+    fn synchronize(&mut self) -> Option<Position> {
+        // the offending token may have opened a block already
+        let mut depth = match self.previous {
+            Some(TokenType::LBrace) => 1usize,
+            _ => 0,
+        };
+        let mut end = None;
+        while let Some(token) = self.peek_unchecked() {
+            match token.ty {
+                TokenType::RBrace if depth == 0 => break,
+                TokenType::Let
+                | TokenType::Auto
+                | TokenType::Typedef
+                | TokenType::Function
+                | TokenType::Return
+                | TokenType::If
+                | TokenType::For
+                | TokenType::While
+                | TokenType::Switch
+                | TokenType::Break
+                | TokenType::Continue
+                    if depth == 0 =>
+                {
+                    break
+                }
+                _ => {}
+            }
+
+            let token = self.consume_unchecked().ok()?;
+            end = Some(token.position);
+            match token.ty {
+                TokenType::Semicolon if depth == 0 => break,
+                TokenType::LBrace => depth += 1,
+                TokenType::RBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        end
     }
 
     fn consume_statement(&mut self) -> Result<Option<StatementNode>, ParserError> {
@@ -67,41 +309,22 @@ impl<'ast> Parser<'ast> {
         }
 
         let token = self.consume_unchecked()?;
-        let start = token.position.clone();
+        let start = token.position;
 
         let statement = match token.ty {
-            TokenType::Let => {
-                let var = self.consume_variable_definition()?;
-                let statement = if self.peek(TokenType::Assign) {
-                    self.consume(TokenType::Assign)?;
-                    Statement::Definition(DefinitionType::Let, var, self.consume_expression()?)
-                } else {
-                    Statement::Declaration(var)
-                };
-                let semicolon = self.consume(TokenType::Semicolon)?;
-                Ok(StatementNode::from(statement)
-                    .set_start(start)
-                    .set_end(semicolon.position))
-            }
-            TokenType::Auto => {
-                let identifier = self.consume(TokenType::Identifier)?;
-                self.consume(TokenType::Assign)?;
-                let statement = Statement::Definition(
-                    DefinitionType::Auto,
-                    VariableNode::from(Variable::new(identifier.value, None)),
-                    self.consume_expression()?,
-                );
+            TokenType::Let | TokenType::Auto | TokenType::Identifier => {
+                let statement = self.consume_simple_statement(token)?;
                 let semicolon = self.consume(TokenType::Semicolon)?;
-                Ok(StatementNode::from(statement)
-                    .set_start(start)
-                    .set_end(semicolon.position))
+                let span = statement.span.to(semicolon.span);
+                Ok(statement.set_end(semicolon.position).set_span(span))
             }
             TokenType::Return => {
                 let expr = self.consume_expression()?;
                 let semicolon = self.consume(TokenType::Semicolon)?;
                 Ok(StatementNode::from(Statement::Return(expr))
                     .set_start(start)
-                    .set_end(semicolon.position))
+                    .set_end(semicolon.position)
+                    .set_span(self.span_from(start)))
             }
             TokenType::If => {
                 let condition = self.consume_expression()?;
@@ -113,62 +336,652 @@ impl<'ast> Parser<'ast> {
                         Statement::Condition(condition, consequence, Some(alternative.clone()));
                     Ok(StatementNode::from(statement)
                         .set_start(start)
-                        .set_end(alternative.end))
+                        .set_end(alternative.end)
+                        .set_span(self.span_from(start)))
                 } else {
                     let statement = Statement::Condition(condition, consequence.clone(), None);
                     Ok(StatementNode::from(statement)
                         .set_start(start)
-                        .set_end(consequence.end))
+                        .set_end(consequence.end)
+                        .set_span(self.span_from(start)))
+                }
+            }
+            TokenType::While => {
+                let condition = self.consume_expression()?;
+                let body = self.consume_loop_body()?;
+                let end = body.end;
+                Ok(StatementNode::from(Statement::While(condition, body))
+                    .set_start(start)
+                    .set_end(end)
+                    .set_span(self.span_from(start)))
+            }
+            TokenType::For => {
+                let init = if self.peek(TokenType::Semicolon) {
+                    None
+                } else {
+                    let token = self.consume_unchecked()?;
+                    Some(Box::new(self.consume_simple_statement(token)?))
+                };
+                self.consume(TokenType::Semicolon)?;
+                let condition = self.consume_expression()?;
+                self.consume(TokenType::Semicolon)?;
+                let step = if self.peek(TokenType::LBrace) {
+                    None
+                } else {
+                    let token = self.consume_unchecked()?;
+                    Some(Box::new(self.consume_simple_statement(token)?))
+                };
+                let body = self.consume_loop_body()?;
+                let end = body.end;
+                Ok(
+                    StatementNode::from(Statement::For(init, condition, step, body))
+                        .set_start(start)
+                        .set_end(end)
+                        .set_span(self.span_from(start)),
+                )
+            }
+            TokenType::Switch => self.consume_switch(start),
+            TokenType::Typedef => {
+                // typedef Name = type;
+                let identifier = self.consume(TokenType::Identifier)?;
+                self.consume(TokenType::Assign)?;
+                let type_node = self.consume_type()?;
+                let semicolon = self.consume(TokenType::Semicolon)?;
+
+                let scope = self.type_scopes.last_mut().unwrap();
+                if scope.contains_key(&identifier.value) {
+                    self.report(
+                        ParserError::new(
+                            ParserErrorKind::InvalidType,
+                            format!("Type `{}` is already defined", identifier.value),
+                        )
+                        .set_position(identifier.position)
+                        .set_span(identifier.span),
+                    );
+                } else {
+                    scope.insert(identifier.value.clone(), type_node.value.clone());
+                }
+
+                Ok(
+                    StatementNode::from(Statement::TypeDefinition(identifier.value, type_node))
+                        .set_start(start)
+                        .set_end(semicolon.position)
+                        .set_span(self.span_from(start)),
+                )
+            }
+            TokenType::Break | TokenType::Continue => {
+                if self.loop_depth == 0 {
+                    self.report(
+                        ParserError::new(
+                            ParserErrorKind::InvalidStatement,
+                            format!("`{}` outside of a loop", token.value),
+                        )
+                        .set_position(start)
+                        .set_span(token.span),
+                    );
                 }
+                let semicolon = self.consume(TokenType::Semicolon)?;
+                let statement = match token.ty {
+                    TokenType::Break => Statement::Break,
+                    _ => Statement::Continue,
+                };
+                Ok(StatementNode::from(statement)
+                    .set_start(start)
+                    .set_end(semicolon.position)
+                    .set_span(self.span_from(start)))
+            }
+            TokenType::Function => {
+                let identifier = self.consume(TokenType::Identifier)?;
+                let function = self.consume_function(start, identifier.value)?;
+                let end = function.end;
+                Ok(StatementNode::from(Statement::FunctionDefinition(function))
+                    .set_start(start)
+                    .set_end(end)
+                    .set_span(self.span_from(start)))
             }
-            _ => Err(ParserError::eof()),
+            _ => Err(ParserError::unexpected(&token)),
         }?;
         Ok(Some(statement))
     }
 
+    /// Consumes a statement that is not terminated by a semicolon, as found in `for` headers
+    fn consume_simple_statement(&mut self, token: Token) -> Result<StatementNode, ParserError> {
+        let start = token.position;
+        match token.ty {
+            TokenType::Let => {
+                let var = self.consume_variable_definition()?;
+                if self.peek(TokenType::Assign) {
+                    self.consume(TokenType::Assign)?;
+                    let expr = self.consume_expression()?;
+                    let end = expr.end;
+                    Ok(
+                        StatementNode::from(Statement::Definition(DefinitionType::Let, var, expr))
+                            .set_start(start)
+                            .set_end(end)
+                            .set_span(self.span_from(start)),
+                    )
+                } else {
+                    let end = var.end;
+                    Ok(StatementNode::from(Statement::Declaration(var))
+                        .set_start(start)
+                        .set_end(end)
+                        .set_span(self.span_from(start)))
+                }
+            }
+            TokenType::Auto => {
+                let identifier = self.consume(TokenType::Identifier)?;
+                self.consume(TokenType::Assign)?;
+                let expr = self.consume_expression()?;
+                let end = expr.end;
+                let statement = Statement::Definition(
+                    DefinitionType::Auto,
+                    VariableNode::from(Variable::new(identifier.value, None))
+                        .set_start(identifier.position)
+                        .set_end(identifier.position)
+                        .set_span(identifier.span),
+                    expr,
+                );
+                Ok(StatementNode::from(statement)
+                    .set_start(start)
+                    .set_end(end)
+                    .set_span(self.span_from(start)))
+            }
+            TokenType::Identifier if self.peek(TokenType::LParen) => {
+                self.consume(TokenType::LParen)?;
+                let (arguments, right_paren) = self.consume_expression_list(TokenType::RParen)?;
+                Ok(
+                    StatementNode::from(Statement::FunctionCall(token.value, arguments))
+                        .set_start(start)
+                        .set_end(right_paren.position)
+                        .set_span(self.span_from(start)),
+                )
+            }
+            TokenType::Identifier => {
+                let assignee = AssigneeNode::from(Assignee::Identifier(token.value.clone()))
+                    .set_start(start)
+                    .set_end(start)
+                    .set_span(self.span_from(start));
+                let operator = self.consume_unchecked()?;
+                let expr = self.consume_assigned_expression(token, operator)?;
+                let end = expr.end;
+                Ok(StatementNode::from(Statement::Assignment(assignee, expr))
+                    .set_start(start)
+                    .set_end(end)
+                    .set_span(self.span_from(start)))
+            }
+            _ => Err(ParserError::unexpected(&token)),
+        }
+    }
+
+    /// Consumes the right hand side of an assignment to `target`.
+    /// Compound assignments and `++`/`--` are desugared, `x += e` becomes `x = x + e`
+    fn consume_assigned_expression(
+        &mut self,
+        target: Token,
+        operator: Token,
+    ) -> Result<ExpressionNode, ParserError> {
+        let (binary_operator, rhs) = match operator.ty {
+            TokenType::Assign => return self.consume_expression(),
+            TokenType::Increment | TokenType::Decrement => {
+                let one = ExpressionNode::from(Expression::Literal(Literal::Int32(1)))
+                    .set_start(operator.position)
+                    .set_end(operator.position)
+                    .set_span(self.span_from(operator.position));
+                let binary_operator = match operator.ty {
+                    TokenType::Increment => TokenType::Plus,
+                    _ => TokenType::Minus,
+                };
+                (binary_operator, one)
+            }
+            ref ty => match compound_assignment_operator(ty) {
+                Some(binary_operator) => (binary_operator, self.consume_expression()?),
+                None => return Err(ParserError::unexpected(&operator)),
+            },
+        };
+
+        let start = target.position;
+        let end = rhs.end;
+        let lhs = ExpressionNode::from(Expression::Identifier(target.value))
+            .set_start(start)
+            .set_end(start)
+            .set_span(target.span);
+        Ok(
+            ExpressionNode::from(binary_expression(&binary_operator, lhs, rhs))
+                .set_start(start)
+                .set_end(end)
+                .set_span(self.span_from(start)),
+        )
+    }
+
+    fn consume_switch(&mut self, start: Position) -> Result<StatementNode, ParserError> {
+        let scrutinee = self.consume_expression()?;
+        self.consume(TokenType::LBrace)?;
+
+        let mut cases: Vec<SwitchCaseNode> = vec![];
+        let mut default: Option<BlockStatementNode> = None;
+        while !self.peek(TokenType::RBrace) {
+            let token = self.consume_unchecked()?;
+            match token.ty {
+                TokenType::Case => {
+                    let value = self.consume_expression()?;
+                    let body = self.consume_block()?;
+                    let end = body.end;
+                    cases.push(
+                        SwitchCaseNode::from(SwitchCase::new(value, body))
+                            .set_start(token.position)
+                            .set_end(end)
+                            .set_span(self.span_from(token.position)),
+                    );
+                }
+                TokenType::Default => {
+                    let block = self.consume_block()?;
+                    if default.is_some() {
+                        self.report(
+                            ParserError::new(
+                                ParserErrorKind::InvalidStatement,
+                                String::from("Multiple `default` arms in switch"),
+                            )
+                            .set_position(token.position)
+                            .set_span(token.span),
+                        );
+                    } else {
+                        default = Some(block);
+                    }
+                }
+                _ => return Err(ParserError::unexpected(&token)),
+            }
+        }
+        let right_brace = self.consume(TokenType::RBrace)?;
+
+        Ok(
+            StatementNode::from(Statement::Switch(scrutinee, cases, default))
+                .set_start(start)
+                .set_end(right_brace.position)
+                .set_span(self.span_from(start)),
+        )
+    }
+
+    fn consume_loop_body(&mut self) -> Result<BlockStatementNode, ParserError> {
+        self.loop_depth += 1;
+        let body = self.consume_block();
+        self.loop_depth -= 1;
+        body
+    }
+
+    /// Consumes the parameters, optional return type and body of a function whose `func` keyword
+    /// (and name, if any) have already been consumed
+    fn consume_function(
+        &mut self,
+        start: Position,
+        id: Identifier,
+    ) -> Result<FunctionNode, ParserError> {
+        self.consume(TokenType::LParen)?;
+        let mut parameters = vec![];
+        if !self.peek(TokenType::RParen) {
+            parameters.push(self.consume_variable_definition()?);
+            while self.peek(TokenType::Comma) {
+                self.consume(TokenType::Comma)?;
+                parameters.push(self.consume_variable_definition()?);
+            }
+        }
+        self.consume(TokenType::RParen)?;
+
+        let return_type = if self.peek(TokenType::Colon) {
+            self.consume(TokenType::Colon)?;
+            Some(self.consume_type()?.value)
+        } else {
+            None
+        };
+
+        // `break` and `continue` cannot cross a function boundary
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        let body = self.consume_block();
+        self.loop_depth = loop_depth;
+        let body = body?;
+
+        let function = Function::new(id, parameters, body.value.0, return_type);
+        Ok(FunctionNode::from(function)
+            .set_start(start)
+            .set_end(body.end)
+            .set_span(self.span_from(start)))
+    }
+
     fn consume_variable_definition(&mut self) -> Result<VariableNode, ParserError> {
         let identifier = self.consume(TokenType::Identifier)?;
         self.consume(TokenType::Colon)?;
-        let type_token = self.consume(TokenType::VarType)?;
-        let var_type = type_token.clone().value.try_into().map_err(|e| {
-            ParserError::new(ParserErrorKind::InvalidType, format!("{}", e))
-                .set_position(type_token.position)
-        })?;
-        Ok(VariableNode::from(Variable::new(
-            identifier.value,
-            Some(var_type),
-        )))
+        let type_node = self.consume_type()?;
+        Ok(
+            VariableNode::from(Variable::new(identifier.value, Some(type_node.value)))
+                .set_start(identifier.position)
+                .set_end(type_node.end)
+                .set_span(self.span_from(identifier.position)),
+        )
+    }
+
+    /// Consumes a builtin type or the name of a type definition visible in the current scope
+    fn consume_type(&mut self) -> Result<TypeNode, ParserError> {
+        let type_token = self.consume_unchecked()?;
+        let position = type_token.position;
+        let span = type_token.span;
+        let var_type = match type_token.ty {
+            TokenType::VarType => type_token.value.try_into().map_err(|e| {
+                ParserError::new(ParserErrorKind::InvalidType, format!("{}", e))
+                    .set_position(position)
+                    .set_span(span)
+            })?,
+            TokenType::Identifier => self.resolve_type(&type_token.value).ok_or_else(|| {
+                ParserError::new(
+                    ParserErrorKind::InvalidType,
+                    format!("Unknown type `{}`", type_token.value),
+                )
+                .set_position(position)
+                .set_span(span)
+            })?,
+            _ => return Err(ParserError::unexpected(&type_token)),
+        };
+        Ok(TypeNode::from(var_type)
+            .set_start(position)
+            .set_end(position)
+            .set_span(self.span_from(position)))
+    }
+
+    /// Span from `start` to the end of the last consumed token
+    fn span_from(&self, start: Position) -> Span {
+        Span::new(self.lexer.file_id(), start.offset, self.previous_hi)
+    }
+
+    fn resolve_type(&self, id: &str) -> Option<Type> {
+        self.type_scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(id))
+            .cloned()
     }
 
     fn consume_block(&mut self) -> Result<BlockStatementNode, ParserError> {
-        let mut statements: Vec<StatementNode> = vec![];
         let left_brace = self.consume(TokenType::LBrace)?;
 
+        // type definitions are scoped to the block they appear in
+        self.type_scopes.push(HashMap::new());
+        let statements = self.consume_block_statements();
+        self.type_scopes.pop();
+
+        let right_brace = self.consume(TokenType::RBrace)?;
+        let block = BlockStatement(statements);
+
+        Ok(BlockStatementNode::from(block)
+            .set_start(left_brace.position)
+            .set_end(right_brace.position)
+            .set_span(self.span_from(left_brace.position)))
+    }
+
+    fn consume_block_statements(&mut self) -> Vec<StatementNode> {
+        let mut statements: Vec<StatementNode> = vec![];
         while !self.peek(TokenType::RBrace) {
-            match self.consume_statement()? {
+            match self.consume_statement_or_recover() {
                 Some(s) => statements.push(s),
                 None => break,
             }
         }
+        statements
+    }
 
-        let right_brace = self.consume(TokenType::RBrace)?;
-        let block = BlockStatement(statements);
+    fn consume_expression(&mut self) -> Result<ExpressionNode, ParserError> {
+        self.consume_expression_bp(0)
+    }
 
-        Ok(BlockStatementNode::from(block)
-            .set_start(left_brace.position)
-            .set_end(right_brace.position))
+    /// Precedence climbing: parses an expression whose operators all bind at least as tight as `min_bp`
+    fn consume_expression_bp(&mut self, min_bp: u8) -> Result<ExpressionNode, ParserError> {
+        let lhs = self.consume_prefix_expression()?;
+        self.consume_infix_expression(lhs, min_bp)
     }
 
-    fn consume_expression(&self) -> Result<ExpressionNode, ParserError> {
-        unimplemented!()
+    fn consume_infix_expression(
+        &mut self,
+        mut lhs: ExpressionNode,
+        min_bp: u8,
+    ) -> Result<ExpressionNode, ParserError> {
+        while let Some(token) = self.peek_unchecked() {
+            let ty = token.ty.clone();
+
+            if ty == TokenType::QuestionMark {
+                let (l_bp, r_bp) = TERNARY_BINDING_POWER;
+                if l_bp < min_bp {
+                    break;
+                }
+                self.consume(TokenType::QuestionMark)?;
+                let consequence = self.consume_expression_bp(0)?;
+                self.consume(TokenType::Colon)?;
+                let alternative = self.consume_expression_bp(r_bp)?;
+                let (start, end) = (lhs.start, alternative.end);
+                lhs = ExpressionNode::from(Expression::Ternary(
+                    Box::new(lhs),
+                    Box::new(consequence),
+                    Box::new(alternative),
+                ))
+                .set_start(start)
+                .set_end(end)
+                .set_span(self.span_from(start));
+                continue;
+            }
+
+            // the lexer reads `a -1` as an identifier followed by a negative literal,
+            // in infix position this is a subtraction
+            let negative_literal = ty == TokenType::IntLiteral && token.value.starts_with('-');
+            let operator = if negative_literal {
+                TokenType::Minus
+            } else {
+                ty
+            };
+
+            let (l_bp, r_bp) = match infix_binding_power(&operator) {
+                Some(bp) => bp,
+                None => break,
+            };
+            if l_bp < min_bp {
+                break;
+            }
+
+            let token = self.consume_unchecked()?;
+            let rhs = if negative_literal {
+                let position = Position {
+                    line: token.position.line,
+                    col: token.position.col + 1,
+                    offset: token.position.offset + 1,
+                };
+                let literal = self.consume_int_literal(&token.value[1..], position)?;
+                self.consume_infix_expression(literal, r_bp)?
+            } else {
+                self.consume_expression_bp(r_bp)?
+            };
+
+            let (start, end) = (lhs.start, rhs.end);
+            lhs = ExpressionNode::from(binary_expression(&operator, lhs, rhs))
+                .set_start(start)
+                .set_end(end)
+                .set_span(self.span_from(start));
+        }
+        Ok(lhs)
+    }
+
+    fn consume_prefix_expression(&mut self) -> Result<ExpressionNode, ParserError> {
+        let token = self.consume_unchecked()?;
+        let start = token.position;
+
+        match token.ty {
+            TokenType::Not | TokenType::Minus => {
+                let operand = self.consume_expression_bp(PREFIX_BINDING_POWER)?;
+                let end = operand.end;
+                let expression = match token.ty {
+                    TokenType::Not => Expression::Not(Box::new(operand)),
+                    _ => Expression::Neg(Box::new(operand)),
+                };
+                Ok(ExpressionNode::from(expression)
+                    .set_start(start)
+                    .set_end(end)
+                    .set_span(self.span_from(start)))
+            }
+            TokenType::LParen => {
+                let expression = self.consume_expression()?;
+                let right_paren = self.consume(TokenType::RParen)?;
+                Ok(expression
+                    .set_start(start)
+                    .set_end(right_paren.position)
+                    .set_span(self.span_from(start)))
+            }
+            TokenType::LBracket => {
+                let (elements, right_bracket) =
+                    self.consume_expression_list(TokenType::RBracket)?;
+                let elements = elements.into_iter().map(|e| e.value).collect();
+                Ok(
+                    ExpressionNode::from(Expression::Literal(Literal::Array(elements)))
+                        .set_start(start)
+                        .set_end(right_bracket.position)
+                        .set_span(self.span_from(start)),
+                )
+            }
+            TokenType::Function => {
+                let function = self.consume_function(start, Identifier::new())?;
+                let end = function.end;
+                Ok(ExpressionNode::from(Expression::FunctionDef(function))
+                    .set_start(start)
+                    .set_end(end)
+                    .set_span(self.span_from(start)))
+            }
+            // the lexer keeps the sign of `-2`, which is negated like any other operand so
+            // that `-2 ** 2` is `-(2 ** 2)`
+            TokenType::IntLiteral if token.value.starts_with('-') => {
+                let position = Position {
+                    line: start.line,
+                    col: start.col + 1,
+                    offset: start.offset + 1,
+                };
+                let literal = self.consume_int_literal(&token.value[1..], position)?;
+                let operand = self.consume_infix_expression(literal, PREFIX_BINDING_POWER)?;
+                let end = operand.end;
+                Ok(ExpressionNode::from(Expression::Neg(Box::new(operand)))
+                    .set_start(start)
+                    .set_end(end)
+                    .set_span(self.span_from(start)))
+            }
+            TokenType::IntLiteral => self.consume_int_literal(&token.value, start),
+            TokenType::BooleanLiteral => Ok(ExpressionNode::from(Expression::Literal(
+                Literal::Boolean(token.value == "true"),
+            ))
+            .set_start(start)
+            .set_end(start)
+            .set_span(self.span_from(start))),
+            TokenType::NullLiteral => Ok(ExpressionNode::from(Expression::Literal(Literal::Null))
+                .set_start(start)
+                .set_end(start)
+                .set_span(self.span_from(start))),
+            TokenType::StringLiteral => Ok(ExpressionNode::from(Expression::Literal(
+                Literal::String(token.value),
+            ))
+            .set_start(start)
+            .set_end(start)
+            .set_span(self.span_from(start))),
+            TokenType::Identifier => {
+                if self.peek(TokenType::LParen) {
+                    self.consume(TokenType::LParen)?;
+                    let (arguments, right_paren) =
+                        self.consume_expression_list(TokenType::RParen)?;
+                    Ok(
+                        ExpressionNode::from(Expression::FunctionCall(token.value, arguments))
+                            .set_start(start)
+                            .set_end(right_paren.position)
+                            .set_span(self.span_from(start)),
+                    )
+                } else {
+                    Ok(ExpressionNode::from(Expression::Identifier(token.value))
+                        .set_start(start)
+                        .set_end(start)
+                        .set_span(self.span_from(start)))
+                }
+            }
+            _ => Err(ParserError::unexpected(&token)),
+        }
+    }
+
+    /// Consumes a comma separated list of expressions up to and including the `closing` token
+    fn consume_expression_list(
+        &mut self,
+        closing: TokenType,
+    ) -> Result<(Vec<ExpressionNode>, Token), ParserError> {
+        let mut expressions = vec![];
+        if !self.peek(closing.clone()) {
+            expressions.push(self.consume_expression()?);
+            while self.peek(TokenType::Comma) {
+                self.consume(TokenType::Comma)?;
+                expressions.push(self.consume_expression()?);
+            }
+        }
+        let closing = self.consume(closing)?;
+        Ok((expressions, closing))
+    }
+
+    /// Integer literals are typed as the smallest of `i32`, `i64` and `u64` that holds the value
+    fn consume_int_literal(
+        &mut self,
+        value: &str,
+        position: Position,
+    ) -> Result<ExpressionNode, ParserError> {
+        let literal = if let Ok(v) = value.parse::<i32>() {
+            Literal::Int32(v)
+        } else if let Ok(v) = value.parse::<i64>() {
+            Literal::Int64(v)
+        } else if let Ok(v) = value.parse::<u64>() {
+            Literal::UInt64(v)
+        } else {
+            return Err(ParserError::new(
+                ParserErrorKind::InvalidLiteral,
+                format!("Integer literal `{}` is out of range", value),
+            )
+            .set_position(position)
+            .set_span(self.span_from(position)));
+        };
+        Ok(ExpressionNode::from(Expression::Literal(literal))
+            .set_start(position)
+            .set_end(position)
+            .set_span(self.span_from(position)))
+    }
+
+    /// Fills the lookahead with the next significant token.
+    /// Comments are skipped and lexical errors are reported, so the parser never sees either.
+    fn fill_lookahead(&mut self) {
+        while self.lookahead.is_none() {
+            let token = match self.lexer.next_token() {
+                Some(token) => token,
+                None => break,
+            };
+            match token.ty {
+                TokenType::Comment => {}
+                TokenType::Error(ref error) => {
+                    let message = match error {
+                        LexError::UnexpectedCharacter => format!("{} `{}`", error, token.value),
+                        LexError::UnterminatedString => format!("{}", error),
+                    };
+                    self.report(
+                        ParserError::new(ParserErrorKind::InvalidToken, message)
+                            .set_position(token.position)
+                            .set_span(token.span),
+                    );
+                }
+                _ => self.lookahead = Some(token),
+            }
+        }
     }
 
     fn consume_unchecked(&mut self) -> Result<Token, ParserError> {
-        Ok(self.lexer.next().ok_or(ParserError::eof())?)
+        self.fill_lookahead();
+        let token = self.lookahead.take().ok_or_else(ParserError::eof)?;
+        self.previous = Some(token.ty.clone());
+        self.previous_hi = token.span.hi;
+        Ok(token)
     }
 
     fn consume(&mut self, expected_type: TokenType) -> Result<Token, ParserError> {
-        let token = self.lexer.next().ok_or(ParserError::eof())?;
+        let token = self.consume_unchecked()?;
         if token.ty == expected_type {
             Ok(token)
         } else {
@@ -176,25 +989,18 @@ impl<'ast> Parser<'ast> {
                 ParserErrorKind::UnexpectedToken,
                 format!("Expected token {:?}, but got {:?}", expected_type, token.ty),
             )
-            .set_position(token.position))
+            .set_position(token.position)
+            .set_span(token.span))
         }
     }
 
     fn peek_unchecked(&mut self) -> Option<&Token> {
-        self.lexer.peek()
-    }
-
-    fn peek_next(&mut self, expected_type: TokenType) -> Option<&Token> {
-        let token = self.lexer.peek()?;
-        if token.ty == expected_type {
-            Some(token)
-        } else {
-            None
-        }
+        self.fill_lookahead();
+        self.lookahead.as_ref()
     }
 
     fn peek(&mut self, expected_type: TokenType) -> bool {
-        if let Some(t) = self.lexer.peek() {
+        if let Some(t) = self.peek_unchecked() {
             t.ty == expected_type
         } else {
             false
@@ -206,6 +1012,35 @@ impl<'ast> Parser<'ast> {
 mod tests {
     use super::*;
     use crate::ast::types::Type;
+    use crate::source_map::SourceMap;
+
+    type Binary = fn(Box<ExpressionNode>, Box<ExpressionNode>) -> Expression;
+
+    fn parse_expression(code: &str) -> ExpressionNode {
+        let mut parser = Parser::new(Lexer::new(code));
+        parser.consume_expression().unwrap()
+    }
+
+    fn parse(code: &str) -> Vec<StatementNode> {
+        let mut parser = Parser::new(Lexer::new(code));
+        parser.parse_program().unwrap().0
+    }
+
+    fn param(id: &str, ty: Type) -> VariableNode {
+        VariableNode::from(Variable::new(String::from(id), Some(ty)))
+    }
+
+    fn id(id: &str) -> ExpressionNode {
+        ExpressionNode::from(Expression::Identifier(String::from(id)))
+    }
+
+    fn int(value: i32) -> ExpressionNode {
+        ExpressionNode::from(Expression::Literal(Literal::Int32(value)))
+    }
+
+    fn binary(op: Binary, lhs: ExpressionNode, rhs: ExpressionNode) -> ExpressionNode {
+        ExpressionNode::from(op(Box::new(lhs), Box::new(rhs)))
+    }
 
     #[test]
     fn let_declaration() {
@@ -223,4 +1058,576 @@ mod tests {
             ))]
         )
     }
+
+    #[test]
+    fn expression_precedence() {
+        assert_eq!(
+            parse_expression("a + b * c - d"),
+            binary(
+                Expression::Sub,
+                binary(
+                    Expression::Add,
+                    id("a"),
+                    binary(Expression::Mul, id("b"), id("c"))
+                ),
+                id("d")
+            )
+        );
+        assert_eq!(
+            parse_expression("a < b && !c || d == 1"),
+            binary(
+                Expression::Or,
+                binary(
+                    Expression::And,
+                    binary(Expression::Lt, id("a"), id("b")),
+                    ExpressionNode::from(Expression::Not(Box::new(id("c"))))
+                ),
+                binary(Expression::Eq, id("d"), int(1))
+            )
+        );
+        assert_eq!(
+            parse_expression("-a * b"),
+            binary(
+                Expression::Mul,
+                ExpressionNode::from(Expression::Neg(Box::new(id("a")))),
+                id("b")
+            )
+        );
+        assert_eq!(
+            parse_expression("!a * b"),
+            binary(
+                Expression::Mul,
+                ExpressionNode::from(Expression::Not(Box::new(id("a")))),
+                id("b")
+            )
+        );
+        assert_eq!(
+            parse_expression("-2 ** 2"),
+            ExpressionNode::from(Expression::Neg(Box::new(binary(
+                Expression::Pow,
+                int(2),
+                int(2)
+            ))))
+        );
+        assert_eq!(
+            parse_expression("-2 * a"),
+            binary(
+                Expression::Mul,
+                ExpressionNode::from(Expression::Neg(Box::new(int(2)))),
+                id("a")
+            )
+        );
+        assert_eq!(
+            parse_expression("-a ** b"),
+            ExpressionNode::from(Expression::Neg(Box::new(binary(
+                Expression::Pow,
+                id("a"),
+                id("b")
+            ))))
+        );
+        assert_eq!(
+            parse_expression("(a + b) % c"),
+            binary(
+                Expression::Mod,
+                binary(Expression::Add, id("a"), id("b")),
+                id("c")
+            )
+        );
+    }
+
+    #[test]
+    fn expression_associativity() {
+        assert_eq!(
+            parse_expression("a ** b ** c"),
+            binary(
+                Expression::Pow,
+                id("a"),
+                binary(Expression::Pow, id("b"), id("c"))
+            )
+        );
+        assert_eq!(
+            parse_expression("a / b / c"),
+            binary(
+                Expression::Div,
+                binary(Expression::Div, id("a"), id("b")),
+                id("c")
+            )
+        );
+        assert_eq!(
+            parse_expression("a ? b : c ? d : e"),
+            ExpressionNode::from(Expression::Ternary(
+                Box::new(id("a")),
+                Box::new(id("b")),
+                Box::new(ExpressionNode::from(Expression::Ternary(
+                    Box::new(id("c")),
+                    Box::new(id("d")),
+                    Box::new(id("e"))
+                )))
+            ))
+        );
+    }
+
+    #[test]
+    fn expression_negative_literal_in_infix_position() {
+        assert_eq!(
+            parse_expression("a -1 * b"),
+            binary(
+                Expression::Sub,
+                id("a"),
+                binary(Expression::Mul, int(1), id("b"))
+            )
+        );
+        assert_eq!(
+            parse_expression("-1 + a"),
+            binary(
+                Expression::Add,
+                ExpressionNode::from(Expression::Neg(Box::new(int(1)))),
+                id("a")
+            )
+        );
+    }
+
+    #[test]
+    fn expression_function_call() {
+        assert_eq!(
+            parse_expression("foo(a, 1 + 2, bar())"),
+            ExpressionNode::from(Expression::FunctionCall(
+                String::from("foo"),
+                vec![
+                    id("a"),
+                    binary(Expression::Add, int(1), int(2)),
+                    ExpressionNode::from(Expression::FunctionCall(String::from("bar"), vec![]))
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn expression_positions() {
+        let expression = parse_expression("foo(a) + (b * 2)");
+        assert_eq!(expression.start.col, 0);
+        assert_eq!(expression.end.col, 15);
+
+        match expression.value {
+            Expression::Add(lhs, rhs) => {
+                assert_eq!((lhs.start.col, lhs.end.col), (0, 5));
+                assert_eq!((rhs.start.col, rhs.end.col), (9, 15));
+            }
+            _ => panic!("expected an addition"),
+        }
+    }
+
+    #[test]
+    fn let_definition() {
+        let code = r#"let a: u64 = 18446744073709551615;"#;
+        let mut parser = Parser::new(Lexer::new(code));
+
+        let program = parser.parse_program().unwrap();
+        assert_eq!(
+            program.0,
+            vec![StatementNode::from(Statement::Definition(
+                DefinitionType::Let,
+                VariableNode::from(Variable::new(String::from("a"), Some(Type::UInt(64)))),
+                ExpressionNode::from(Expression::Literal(Literal::UInt64(u64::MAX)))
+            ))]
+        )
+    }
+
+    #[test]
+    fn function_definition() {
+        let statements = parse(
+            r#"
+            func outer(a: i32, b: u64): bool {
+                func inner() {
+                    return 1;
+                }
+                return a < b;
+            }
+        "#,
+        );
+
+        let inner = Function::new(
+            String::from("inner"),
+            vec![],
+            vec![StatementNode::from(Statement::Return(int(1)))],
+            None,
+        );
+        let outer = Function::new(
+            String::from("outer"),
+            vec![param("a", Type::Int(32)), param("b", Type::UInt(64))],
+            vec![
+                StatementNode::from(Statement::FunctionDefinition(FunctionNode::from(inner))),
+                StatementNode::from(Statement::Return(binary(Expression::Lt, id("a"), id("b")))),
+            ],
+            Some(Type::Boolean),
+        );
+
+        assert_eq!(outer.signature.inputs, vec![Type::Int(32), Type::UInt(64)]);
+        assert_eq!(
+            statements,
+            vec![StatementNode::from(Statement::FunctionDefinition(
+                FunctionNode::from(outer)
+            ))]
+        );
+        assert_eq!((statements[0].start.line, statements[0].end.line), (1, 6));
+    }
+
+    #[test]
+    fn anonymous_function() {
+        let statements = parse(r#"auto f = func(x: i32): i32 { return x * 2; };"#);
+
+        let function = Function::new(
+            Identifier::new(),
+            vec![param("x", Type::Int(32))],
+            vec![StatementNode::from(Statement::Return(binary(
+                Expression::Mul,
+                id("x"),
+                int(2),
+            )))],
+            Some(Type::Int(32)),
+        );
+
+        assert_eq!(
+            statements,
+            vec![StatementNode::from(Statement::Definition(
+                DefinitionType::Auto,
+                VariableNode::from(Variable::new(String::from("f"), None)),
+                ExpressionNode::from(Expression::FunctionDef(FunctionNode::from(function)))
+            ))]
+        );
+    }
+
+    #[test]
+    fn loops() {
+        let statements = parse(
+            r#"
+            while a < 10 {
+                continue;
+            }
+            for let i: i32 = 0; i < 10; auto j = i {
+                break;
+            }
+        "#,
+        );
+
+        let block = |statement: Statement| {
+            BlockStatementNode::from(BlockStatement(vec![StatementNode::from(statement)]))
+        };
+
+        assert_eq!(
+            statements,
+            vec![
+                StatementNode::from(Statement::While(
+                    binary(Expression::Lt, id("a"), int(10)),
+                    block(Statement::Continue)
+                )),
+                StatementNode::from(Statement::For(
+                    Some(Box::new(StatementNode::from(Statement::Definition(
+                        DefinitionType::Let,
+                        param("i", Type::Int(32)),
+                        int(0)
+                    )))),
+                    binary(Expression::Lt, id("i"), int(10)),
+                    Some(Box::new(StatementNode::from(Statement::Definition(
+                        DefinitionType::Auto,
+                        VariableNode::from(Variable::new(String::from("j"), None)),
+                        id("i")
+                    )))),
+                    block(Statement::Break)
+                ))
+            ]
+        );
+    }
+
+    #[test]
+    fn break_outside_of_loop() {
+        let code = r#"
+            while true {
+                func f() {
+                    break;
+                }
+            }
+        "#;
+        let mut parser = Parser::new(Lexer::new(code));
+        let error = parser.parse_program().unwrap_err().remove(0);
+
+        assert!(matches!(error.kind, ParserErrorKind::InvalidStatement));
+        assert_eq!(error.position.map(|p| p.line), Some(3));
+
+        let mut parser = Parser::new(Lexer::new("continue;"));
+        assert!(parser.parse_program().is_err());
+    }
+
+    #[test]
+    fn switch_statement() {
+        let statements = parse(
+            r#"
+            switch x {
+                case 1 { return 1; }
+                case 2 { return 4; }
+                default { return 0; }
+            }
+        "#,
+        );
+
+        let block = |value: i32| {
+            BlockStatementNode::from(BlockStatement(vec![StatementNode::from(
+                Statement::Return(int(value)),
+            )]))
+        };
+        let case = |value: i32, result: i32| {
+            SwitchCaseNode::from(SwitchCase::new(int(value), block(result)))
+        };
+
+        assert_eq!(
+            statements,
+            vec![StatementNode::from(Statement::Switch(
+                id("x"),
+                vec![case(1, 1), case(2, 4)],
+                Some(block(0))
+            ))]
+        );
+    }
+
+    #[test]
+    fn invalid_switch_statements() {
+        let mut parser = Parser::new(Lexer::new("switch x { default {} default {} }"));
+        assert_eq!(
+            parser.parse_program().unwrap_err()[0].message,
+            "Multiple `default` arms in switch"
+        );
+    }
+
+    #[test]
+    fn assignments() {
+        let statements = parse("a = 1; a += b * 2; a <<= 3; a++; for ; a < 3; a-- {}");
+
+        let assignment = |expr: ExpressionNode| {
+            StatementNode::from(Statement::Assignment(
+                AssigneeNode::from(Assignee::Identifier(String::from("a"))),
+                expr,
+            ))
+        };
+
+        assert_eq!(
+            statements,
+            vec![
+                assignment(int(1)),
+                assignment(binary(
+                    Expression::Add,
+                    id("a"),
+                    binary(Expression::Mul, id("b"), int(2))
+                )),
+                assignment(binary(Expression::Shl, id("a"), int(3))),
+                assignment(binary(Expression::Add, id("a"), int(1))),
+                StatementNode::from(Statement::For(
+                    None,
+                    binary(Expression::Lt, id("a"), int(3)),
+                    Some(Box::new(assignment(binary(
+                        Expression::Sub,
+                        id("a"),
+                        int(1)
+                    )))),
+                    BlockStatementNode::from(BlockStatement(vec![]))
+                ))
+            ]
+        );
+
+        // positions of the desugared `a += b * 2`
+        assert_eq!((statements[1].start.col, statements[1].end.col), (7, 17));
+        match &statements[1].value {
+            Statement::Assignment(_, expr) => assert_eq!((expr.start.col, expr.end.col), (7, 16)),
+            _ => panic!("expected an assignment"),
+        }
+
+        let mut parser = Parser::new(Lexer::new("a < 1;"));
+        assert!(matches!(
+            parser.parse_program().unwrap_err()[0].kind,
+            ParserErrorKind::UnexpectedToken
+        ));
+    }
+
+    #[test]
+    fn function_call_statement() {
+        assert_eq!(
+            parse("foo(a, 1);"),
+            vec![StatementNode::from(Statement::FunctionCall(
+                String::from("foo"),
+                vec![id("a"), int(1)]
+            ))]
+        );
+    }
+
+    #[test]
+    fn type_definitions() {
+        let statements = parse(
+            r#"
+            typedef Id = u64;
+            typedef Key = Id;
+            func get(key: Key): Id {
+                typedef Flag = bool;
+                let f: Flag;
+                return key;
+            }
+        "#,
+        );
+
+        match &statements[2].value {
+            Statement::FunctionDefinition(function) => {
+                let function = &function.value;
+                assert_eq!(function.signature.inputs, vec![Type::UInt(64)]);
+                assert_eq!(function.signature.output, Some(Type::UInt(64)));
+                assert_eq!(
+                    function.statements[1],
+                    StatementNode::from(Statement::Declaration(param("f", Type::Boolean)))
+                );
+            }
+            _ => panic!("expected a function definition"),
+        }
+        assert_eq!(
+            statements[1],
+            StatementNode::from(Statement::TypeDefinition(
+                String::from("Key"),
+                TypeNode::from(Type::UInt(64))
+            ))
+        );
+
+        let errors = vec![
+            ("let a: Id;", "Unknown type `Id`"),
+            (
+                "if true { typedef Id = u64; } let a: Id;",
+                "Unknown type `Id`",
+            ),
+            (
+                "typedef Id = u64; typedef Id = u32;",
+                "Type `Id` is already defined",
+            ),
+        ];
+        for (code, message) in errors {
+            let mut parser = Parser::new(Lexer::new(code));
+            assert_eq!(parser.parse_program().unwrap_err()[0].message, message);
+        }
+    }
+
+    #[test]
+    fn error_recovery() {
+        let code = r#"
+            let a: i32 = ;
+            func f(): i32 {
+                let b: foo;
+                if b < { return 1; }
+                return 2;
+            }
+            break;
+            let c: bool = true;
+        "#;
+        let mut parser = Parser::new(Lexer::new(code));
+        let (program, errors) = parser.parse_partial_program();
+
+        let messages: Vec<(usize, &str)> = errors
+            .iter()
+            .map(|e| (e.position.unwrap().line, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (1, "Unexpected token Semicolon `;`"),
+                (3, "Unknown type `foo`"),
+                (4, "Unexpected token LBrace `{`"),
+                (7, "`break` outside of a loop"),
+            ]
+        );
+
+        let statements = program.0;
+        assert_eq!(statements.len(), 4);
+        assert_eq!(statements[0], StatementNode::from(Statement::Error));
+        assert_eq!((statements[0].start.line, statements[0].end.line), (1, 1));
+        match &statements[1].value {
+            Statement::FunctionDefinition(function) => {
+                let statements = &function.value.statements;
+                assert_eq!(statements.len(), 3);
+                assert_eq!(statements[0], StatementNode::from(Statement::Error));
+                assert_eq!(statements[1], StatementNode::from(Statement::Error));
+                assert_eq!(
+                    statements[2],
+                    StatementNode::from(Statement::Return(int(2)))
+                );
+            }
+            _ => panic!("expected a function definition"),
+        }
+        assert_eq!(statements[2], StatementNode::from(Statement::Break));
+        assert_eq!(
+            statements[3],
+            StatementNode::from(Statement::Definition(
+                DefinitionType::Let,
+                param("c", Type::Boolean),
+                ExpressionNode::from(Expression::Literal(Literal::Boolean(true)))
+            ))
+        );
+    }
+
+    #[test]
+    fn lexer_errors() {
+        let code = r#"
+            let a: i32 = 1; $
+            let b: string = "abc
+        "#;
+        let mut parser = Parser::new(Lexer::new(code));
+        let (program, errors) = parser.parse_partial_program();
+
+        let messages: Vec<(Option<usize>, &str)> = errors
+            .iter()
+            .map(|e| (e.position.map(|p| p.line), e.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (Some(1), "Unexpected character `$`"),
+                (Some(2), "Unterminated string literal"),
+                (None, "Unexpected end of file (EOF)"),
+            ]
+        );
+        assert_eq!(program.0.len(), 2);
+    }
+
+    #[test]
+    fn spans() {
+        let mut source_map = SourceMap::new();
+        source_map.add_file(String::from("a.out"), String::new());
+        let code = "auto é = \"ü\";\r\nx += foo(1, -2) * 3;";
+        let file_id = source_map.add_file(String::from("b.out"), String::from(code));
+
+        let mut parser = Parser::new(Lexer::with_file(code, file_id));
+        // `é` is not a valid identifier character, its span covers both of its bytes
+        let errors = parser.parse_program().unwrap_err();
+        assert_eq!(errors[0].span(), Some(Span::new(file_id, 5, 7)));
+
+        let code = "auto s = \"ü\";\r\nx += foo(1, -2) * 3;";
+        let file_id = source_map.add_file(String::from("c.out"), String::from(code));
+        let mut parser = Parser::new(Lexer::with_file(code, file_id));
+        let statements = parser.parse_program().unwrap().0;
+
+        let snippet = |span: Span| source_map.snippet(span);
+        assert_eq!(statements[0].span.file_id, file_id);
+        assert_eq!(snippet(statements[0].span), "auto s = \"ü\";");
+        assert_eq!(snippet(statements[1].span), "x += foo(1, -2) * 3;");
+        match &statements[1].value {
+            Statement::Assignment(assignee, expr) => {
+                assert_eq!(snippet(assignee.span), "x");
+                assert_eq!(snippet(expr.span), "x += foo(1, -2) * 3");
+                match &expr.value {
+                    Expression::Add(lhs, rhs) => {
+                        assert_eq!(snippet(lhs.span), "x");
+                        assert_eq!(snippet(rhs.span), "foo(1, -2) * 3");
+                    }
+                    _ => panic!("expected an addition"),
+                }
+            }
+            _ => panic!("expected an assignment"),
+        }
+
+        let position = source_map.position(file_id, statements[1].span.lo);
+        assert_eq!((position.line, position.col), (1, 0));
+        assert_eq!(statements[1].start.offset, statements[1].span.lo);
+    }
 }
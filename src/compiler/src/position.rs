@@ -1,13 +1,51 @@
 use core::fmt;
 
-#[derive(Debug, Clone, Default, Copy)]
+pub type FileId = usize;
+
+#[derive(Debug, Clone, Default, Copy, PartialEq)]
 pub struct Position {
     pub line: usize,
     pub col: usize,
+    /// byte offset from the start of the file
+    pub offset: usize,
 }
 
 impl fmt::Display for Position {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}:{}", self.line, self.col)
+        // lines and columns are stored 0-based but displayed 1-based
+        write!(f, "{}:{}", self.line + 1, self.col + 1)
+    }
+}
+
+/// Half-open byte range `lo..hi` of a file in a `SourceMap`
+#[derive(Debug, Clone, Default, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub file_id: FileId,
+    pub lo: usize,
+    pub hi: usize,
+}
+
+impl Span {
+    pub fn new(file_id: FileId, lo: usize, hi: usize) -> Self {
+        Self { file_id, lo, hi }
+    }
+
+    /// Returns the smallest span covering both `self` and `other`
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.file_id, self.lo.min(other.lo), self.hi.max(other.hi))
+    }
+
+    pub fn len(&self) -> usize {
+        self.hi - self.lo
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lo == self.hi
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..{}", self.lo, self.hi)
     }
 }
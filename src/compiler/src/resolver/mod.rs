@@ -0,0 +1,602 @@
+use crate::ast::node::Node;
+use crate::ast::{
+    Assignee, BlockStatementNode, DefinitionType, Expression, ExpressionNode, FunctionNode,
+    Program, Statement, StatementNode, VariableNode,
+};
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::position::{Position, Span};
+use core::fmt;
+use std::collections::HashMap;
+
+pub type SymbolId = usize;
+pub type ScopeId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Variable,
+    Parameter,
+    Function,
+}
+
+impl fmt::Display for SymbolKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolKind::Variable => write!(f, "variable"),
+            SymbolKind::Parameter => write!(f, "parameter"),
+            SymbolKind::Function => write!(f, "function"),
+        }
+    }
+}
+
+/// A declared name
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub scope: ScopeId,
+    /// start and span of the declaration
+    pub position: Position,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScopeKind {
+    Program,
+    Block,
+    Function,
+}
+
+#[derive(Debug, Clone)]
+pub struct Scope {
+    pub kind: ScopeKind,
+    pub parent: Option<ScopeId>,
+    names: HashMap<String, SymbolId>,
+}
+
+/// Every scope and declaration of a program, and the declaration each name refers to
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    scopes: Vec<Scope>,
+    symbols: Vec<Symbol>,
+    /// span of a use, or of a declaration, to the symbol it refers to
+    bindings: HashMap<Span, SymbolId>,
+}
+
+impl SymbolTable {
+    pub fn symbol(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id]
+    }
+
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    pub fn scope(&self, id: ScopeId) -> &Scope {
+        &self.scopes[id]
+    }
+
+    /// The symbol a name at `span` is bound to
+    pub fn binding(&self, span: Span) -> Option<&Symbol> {
+        self.symbol_id(span).map(|id| &self.symbols[id])
+    }
+
+    pub fn symbol_id(&self, span: Span) -> Option<SymbolId> {
+        self.bindings.get(&span).copied()
+    }
+
+    /// Spans of the uses of a symbol, declarations excluded
+    pub fn references(&self, id: SymbolId) -> impl Iterator<Item = Span> + '_ {
+        let declaration = self.symbols[id].span;
+        self.bindings
+            .iter()
+            .filter(move |(span, symbol)| **symbol == id && **span != declaration)
+            .map(|(span, _)| *span)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResolveErrorKind {
+    UndefinedVariable(String),
+    UndefinedFunction(String),
+    DuplicateDeclaration(String),
+    FunctionAsVariable(String),
+    VariableAsFunction(String),
+    /// a variable of an enclosing function used inside a nested function
+    CapturedVariable(String),
+    ShadowedDeclaration(String),
+}
+
+impl fmt::Display for ResolveErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResolveErrorKind::UndefinedVariable(id) => write!(f, "Undefined variable `{}`", id),
+            ResolveErrorKind::UndefinedFunction(id) => write!(f, "Undefined function `{}`", id),
+            ResolveErrorKind::DuplicateDeclaration(id) => {
+                write!(f, "`{}` is declared twice in the same scope", id)
+            }
+            ResolveErrorKind::FunctionAsVariable(id) => {
+                write!(f, "Function `{}` is used as a variable", id)
+            }
+            ResolveErrorKind::VariableAsFunction(id) => write!(f, "`{}` is not a function", id),
+            ResolveErrorKind::CapturedVariable(id) => {
+                write!(f, "Cannot use `{}` of an enclosing function", id)
+            }
+            ResolveErrorKind::ShadowedDeclaration(id) => {
+                write!(f, "`{}` shadows a previous declaration", id)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolveError {
+    kind: ResolveErrorKind,
+    position: Position,
+    span: Span,
+    /// the declaration the error relates to, if any
+    declaration: Option<Span>,
+}
+
+impl ResolveError {
+    pub fn new(kind: ResolveErrorKind, position: Position, span: Span) -> Self {
+        Self {
+            kind,
+            position,
+            span,
+            declaration: None,
+        }
+    }
+    pub fn kind(&self) -> &ResolveErrorKind {
+        &self.kind
+    }
+    pub fn position(&self) -> Position {
+        self.position
+    }
+    pub fn span(&self) -> Span {
+        self.span
+    }
+    pub fn declaration(&self) -> Option<Span> {
+        self.declaration
+    }
+    /// Shadowing is legal, so it is only reported as a warning
+    pub fn severity(&self) -> Severity {
+        match self.kind {
+            ResolveErrorKind::ShadowedDeclaration(_) => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+    pub fn is_error(&self) -> bool {
+        self.severity() == Severity::Error
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.kind, self.position)
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+impl From<&ResolveError> for Diagnostic {
+    fn from(error: &ResolveError) -> Self {
+        let diagnostic = Diagnostic::new(error.severity(), error.kind.to_string())
+            .with_label(error.span, String::new());
+        let (message, note) = match &error.kind {
+            ResolveErrorKind::DuplicateDeclaration(_) => ("first declared here", None),
+            ResolveErrorKind::ShadowedDeclaration(_) => ("previously declared here", None),
+            ResolveErrorKind::FunctionAsVariable(_) => ("function declared here", None),
+            ResolveErrorKind::VariableAsFunction(_) => ("variable declared here", None),
+            ResolveErrorKind::CapturedVariable(_) => (
+                "declared here",
+                Some("functions cannot capture variables, pass it as a parameter instead"),
+            ),
+            _ => ("", None),
+        };
+        let diagnostic = match error.declaration {
+            Some(declaration) => {
+                diagnostic.with_secondary_label(declaration, String::from(message))
+            }
+            None => diagnostic,
+        };
+        match note {
+            Some(note) => diagnostic.with_note(String::from(note)),
+            None => diagnostic,
+        }
+    }
+}
+
+/// Binds every name of a program to its declaration
+#[derive(Debug, Clone)]
+pub struct Resolver {
+    table: SymbolTable,
+    /// innermost scope last
+    stack: Vec<ScopeId>,
+    errors: Vec<ResolveError>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+enum Lookup {
+    Found(SymbolId),
+    /// only a variable of an enclosing function has this name
+    Captured(SymbolId),
+    NotFound,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        let mut resolver = Self {
+            table: SymbolTable::default(),
+            stack: vec![],
+            errors: vec![],
+        };
+        resolver.push_scope(ScopeKind::Program);
+        resolver
+    }
+
+    /// Returns the symbol table with the errors and warnings found, sorted by position
+    pub fn resolve_program(mut self, program: &Program) -> (SymbolTable, Vec<ResolveError>) {
+        let errors = self.resolve_more(program);
+        (self.table, errors)
+    }
+
+    /// Resolves top level statements following the ones resolved before, which they can
+    /// refer to. Returns the errors and warnings of these statements only.
+    pub fn resolve_more(&mut self, program: &Program) -> Vec<ResolveError> {
+        self.resolve_statements(&program.0);
+        let mut errors = std::mem::take(&mut self.errors);
+        errors.sort_by_key(|error| (error.span.file_id, error.span.lo));
+        errors
+    }
+
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.table
+    }
+
+    fn push_scope(&mut self, kind: ScopeKind) {
+        let id = self.table.scopes.len();
+        self.table.scopes.push(Scope {
+            kind,
+            parent: self.stack.last().copied(),
+            names: HashMap::new(),
+        });
+        self.stack.push(id);
+    }
+
+    fn pop_scope(&mut self) {
+        self.stack.pop();
+    }
+
+    fn report<T>(&mut self, kind: ResolveErrorKind, node: &Node<T>) -> &mut ResolveError {
+        self.errors
+            .push(ResolveError::new(kind, node.start, node.span));
+        self.errors.last_mut().unwrap()
+    }
+
+    fn declare<T>(&mut self, name: &str, kind: SymbolKind, node: &Node<T>) {
+        let scope = *self.stack.last().unwrap();
+        if let Some(previous) = self.table.scopes[scope].names.get(name) {
+            let declaration = self.table.symbols[*previous].span;
+            let error = self.report(
+                ResolveErrorKind::DuplicateDeclaration(name.to_string()),
+                node,
+            );
+            error.declaration = Some(declaration);
+            return;
+        }
+        if let Lookup::Found(previous) = self.lookup(name) {
+            let declaration = self.table.symbols[previous].span;
+            let error = self.report(
+                ResolveErrorKind::ShadowedDeclaration(name.to_string()),
+                node,
+            );
+            error.declaration = Some(declaration);
+        }
+
+        let id = self.table.symbols.len();
+        self.table.symbols.push(Symbol {
+            name: name.to_string(),
+            kind,
+            scope,
+            position: node.start,
+            span: node.span,
+        });
+        self.table.scopes[scope].names.insert(name.to_string(), id);
+        self.table.bindings.insert(node.span, id);
+    }
+
+    /// Functions are visible from nested functions, variables are not
+    fn lookup(&self, name: &str) -> Lookup {
+        let mut crossed_function = false;
+        let mut captured = None;
+        for scope in self.stack.iter().rev() {
+            let scope = &self.table.scopes[*scope];
+            if let Some(id) = scope.names.get(name) {
+                if !crossed_function || self.table.symbols[*id].kind == SymbolKind::Function {
+                    return Lookup::Found(*id);
+                }
+                captured = captured.or(Some(*id));
+            }
+            crossed_function |= scope.kind == ScopeKind::Function;
+        }
+        match captured {
+            Some(id) => Lookup::Captured(id),
+            None => Lookup::NotFound,
+        }
+    }
+
+    /// Binds a use of `name` as a variable (`function == false`) or as a callee
+    fn bind<T>(&mut self, name: &str, function: bool, node: &Node<T>) {
+        match self.lookup(name) {
+            Lookup::Found(id) => {
+                let symbol = &self.table.symbols[id];
+                let declaration = symbol.span;
+                match (function, symbol.kind == SymbolKind::Function) {
+                    (false, true) => {
+                        let kind = ResolveErrorKind::FunctionAsVariable(name.to_string());
+                        self.report(kind, node).declaration = Some(declaration);
+                    }
+                    (true, false) => {
+                        let kind = ResolveErrorKind::VariableAsFunction(name.to_string());
+                        self.report(kind, node).declaration = Some(declaration);
+                    }
+                    _ => {
+                        self.table.bindings.insert(node.span, id);
+                    }
+                }
+            }
+            Lookup::Captured(id) if !function => {
+                let declaration = self.table.symbols[id].span;
+                let kind = ResolveErrorKind::CapturedVariable(name.to_string());
+                self.report(kind, node).declaration = Some(declaration);
+            }
+            _ => {
+                let kind = if function {
+                    ResolveErrorKind::UndefinedFunction(name.to_string())
+                } else {
+                    ResolveErrorKind::UndefinedVariable(name.to_string())
+                };
+                self.report(kind, node);
+            }
+        }
+    }
+
+    fn resolve_statements(&mut self, statements: &[StatementNode]) {
+        // functions are visible in their whole block, so they can call each other
+        for statement in statements {
+            if let Statement::FunctionDefinition(function) = &statement.value {
+                self.declare(&function.value.id, SymbolKind::Function, function);
+            }
+        }
+        for statement in statements {
+            self.resolve_statement(statement);
+        }
+    }
+
+    fn resolve_block(&mut self, block: &BlockStatementNode) {
+        self.push_scope(ScopeKind::Block);
+        self.resolve_statements(&block.value.0);
+        self.pop_scope();
+    }
+
+    fn resolve_statement(&mut self, statement: &StatementNode) {
+        match &statement.value {
+            Statement::Declaration(variable) => self.declare_variable(variable),
+            Statement::Definition(DefinitionType::Auto, variable, expression)
+                if matches!(expression.value, Expression::FunctionDef(_)) =>
+            {
+                // declared first so the function can call itself
+                self.declare(&variable.value.id, SymbolKind::Function, variable);
+                self.resolve_expression(expression);
+            }
+            Statement::Definition(_, variable, expression) => {
+                // the initializer cannot refer to the variable being defined
+                self.resolve_expression(expression);
+                self.declare_variable(variable);
+            }
+            Statement::TypeDefinition(..) | Statement::Break | Statement::Continue => {}
+            Statement::Assignment(assignee, expression) => {
+                match &assignee.value {
+                    Assignee::Identifier(id) => self.bind(id, false, assignee),
+                }
+                self.resolve_expression(expression);
+            }
+            Statement::Condition(condition, consequence, alternative) => {
+                self.resolve_expression(condition);
+                self.resolve_block(consequence);
+                if let Some(alternative) = alternative {
+                    self.resolve_block(alternative);
+                }
+            }
+            Statement::While(condition, body) => {
+                self.resolve_expression(condition);
+                self.resolve_block(body);
+            }
+            Statement::For(init, condition, step, body) => {
+                self.push_scope(ScopeKind::Block);
+                if let Some(init) = init {
+                    self.resolve_statement(init);
+                }
+                self.resolve_expression(condition);
+                if let Some(step) = step {
+                    self.resolve_statement(step);
+                }
+                self.resolve_block(body);
+                self.pop_scope();
+            }
+            Statement::Switch(scrutinee, cases, default) => {
+                self.resolve_expression(scrutinee);
+                for case in cases {
+                    self.resolve_expression(&case.value.value);
+                    self.resolve_block(&case.value.body);
+                }
+                if let Some(default) = default {
+                    self.resolve_block(default);
+                }
+            }
+            Statement::FunctionCall(id, arguments) => {
+                self.bind(id, true, statement);
+                for argument in arguments {
+                    self.resolve_expression(argument);
+                }
+            }
+            Statement::FunctionDefinition(function) => self.resolve_function(function),
+            Statement::Return(expression) => self.resolve_expression(expression),
+            Statement::Error => {}
+        }
+    }
+
+    fn declare_variable(&mut self, variable: &VariableNode) {
+        self.declare(&variable.value.id, SymbolKind::Variable, variable);
+    }
+
+    fn resolve_function(&mut self, function: &FunctionNode) {
+        self.push_scope(ScopeKind::Function);
+        for parameter in &function.value.parameters {
+            self.declare(&parameter.value.id, SymbolKind::Parameter, parameter);
+        }
+        // the body shares the scope of the parameters, so it cannot redeclare them
+        self.resolve_statements(&function.value.statements);
+        self.pop_scope();
+    }
+
+    fn resolve_expression(&mut self, expression: &ExpressionNode) {
+        match &expression.value {
+            Expression::Identifier(id) => self.bind(id, false, expression),
+            Expression::Literal(_) => {}
+            Expression::Add(lhs, rhs)
+            | Expression::Sub(lhs, rhs)
+            | Expression::Mul(lhs, rhs)
+            | Expression::Div(lhs, rhs)
+            | Expression::Pow(lhs, rhs)
+            | Expression::Mod(lhs, rhs)
+            | Expression::Eq(lhs, rhs)
+            | Expression::Neq(lhs, rhs)
+            | Expression::Lt(lhs, rhs)
+            | Expression::Le(lhs, rhs)
+            | Expression::Ge(lhs, rhs)
+            | Expression::Gt(lhs, rhs)
+            | Expression::And(lhs, rhs)
+            | Expression::Or(lhs, rhs)
+            | Expression::BitAnd(lhs, rhs)
+            | Expression::BitOr(lhs, rhs)
+            | Expression::BitXor(lhs, rhs)
+            | Expression::Shl(lhs, rhs)
+            | Expression::Shr(lhs, rhs) => {
+                self.resolve_expression(lhs);
+                self.resolve_expression(rhs);
+            }
+            Expression::Not(operand) | Expression::Neg(operand) => self.resolve_expression(operand),
+            Expression::Ternary(condition, consequence, alternative) => {
+                self.resolve_expression(condition);
+                self.resolve_expression(consequence);
+                self.resolve_expression(alternative);
+            }
+            Expression::FunctionCall(id, arguments) => {
+                self.bind(id, true, expression);
+                for argument in arguments {
+                    self.resolve_expression(argument);
+                }
+            }
+            Expression::FunctionDef(function) => self.resolve_function(function),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn resolve(code: &str) -> (Program, SymbolTable, Vec<String>) {
+        let program = Parser::new(Lexer::new(code)).parse_program().unwrap();
+        let (table, errors) = Resolver::new().resolve_program(&program);
+        let errors = errors
+            .iter()
+            .map(|e| format!("{}: {}", e.severity(), e))
+            .collect();
+        (program, table, errors)
+    }
+
+    #[test]
+    fn bind_uses_to_declarations() {
+        let (program, table, errors) = resolve(
+            r#"
+            let a: i32 = 1;
+            func f(a: i32): i32 {
+                return g(a);
+            }
+            func g(b: i32): i32 { return b; }
+            a = f(a);
+        "#,
+        );
+        // the variable `a` is not visible inside `f`, so the parameter does not shadow it
+        assert_eq!(errors, Vec::<String>::new());
+
+        let binding = |statement: &StatementNode| match &statement.value {
+            Statement::Assignment(assignee, expression) => {
+                (table.binding(assignee.span), table.binding(expression.span))
+            }
+            _ => unreachable!(),
+        };
+        let (assignee, call) = binding(&program.0[3]);
+        let assignee = assignee.unwrap();
+        assert_eq!(
+            (assignee.name.as_str(), assignee.kind),
+            ("a", SymbolKind::Variable)
+        );
+        assert_eq!(assignee.position.line, 1);
+        let call = call.unwrap();
+        assert_eq!((call.name.as_str(), call.kind), ("f", SymbolKind::Function));
+
+        // `a` inside `f` is the parameter
+        let parameter = table
+            .symbols()
+            .iter()
+            .position(|s| s.kind == SymbolKind::Parameter && s.name == "a")
+            .unwrap();
+        assert_eq!(table.references(parameter).count(), 1);
+        assert_eq!(
+            table.scope(table.symbol(parameter).scope).kind,
+            ScopeKind::Function
+        );
+    }
+
+    #[test]
+    fn resolve_errors() {
+        let (_, _, errors) = resolve(
+            r#"
+            let a: i32 = b;
+            let a: i32 = 2;
+            func f(x: i32, x: i32) {
+                let y: i32 = a;
+                if true {
+                    let y: i32 = f;
+                }
+            }
+            a();
+            auto h = func(): i32 { return h(); };
+            for let i: i32 = 0; i < 10; i++ { g(i); }
+        "#,
+        );
+        assert_eq!(
+            errors,
+            vec![
+                "error: Undefined variable `b` at 2:26",
+                "error: `a` is declared twice in the same scope at 3:17",
+                "error: `x` is declared twice in the same scope at 4:28",
+                "error: Cannot use `a` of an enclosing function at 5:30",
+                "warning: `y` shadows a previous declaration at 7:25",
+                "error: Function `f` is used as a variable at 7:34",
+                "error: `a` is not a function at 10:13",
+                "error: Undefined function `g` at 12:47",
+            ]
+        );
+    }
+}
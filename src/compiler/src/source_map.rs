@@ -0,0 +1,124 @@
+use crate::position::{FileId, Position, Span};
+
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub id: FileId,
+    pub name: String,
+    pub source: String,
+    /// byte offsets at which each line starts
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    fn new(id: FileId, name: String, source: String) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            id,
+            name,
+            source,
+            line_starts,
+        }
+    }
+
+    /// Converts a byte offset to a 0-based line and a column counted in characters
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.source.len());
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        };
+        let line_start = self.line_starts[line];
+        let col = self.source[line_start..offset].chars().count();
+        Position { line, col, offset }
+    }
+
+    /// Returns the text of a 0-based line, without its line break (`\n` or `\r\n`)
+    pub fn line(&self, line: usize) -> Option<&str> {
+        let start = *self.line_starts.get(line)?;
+        let end = self
+            .line_starts
+            .get(line + 1)
+            .copied()
+            .unwrap_or(self.source.len());
+        let text = &self.source[start..end];
+        let text = text.strip_suffix('\n').unwrap_or(text);
+        Some(text.strip_suffix('\r').unwrap_or(text))
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    pub fn snippet(&self, span: Span) -> &str {
+        &self.source[span.lo.min(self.source.len())..span.hi.min(self.source.len())]
+    }
+}
+
+/// Owns every source file of a compilation, spans refer to files by their `FileId`
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_file(&mut self, name: String, source: String) -> FileId {
+        let id = self.files.len();
+        self.files.push(SourceFile::new(id, name, source));
+        id
+    }
+
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        &self.files[id]
+    }
+
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    pub fn position(&self, file_id: FileId, offset: usize) -> Position {
+        self.file(file_id).position(offset)
+    }
+
+    pub fn snippet(&self, span: Span) -> &str {
+        self.file(span.file_id).snippet(span)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        let mut source_map = SourceMap::new();
+        let first = source_map.add_file(String::from("a.out"), String::from("let a: i32;\n"));
+        let second = source_map.add_file(
+            String::from("b.out"),
+            String::from("let é: string = \"ü\";\r\nlet b: i32;"),
+        );
+
+        assert_eq!(source_map.file(first).name, "a.out");
+
+        let position = source_map.position(first, 4);
+        assert_eq!((position.line, position.col), (0, 4));
+
+        // `=` is preceded by a two byte character
+        let position = source_map.position(second, 15);
+        assert_eq!((position.line, position.col), (0, 14));
+
+        // CRLF line breaks do not count as columns
+        let position = source_map.position(second, 28);
+        assert_eq!((position.line, position.col), (1, 4));
+        assert_eq!(
+            source_map.file(second).line(0),
+            Some("let é: string = \"ü\";")
+        );
+        assert_eq!(source_map.snippet(Span::new(second, 4, 6)), "é");
+    }
+}
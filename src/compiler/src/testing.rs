@@ -0,0 +1,132 @@
+//! Programs shared by the tests of the backends, whose results are compared against the
+//! interpreter
+
+use crate::interpreter::value::Value;
+use crate::interpreter::Interpreter;
+use crate::CheckedProgram;
+
+/// Programs covering every statement and expression, and every runtime error
+pub const PROGRAMS: &[&str] = &[
+    r#"
+    func fib(n: u64): u64 {
+        if n < 2 {
+            return n;
+        }
+        return fib(n - 1) + fib(n - 2);
+    }
+    return fib(20);
+    "#,
+    r#"
+    func collatz(n: i64): i32 {
+        let steps: i32 = 0;
+        while n != 1 {
+            n = n % 2 == 0 ? n / 2 : 3 * n + 1;
+            steps++;
+        }
+        return steps;
+    }
+    return collatz(27);
+    "#,
+    r#"
+    auto sum = 0;
+    for let i: i32 = 0; i < 100; i++ {
+        if i % 3 == 0 {
+            continue;
+        }
+        switch i {
+            case 50 { break; }
+            case 7 { sum -= 1; }
+            default { sum += i; }
+        }
+    }
+    return sum;
+    "#,
+    r#"
+    auto i = 0;
+    auto found = -1;
+    while true {
+        i++;
+        if i * i > 200 {
+            found = i;
+            break;
+        } else {
+            if i == 3 {
+                continue;
+            }
+        }
+    }
+    return found;
+    "#,
+    r#"
+    auto square = func(x: i32): i32 { return x ** 2; };
+    auto bits = (1 << 4 | 3) ^ 1;
+    let big: u64 = 18446744073709551615;
+    let small: i64 = -9223372036854775807 - 1;
+    return square(-9) == 81 && bits == 18 && big >> 63 == 1 && small < 0 && (7 & 3) == 3;
+    "#,
+    r#"
+    auto zero = 0;
+    auto greeting = "out" + "er";
+    return false && 1 / zero == 0 || true || 1 / zero == 0 ? greeting : "wrong";
+    "#,
+    r#"
+    func value(): i32 { return 1; }
+    func call(): i32 { return value(); }
+    func outer(): i32 {
+        func value(): i32 { return 100; }
+        return call() + value();
+    }
+    let x: i32 = 1;
+    if true {
+        let x: i32 = 10;
+        x = x + 1;
+    }
+    return outer() + x;
+    "#,
+    r#"
+    func log(n: i32) {
+        while n > 0 {
+            n = n - 1;
+        }
+    }
+    log(1);
+    log(-1);
+    auto flag = !(1 > 2) && "a" != "b" && 2 >= 2 && 1 <= 0 == false;
+    "#,
+    "",
+    "auto zero = 0; return 1 / zero;",
+    "let x: u32 = 4294967295; x++;",
+    "let n: i64 = -9223372036854775807 - 1; return -n;",
+    "func shift(a: i32, b: i32): i32 { return a << b; } return shift(-3, 29) + shift(3, 30);",
+    "func f(): i32 { auto i = 0; while true { i = i + 1; if i == 3 { return i; } } } return f();",
+    "func pick(c: bool): i32 { let x: i32; if c { x = 1; } else { x = 2; } return x; } return pick(false);",
+    "func sign(b: bool): i32 { switch b { case true { return 1; } case false { return -1; } } } return sign(false);",
+    r#"
+    for let i: i32 = 0; i < 3; i++ {
+        let x: i32;
+        switch i {
+            case 1 { x = 10; }
+            default { x = i; }
+        }
+        if i == 2 {
+            return x;
+        }
+    }
+    "#,
+    "func f(n: i32): i32 { return f(n + 1); } return f(0);",
+    "auto exponent = -1; return 2 ** exponent;",
+];
+
+pub fn check(code: &str) -> CheckedProgram {
+    match crate::check(code) {
+        Ok(checked) => checked,
+        Err(error) => panic!("{}\n{}", error, code),
+    }
+}
+
+/// Runs a program on the interpreter, errors are compared by their message and position
+pub fn interpret(checked: &CheckedProgram) -> Result<Option<Value>, String> {
+    Interpreter::new(&checked.symbols)
+        .run(&checked.program)
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,582 @@
+//! Encoding of modules in the WebAssembly binary format: the magic number and version, then
+//! the type, function, export and code sections, with integers in LEB128.
+
+use super::{BlockType, Instruction, Module, Numeric, ValueType};
+
+pub const MAGIC: &[u8; 4] = b"\0asm";
+pub const VERSION: u32 = 1;
+
+const TYPE_SECTION: u8 = 1;
+const FUNCTION_SECTION: u8 = 3;
+const EXPORT_SECTION: u8 = 7;
+const CODE_SECTION: u8 = 10;
+
+const FUNCTION_TYPE: u8 = 0x60;
+const EMPTY_BLOCK: u8 = 0x40;
+const FUNCTION_EXPORT: u8 = 0x00;
+
+/// Encodes a module as a `.wasm` file
+pub fn encode(module: &Module) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+
+    let mut types = vec![];
+    unsigned(&mut types, module.types.len() as u64);
+    for ty in &module.types {
+        types.push(FUNCTION_TYPE);
+        value_types(&mut types, &ty.parameters);
+        value_types(&mut types, &ty.results);
+    }
+    section(&mut bytes, TYPE_SECTION, &types);
+
+    let mut functions = vec![];
+    unsigned(&mut functions, module.functions.len() as u64);
+    for function in &module.functions {
+        unsigned(&mut functions, function.ty as u64);
+    }
+    section(&mut bytes, FUNCTION_SECTION, &functions);
+
+    let mut exports = vec![];
+    unsigned(&mut exports, module.exports.len() as u64);
+    for export in &module.exports {
+        unsigned(&mut exports, export.name.len() as u64);
+        exports.extend_from_slice(export.name.as_bytes());
+        exports.push(FUNCTION_EXPORT);
+        unsigned(&mut exports, export.function as u64);
+    }
+    section(&mut bytes, EXPORT_SECTION, &exports);
+
+    let mut code = vec![];
+    unsigned(&mut code, module.functions.len() as u64);
+    for function in &module.functions {
+        let mut body = vec![];
+        // locals are declared in runs of the same type
+        let mut runs: Vec<(u32, ValueType)> = vec![];
+        for ty in &function.locals {
+            match runs.last_mut() {
+                Some((count, last)) if last == ty => *count += 1,
+                _ => runs.push((1, *ty)),
+            }
+        }
+        unsigned(&mut body, runs.len() as u64);
+        for (count, ty) in runs {
+            unsigned(&mut body, count as u64);
+            body.push(value_type(ty));
+        }
+        for instruction in &function.body {
+            encode_instruction(&mut body, instruction);
+        }
+        encode_instruction(&mut body, &Instruction::End);
+        unsigned(&mut code, body.len() as u64);
+        code.extend(body);
+    }
+    section(&mut bytes, CODE_SECTION, &code);
+    bytes
+}
+
+fn section(bytes: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    bytes.push(id);
+    unsigned(bytes, contents.len() as u64);
+    bytes.extend_from_slice(contents);
+}
+
+fn unsigned(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn signed(bytes: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        // done once the remaining bits are all copies of the sign bit of `byte`
+        let sign = byte & 0x40 != 0;
+        if (value == 0 && !sign) || (value == -1 && sign) {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+pub fn value_type(ty: ValueType) -> u8 {
+    match ty {
+        ValueType::I32 => 0x7f,
+        ValueType::I64 => 0x7e,
+    }
+}
+
+fn value_types(bytes: &mut Vec<u8>, types: &[ValueType]) {
+    unsigned(bytes, types.len() as u64);
+    bytes.extend(types.iter().map(|ty| value_type(*ty)));
+}
+
+fn block_type(ty: BlockType) -> u8 {
+    ty.map_or(EMPTY_BLOCK, value_type)
+}
+
+/// Opcode of a numeric instruction, comparisons and arithmetic are in two ranges per type
+pub fn numeric_opcode(ty: ValueType, numeric: Numeric) -> u8 {
+    let comparison = match numeric {
+        Numeric::Eqz => Some(0),
+        Numeric::Eq => Some(1),
+        Numeric::Ne => Some(2),
+        Numeric::LtS => Some(3),
+        Numeric::LtU => Some(4),
+        Numeric::GtS => Some(5),
+        Numeric::GtU => Some(6),
+        Numeric::LeS => Some(7),
+        Numeric::LeU => Some(8),
+        Numeric::GeS => Some(9),
+        Numeric::GeU => Some(10),
+        _ => None,
+    };
+    let arithmetic = match numeric {
+        Numeric::Add => 0,
+        Numeric::Sub => 1,
+        Numeric::Mul => 2,
+        Numeric::DivS => 3,
+        Numeric::DivU => 4,
+        Numeric::RemS => 5,
+        Numeric::RemU => 6,
+        Numeric::And => 7,
+        Numeric::Or => 8,
+        Numeric::Xor => 9,
+        Numeric::Shl => 10,
+        Numeric::ShrS => 11,
+        Numeric::ShrU => 12,
+        _ => 0,
+    };
+    match (ty, comparison) {
+        (ValueType::I32, Some(offset)) => 0x45 + offset,
+        (ValueType::I64, Some(offset)) => 0x50 + offset,
+        (ValueType::I32, None) => 0x6a + arithmetic,
+        (ValueType::I64, None) => 0x7c + arithmetic,
+    }
+}
+
+fn encode_instruction(bytes: &mut Vec<u8>, instruction: &Instruction) {
+    match *instruction {
+        Instruction::Unreachable => bytes.push(0x00),
+        Instruction::Block(ty) => bytes.extend([0x02, block_type(ty)]),
+        Instruction::Loop(ty) => bytes.extend([0x03, block_type(ty)]),
+        Instruction::If(ty) => bytes.extend([0x04, block_type(ty)]),
+        Instruction::Else => bytes.push(0x05),
+        Instruction::End => bytes.push(0x0b),
+        Instruction::Br(depth) => {
+            bytes.push(0x0c);
+            unsigned(bytes, depth as u64);
+        }
+        Instruction::BrIf(depth) => {
+            bytes.push(0x0d);
+            unsigned(bytes, depth as u64);
+        }
+        Instruction::Return => bytes.push(0x0f),
+        Instruction::Call(function) => {
+            bytes.push(0x10);
+            unsigned(bytes, function as u64);
+        }
+        Instruction::Drop => bytes.push(0x1a),
+        Instruction::LocalGet(index) => {
+            bytes.push(0x20);
+            unsigned(bytes, index as u64);
+        }
+        Instruction::LocalSet(index) => {
+            bytes.push(0x21);
+            unsigned(bytes, index as u64);
+        }
+        Instruction::LocalTee(index) => {
+            bytes.push(0x22);
+            unsigned(bytes, index as u64);
+        }
+        Instruction::I32Const(value) => {
+            bytes.push(0x41);
+            signed(bytes, value as i64);
+        }
+        Instruction::I64Const(value) => {
+            bytes.push(0x42);
+            signed(bytes, value);
+        }
+        Instruction::Numeric(ty, numeric) => bytes.push(numeric_opcode(ty, numeric)),
+        Instruction::I32WrapI64 => bytes.push(0xa7),
+        Instruction::I64ExtendI32S => bytes.push(0xac),
+        Instruction::I64ExtendI32U => bytes.push(0xad),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, PROGRAMS};
+    use crate::wasm::{compile, Export, Function, FunctionType};
+    use std::convert::TryFrom;
+
+    /// Reads a binary module back, checking its structure along the way
+    struct Decoder<'a> {
+        bytes: &'a [u8],
+        offset: usize,
+    }
+
+    impl<'a> Decoder<'a> {
+        fn byte(&mut self) -> Result<u8, String> {
+            let byte = *self
+                .bytes
+                .get(self.offset)
+                .ok_or_else(|| format!("unexpected end at {}", self.offset))?;
+            self.offset += 1;
+            Ok(byte)
+        }
+
+        fn bytes(&mut self, length: usize) -> Result<&'a [u8], String> {
+            let end = self.offset + length;
+            let bytes = self
+                .bytes
+                .get(self.offset..end)
+                .ok_or_else(|| format!("unexpected end at {}", self.offset))?;
+            self.offset = end;
+            Ok(bytes)
+        }
+
+        fn unsigned(&mut self) -> Result<u64, String> {
+            let (mut value, mut shift) = (0u64, 0);
+            loop {
+                let byte = self.byte()?;
+                value |= ((byte & 0x7f) as u64) << shift;
+                shift += 7;
+                if byte & 0x80 == 0 {
+                    return Ok(value);
+                }
+                if shift >= 64 {
+                    return Err(String::from("LEB128 integer too long"));
+                }
+            }
+        }
+
+        fn u32(&mut self) -> Result<u32, String> {
+            let value = self.unsigned()?;
+            u32::try_from(value).map_err(|_| format!("{} does not fit in u32", value))
+        }
+
+        fn signed(&mut self) -> Result<i64, String> {
+            let (mut value, mut shift) = (0i64, 0);
+            loop {
+                let byte = self.byte()?;
+                value |= ((byte & 0x7f) as i64) << shift;
+                shift += 7;
+                if byte & 0x80 == 0 {
+                    if shift < 64 && byte & 0x40 != 0 {
+                        value |= -1 << shift;
+                    }
+                    return Ok(value);
+                }
+            }
+        }
+
+        fn vector<T>(
+            &mut self,
+            mut element: impl FnMut(&mut Self) -> Result<T, String>,
+        ) -> Result<Vec<T>, String> {
+            let length = self.u32()?;
+            (0..length).map(|_| element(self)).collect()
+        }
+
+        fn value_type(&mut self) -> Result<ValueType, String> {
+            match self.byte()? {
+                0x7f => Ok(ValueType::I32),
+                0x7e => Ok(ValueType::I64),
+                byte => Err(format!("invalid value type {:#x}", byte)),
+            }
+        }
+
+        fn block_type(&mut self) -> Result<BlockType, String> {
+            match self.bytes.get(self.offset) {
+                Some(&EMPTY_BLOCK) => self.byte().map(|_| None),
+                _ => self.value_type().map(Some),
+            }
+        }
+
+        /// Reads a section, which has to be the expected one
+        fn section(&mut self, id: u8) -> Result<Decoder<'a>, String> {
+            let found = self.byte()?;
+            if found != id {
+                return Err(format!("expected section {}, found {}", id, found));
+            }
+            let length = self.u32()? as usize;
+            Ok(Decoder {
+                bytes: self.bytes(length)?,
+                offset: 0,
+            })
+        }
+
+        fn end(&self) -> Result<(), String> {
+            match self.offset == self.bytes.len() {
+                true => Ok(()),
+                false => Err(format!("trailing bytes at {}", self.offset)),
+            }
+        }
+
+        fn instruction(&mut self) -> Result<Instruction, String> {
+            let opcode = self.byte()?;
+            let instruction = match opcode {
+                0x00 => Instruction::Unreachable,
+                0x02 => Instruction::Block(self.block_type()?),
+                0x03 => Instruction::Loop(self.block_type()?),
+                0x04 => Instruction::If(self.block_type()?),
+                0x05 => Instruction::Else,
+                0x0b => Instruction::End,
+                0x0c => Instruction::Br(self.u32()?),
+                0x0d => Instruction::BrIf(self.u32()?),
+                0x0f => Instruction::Return,
+                0x10 => Instruction::Call(self.u32()?),
+                0x1a => Instruction::Drop,
+                0x20 => Instruction::LocalGet(self.u32()?),
+                0x21 => Instruction::LocalSet(self.u32()?),
+                0x22 => Instruction::LocalTee(self.u32()?),
+                0x41 => {
+                    let value = self.signed()?;
+                    Instruction::I32Const(
+                        i32::try_from(value).map_err(|_| format!("{} is not an i32", value))?,
+                    )
+                }
+                0x42 => Instruction::I64Const(self.signed()?),
+                0xa7 => Instruction::I32WrapI64,
+                0xac => Instruction::I64ExtendI32S,
+                0xad => Instruction::I64ExtendI32U,
+                _ => NUMERICS
+                    .iter()
+                    .flat_map(|numeric| {
+                        [ValueType::I32, ValueType::I64]
+                            .iter()
+                            .map(move |ty| (*ty, *numeric))
+                    })
+                    .find(|(ty, numeric)| numeric_opcode(*ty, *numeric) == opcode)
+                    .map(|(ty, numeric)| Instruction::Numeric(ty, numeric))
+                    .ok_or_else(|| format!("unknown opcode {:#x}", opcode))?,
+            };
+            Ok(instruction)
+        }
+    }
+
+    const NUMERICS: &[Numeric] = &[
+        Numeric::Eqz,
+        Numeric::Eq,
+        Numeric::Ne,
+        Numeric::LtS,
+        Numeric::LtU,
+        Numeric::GtS,
+        Numeric::GtU,
+        Numeric::LeS,
+        Numeric::LeU,
+        Numeric::GeS,
+        Numeric::GeU,
+        Numeric::Add,
+        Numeric::Sub,
+        Numeric::Mul,
+        Numeric::DivS,
+        Numeric::DivU,
+        Numeric::RemS,
+        Numeric::RemU,
+        Numeric::And,
+        Numeric::Or,
+        Numeric::Xor,
+        Numeric::Shl,
+        Numeric::ShrS,
+        Numeric::ShrU,
+    ];
+
+    /// Decodes a module and checks the references between its parts: type and function
+    /// indices, local indices and the nesting of blocks. Function names are not encoded.
+    fn decode(bytes: &[u8]) -> Result<Module, String> {
+        let mut decoder = Decoder { bytes, offset: 0 };
+        if decoder.bytes(4)? != MAGIC {
+            return Err(String::from("not a wasm module"));
+        }
+        if decoder.bytes(4)? != VERSION.to_le_bytes() {
+            return Err(String::from("unsupported version"));
+        }
+
+        let mut section = decoder.section(TYPE_SECTION)?;
+        let types = section.vector(|d| {
+            if d.byte()? != FUNCTION_TYPE {
+                return Err(String::from("expected a function type"));
+            }
+            Ok(FunctionType {
+                parameters: d.vector(Decoder::value_type)?,
+                results: d.vector(Decoder::value_type)?,
+            })
+        })?;
+        section.end()?;
+
+        let mut section = decoder.section(FUNCTION_SECTION)?;
+        let signatures = section.vector(Decoder::u32)?;
+        section.end()?;
+        let function_type = |index: u32| {
+            signatures
+                .get(index as usize)
+                .and_then(|ty| types.get(*ty as usize))
+                .ok_or_else(|| format!("invalid function index {}", index))
+        };
+
+        let mut section = decoder.section(EXPORT_SECTION)?;
+        let exports = section.vector(|d| {
+            let length = d.u32()? as usize;
+            let name = String::from_utf8(d.bytes(length)?.to_vec()).map_err(|e| e.to_string())?;
+            if d.byte()? != FUNCTION_EXPORT {
+                return Err(format!("`{}` is not a function export", name));
+            }
+            let function = d.u32()?;
+            function_type(function)?;
+            Ok(Export { name, function })
+        })?;
+        section.end()?;
+
+        let mut section = decoder.section(CODE_SECTION)?;
+        let bodies = section.vector(|d| {
+            let length = d.u32()? as usize;
+            let mut body = Decoder {
+                bytes: d.bytes(length)?,
+                offset: 0,
+            };
+            let mut locals = vec![];
+            for (count, ty) in body.vector(|d| Ok((d.u32()?, d.value_type()?)))? {
+                locals.extend(std::iter::repeat_n(ty, count as usize));
+            }
+            let mut instructions = vec![];
+            // the body is over once the `end` closing it is read
+            let mut depth = 1;
+            while depth > 0 {
+                let instruction = body.instruction()?;
+                match instruction {
+                    Instruction::Block(_) | Instruction::Loop(_) | Instruction::If(_) => depth += 1,
+                    Instruction::End => depth -= 1,
+                    Instruction::Br(target) | Instruction::BrIf(target) if target >= depth => {
+                        return Err(format!("branch to {} at depth {}", target, depth));
+                    }
+                    _ => {}
+                }
+                instructions.push(instruction);
+            }
+            body.end()?;
+            instructions.pop();
+            Ok((locals, instructions))
+        })?;
+        section.end()?;
+        decoder.end()?;
+
+        if bodies.len() != signatures.len() {
+            return Err(String::from("as many bodies as functions are expected"));
+        }
+        let mut functions = vec![];
+        for (index, (locals, body)) in bodies.into_iter().enumerate() {
+            let parameters = function_type(index as u32)?.parameters.len();
+            for instruction in &body {
+                match instruction {
+                    Instruction::LocalGet(local)
+                    | Instruction::LocalSet(local)
+                    | Instruction::LocalTee(local)
+                        if *local as usize >= parameters + locals.len() =>
+                    {
+                        return Err(format!("invalid local {} in function {}", local, index));
+                    }
+                    Instruction::Call(function) => {
+                        function_type(*function)?;
+                    }
+                    _ => {}
+                }
+            }
+            functions.push(Function {
+                name: String::new(),
+                ty: signatures[index],
+                locals,
+                body,
+            });
+        }
+        Ok(Module {
+            types,
+            functions,
+            exports,
+        })
+    }
+
+    #[test]
+    fn decode_every_program() {
+        for program in PROGRAMS {
+            let checked = testing::check(program);
+            let mut module = compile(&checked.program, &checked.symbols).unwrap();
+            let decoded = decode(&encode(&module)).unwrap_or_else(|e| panic!("{}\n{}", e, program));
+            for function in &mut module.functions {
+                function.name.clear();
+            }
+            assert_eq!(decoded, module, "{}", program);
+        }
+    }
+
+    #[test]
+    fn exports() {
+        let checked = testing::check(
+            r#"
+            func fib(n: u64): u64 {
+                func add(a: u64, b: u64): u64 { return a + b; }
+                return n < 2 ? n : add(fib(n - 1), fib(n - 2));
+            }
+            auto square = func(x: i32): i32 { return x * x; };
+            func nothing() {}
+            return fib(10);
+            "#,
+        );
+        let bytes = encode(&compile(&checked.program, &checked.symbols).unwrap());
+        assert_eq!(&bytes[..8], b"\0asm\x01\0\0\0");
+
+        let module = decode(&bytes).unwrap();
+        let exports: Vec<_> = module
+            .exports
+            .iter()
+            .map(|export| (export.name.as_str(), export.function))
+            .collect();
+        // `add` is only called by `fib`, the helpers come after the functions of the program
+        assert_eq!(exports, [("fib", 0), ("square", 2), ("nothing", 3)]);
+        assert_eq!(module.functions.len(), 7);
+        assert_eq!(
+            module.types[module.functions[3].ty as usize],
+            FunctionType {
+                parameters: vec![],
+                results: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn integers() {
+        let mut bytes = vec![];
+        unsigned(&mut bytes, 624485);
+        assert_eq!(bytes, [0xe5, 0x8e, 0x26]);
+        for value in [
+            0,
+            1,
+            -1,
+            63,
+            64,
+            -64,
+            -65,
+            i32::MIN as i64,
+            i64::MIN,
+            i64::MAX,
+        ] {
+            let mut bytes = vec![];
+            signed(&mut bytes, value);
+            let mut decoder = Decoder {
+                bytes: &bytes,
+                offset: 0,
+            };
+            assert_eq!(decoder.signed(), Ok(value));
+            decoder.end().unwrap();
+        }
+    }
+}
@@ -0,0 +1,911 @@
+use super::{Export, Function, FunctionType, Instruction, Module, Numeric, ValueType};
+use crate::ast::node::Node;
+use crate::ast::types::Type;
+use crate::ast::{
+    Assignee, BlockStatementNode, Expression, ExpressionNode, Function as OuterFunction, Literal,
+    Program, Statement, StatementNode,
+};
+use crate::bytecode::compiler::collect_functions;
+use crate::diagnostics::Diagnostic;
+use crate::position::{Position, Span};
+use crate::resolver::{SymbolId, SymbolTable};
+use core::fmt;
+use std::collections::{HashMap, HashSet};
+
+/// A construct of the program which has no WebAssembly lowering, like strings
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsupportedError {
+    what: String,
+    position: Position,
+    span: Span,
+}
+
+impl UnsupportedError {
+    fn new(what: String, position: Position, span: Span) -> Self {
+        Self {
+            what,
+            position,
+            span,
+        }
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl fmt::Display for UnsupportedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} are not supported by the WebAssembly backend at {}",
+            self.what, self.position
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedError {}
+
+impl From<&UnsupportedError> for Diagnostic {
+    fn from(error: &UnsupportedError) -> Self {
+        Diagnostic::error(format!(
+            "{} are not supported by the WebAssembly backend",
+            error.what
+        ))
+        .with_label(error.span, String::new())
+    }
+}
+
+/// Compiles the functions of a checked program, `table` is the symbol table the resolver
+/// built for it
+pub fn compile(program: &Program, table: &SymbolTable) -> Result<Module, UnsupportedError> {
+    let mut functions = vec![];
+    collect_functions(table, &program.0, &mut functions);
+
+    let mut compiler = Compiler {
+        table,
+        module: Module {
+            types: vec![],
+            functions: vec![],
+            exports: vec![],
+        },
+        functions: HashMap::new(),
+        helpers: vec![],
+        base: functions.len() as u32,
+        function: Function {
+            name: String::new(),
+            ty: 0,
+            locals: vec![],
+            body: vec![],
+        },
+        parameters: 0,
+        variables: HashMap::new(),
+        flags: HashMap::new(),
+        depth: 0,
+        loops: vec![],
+    };
+    for (index, (id, function)) in functions.iter().enumerate() {
+        compiler
+            .functions
+            .insert(*id, (index as u32, function.return_type.clone()));
+    }
+
+    let exported: HashSet<SymbolId> = top_level_functions(table, &program.0).collect();
+    for (index, (id, function)) in functions.iter().enumerate() {
+        let name = table.symbol(*id).name.clone();
+        compiler.compile_function(*id, function)?;
+        if exported.contains(id) {
+            compiler.module.exports.push(Export {
+                name,
+                function: index as u32,
+            });
+        }
+    }
+
+    // helpers can use other helpers, which are added to the list while it is compiled
+    let mut index = 0;
+    while index < compiler.helpers.len() {
+        let (helper, ty) = compiler.helpers[index].clone();
+        compiler.compile_helper(helper, &ty);
+        index += 1;
+    }
+    Ok(compiler.module)
+}
+
+/// The functions defined by the top level statements
+fn top_level_functions<'a>(
+    table: &'a SymbolTable,
+    statements: &'a [StatementNode],
+) -> impl Iterator<Item = SymbolId> + 'a {
+    statements
+        .iter()
+        .filter_map(move |statement| match &statement.value {
+            Statement::FunctionDefinition(function) => table.symbol_id(function.span),
+            Statement::Definition(_, variable, expression)
+                if matches!(expression.value, Expression::FunctionDef(_)) =>
+            {
+                table.symbol_id(variable.span)
+            }
+            _ => None,
+        })
+}
+
+/// Operators checking for overflows, lowered to functions of the module
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Helper {
+    Add,
+    Sub,
+    Mul,
+    Rem,
+    Pow,
+    Shl,
+    Shr,
+    Neg,
+}
+
+impl fmt::Display for Helper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Helper::Add => "add",
+            Helper::Sub => "sub",
+            Helper::Mul => "mul",
+            Helper::Rem => "rem",
+            Helper::Pow => "pow",
+            Helper::Shl => "shl",
+            Helper::Shr => "shr",
+            Helper::Neg => "neg",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Relative depths of the labels of a loop, from the start of the function
+struct Loop {
+    exit: u32,
+    /// `continue` jumps to the end of this block, or to the start of the loop
+    next: u32,
+}
+
+struct Compiler<'a> {
+    table: &'a SymbolTable,
+    module: Module,
+    /// index and return type of every function of the program
+    functions: HashMap<SymbolId, (u32, Option<Type>)>,
+    /// helpers used so far, the index of a helper is `base` plus its position
+    helpers: Vec<(Helper, Type)>,
+    base: u32,
+    /// the function being compiled
+    function: Function,
+    parameters: u32,
+    /// local index and type of every variable met so far
+    variables: HashMap<SymbolId, (u32, Type)>,
+    /// the local telling if a variable declared without a value is initialized
+    flags: HashMap<SymbolId, u32>,
+    /// number of blocks the current instruction is in
+    depth: u32,
+    loops: Vec<Loop>,
+}
+
+impl<'a> Compiler<'a> {
+    fn compile_function(
+        &mut self,
+        id: SymbolId,
+        function: &OuterFunction,
+    ) -> Result<(), UnsupportedError> {
+        self.variables.clear();
+        self.flags.clear();
+        let mut inputs = vec![];
+        for parameter in &function.parameters {
+            let ty = parameter.value.ty.clone().expect("parameters are typed");
+            inputs.push(value_type(&ty).ok_or_else(|| unsupported(&ty, parameter))?);
+            let id = self.symbol(parameter);
+            self.variables.insert(id, (inputs.len() as u32 - 1, ty));
+        }
+        let symbol = self.table.symbol(id);
+        let results = match &function.return_type {
+            Some(ty) => match value_type(ty) {
+                Some(value_type) => vec![value_type],
+                None => {
+                    let what = unsupported_type(ty);
+                    return Err(UnsupportedError::new(what, symbol.position, symbol.span));
+                }
+            },
+            None => vec![],
+        };
+        self.start_function(symbol.name.clone(), inputs, results);
+
+        self.statements(&function.statements)?;
+        // the checker makes sure every path of a function with an output returns
+        if function.return_type.is_some() {
+            self.emit(Instruction::Unreachable);
+        }
+        self.finish_function();
+        Ok(())
+    }
+
+    fn start_function(&mut self, name: String, inputs: Vec<ValueType>, results: Vec<ValueType>) {
+        let ty = FunctionType {
+            parameters: inputs,
+            results,
+        };
+        let index = match self.module.types.iter().position(|t| *t == ty) {
+            Some(index) => index,
+            None => {
+                self.module.types.push(ty.clone());
+                self.module.types.len() - 1
+            }
+        };
+        self.parameters = ty.parameters.len() as u32;
+        self.function = Function {
+            name,
+            ty: index as u32,
+            locals: vec![],
+            body: vec![],
+        };
+        self.depth = 0;
+        self.loops.clear();
+    }
+
+    fn finish_function(&mut self) {
+        let function = std::mem::replace(
+            &mut self.function,
+            Function {
+                name: String::new(),
+                ty: 0,
+                locals: vec![],
+                body: vec![],
+            },
+        );
+        self.module.functions.push(function);
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        match instruction {
+            Instruction::Block(_) | Instruction::Loop(_) | Instruction::If(_) => self.depth += 1,
+            Instruction::End => self.depth -= 1,
+            _ => {}
+        }
+        self.function.body.push(instruction);
+    }
+
+    fn new_local(&mut self, ty: ValueType) -> u32 {
+        self.function.locals.push(ty);
+        self.parameters + self.function.locals.len() as u32 - 1
+    }
+
+    fn symbol<T>(&self, node: &Node<T>) -> SymbolId {
+        self.table
+            .symbol_id(node.span)
+            .expect("names are resolved before compiling")
+    }
+
+    /// The local of the variable declared at `node`
+    fn declare<T>(&mut self, node: &Node<T>, ty: Type) -> Result<u32, UnsupportedError> {
+        let id = self.symbol(node);
+        let value_type = value_type(&ty).ok_or_else(|| unsupported(&ty, node))?;
+        let local = self.new_local(value_type);
+        self.variables.insert(id, (local, ty));
+        Ok(local)
+    }
+
+    fn statements(&mut self, statements: &[StatementNode]) -> Result<(), UnsupportedError> {
+        for statement in statements {
+            self.statement(statement)?;
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, block: &BlockStatementNode) -> Result<(), UnsupportedError> {
+        self.statements(&block.value.0)
+    }
+
+    fn statement(&mut self, statement: &StatementNode) -> Result<(), UnsupportedError> {
+        match &statement.value {
+            Statement::Declaration(variable) => {
+                let ty = variable.value.ty.clone().expect("declarations are typed");
+                self.declare(variable, ty)?;
+                // a loop can run the declaration again, which uninitializes the variable
+                let flag = self.new_local(ValueType::I32);
+                self.flags.insert(self.symbol(variable), flag);
+                self.emit(Instruction::I32Const(0));
+                self.emit(Instruction::LocalSet(flag));
+            }
+            Statement::Definition(_, _, expression)
+                if matches!(expression.value, Expression::FunctionDef(_)) => {}
+            Statement::Definition(_, variable, expression) => {
+                let value_ty = self.expression(expression)?;
+                let ty = variable.value.ty.clone().unwrap_or(value_ty);
+                let local = self.declare(variable, ty)?;
+                self.emit(Instruction::LocalSet(local));
+            }
+            Statement::Assignment(assignee, expression) => {
+                self.expression(expression)?;
+                let id = match &assignee.value {
+                    Assignee::Identifier(_) => self.symbol(assignee),
+                };
+                let (local, _) = self.variables[&id];
+                self.emit(Instruction::LocalSet(local));
+                if let Some(flag) = self.flags.get(&id).copied() {
+                    self.emit(Instruction::I32Const(1));
+                    self.emit(Instruction::LocalSet(flag));
+                }
+            }
+            Statement::Condition(condition, consequence, alternative) => {
+                self.expression(condition)?;
+                self.emit(Instruction::If(None));
+                self.block(consequence)?;
+                if let Some(alternative) = alternative {
+                    self.emit(Instruction::Else);
+                    self.block(alternative)?;
+                }
+                self.emit(Instruction::End);
+            }
+            Statement::While(condition, body) => {
+                self.emit(Instruction::Block(None));
+                self.emit(Instruction::Loop(None));
+                let (exit, start) = (self.depth - 1, self.depth);
+                self.exit_unless(condition, exit)?;
+                self.loops.push(Loop { exit, next: start });
+                self.block(body)?;
+                self.loops.pop();
+                self.emit(Instruction::Br(0));
+                self.emit(Instruction::End);
+                self.emit(Instruction::End);
+            }
+            Statement::For(init, condition, step, body) => {
+                if let Some(init) = init {
+                    self.statement(init)?;
+                }
+                self.emit(Instruction::Block(None));
+                self.emit(Instruction::Loop(None));
+                let exit = self.depth - 1;
+                self.exit_unless(condition, exit)?;
+                // `continue` leaves this block to run the step
+                self.emit(Instruction::Block(None));
+                self.loops.push(Loop {
+                    exit,
+                    next: self.depth,
+                });
+                self.block(body)?;
+                self.loops.pop();
+                self.emit(Instruction::End);
+                if let Some(step) = step {
+                    self.statement(step)?;
+                }
+                self.emit(Instruction::Br(0));
+                self.emit(Instruction::End);
+                self.emit(Instruction::End);
+            }
+            Statement::Switch(scrutinee, cases, default) => {
+                // the value is kept in a local, the cases compare against it
+                let ty = self.expression(scrutinee)?;
+                let value_type = value_type(&ty).ok_or_else(|| unsupported(&ty, scrutinee))?;
+                let local = self.new_local(value_type);
+                self.emit(Instruction::LocalSet(local));
+                for case in cases {
+                    self.emit(Instruction::LocalGet(local));
+                    self.expression(&case.value.value)?;
+                    self.emit(Instruction::Numeric(value_type, Numeric::Eq));
+                    self.emit(Instruction::If(None));
+                    self.block(&case.value.body)?;
+                    self.emit(Instruction::Else);
+                }
+                if let Some(default) = default {
+                    self.block(default)?;
+                }
+                for _ in cases {
+                    self.emit(Instruction::End);
+                }
+            }
+            Statement::Break | Statement::Continue => {
+                let is_break = matches!(statement.value, Statement::Break);
+                match self.loops.last() {
+                    Some(labels) => {
+                        let target = if is_break { labels.exit } else { labels.next };
+                        self.emit(Instruction::Br(self.depth - target));
+                    }
+                    // like the interpreter, outside of a loop it leaves the function, which
+                    // is an error if the function has to return a value
+                    None if self.module.types[self.function.ty as usize]
+                        .results
+                        .is_empty() =>
+                    {
+                        self.emit(Instruction::Return)
+                    }
+                    None => self.emit(Instruction::Unreachable),
+                }
+            }
+            Statement::FunctionCall(_, arguments) => {
+                if self.call(arguments, statement)?.is_some() {
+                    self.emit(Instruction::Drop);
+                }
+            }
+            Statement::Return(expression) => {
+                self.expression(expression)?;
+                self.emit(Instruction::Return);
+            }
+            Statement::TypeDefinition(..) | Statement::FunctionDefinition(_) | Statement::Error => {
+            }
+        }
+        Ok(())
+    }
+
+    /// Leaves the block at depth `exit` if the condition is false
+    fn exit_unless(
+        &mut self,
+        condition: &ExpressionNode,
+        exit: u32,
+    ) -> Result<(), UnsupportedError> {
+        self.expression(condition)?;
+        self.emit(Instruction::Numeric(ValueType::I32, Numeric::Eqz));
+        self.emit(Instruction::BrIf(self.depth - exit));
+        Ok(())
+    }
+
+    /// Compiles a call, returns the type of the value it leaves on the stack
+    fn call<T>(
+        &mut self,
+        arguments: &[ExpressionNode],
+        node: &Node<T>,
+    ) -> Result<Option<Type>, UnsupportedError> {
+        for argument in arguments {
+            self.expression(argument)?;
+        }
+        let (index, output) = self.functions[&self.symbol(node)].clone();
+        self.emit(Instruction::Call(index));
+        Ok(output)
+    }
+
+    /// Compiles an expression, which leaves its value on the stack, returns its type
+    fn expression(&mut self, expression: &ExpressionNode) -> Result<Type, UnsupportedError> {
+        let ty = match &expression.value {
+            Expression::Identifier(_) => {
+                let id = self.symbol(expression);
+                if let Some(flag) = self.flags.get(&id).copied() {
+                    self.emit(Instruction::LocalGet(flag));
+                    self.emit(Instruction::Numeric(ValueType::I32, Numeric::Eqz));
+                    self.trap_if();
+                }
+                let (local, ty) = self.variables[&id].clone();
+                self.emit(Instruction::LocalGet(local));
+                ty
+            }
+            Expression::Literal(literal) => {
+                let instruction = match literal {
+                    Literal::Int32(v) => Instruction::I32Const(*v),
+                    Literal::UInt32(v) => Instruction::I32Const(*v as i32),
+                    Literal::Int64(v) => Instruction::I64Const(*v),
+                    Literal::UInt64(v) => Instruction::I64Const(*v as i64),
+                    Literal::Boolean(v) => Instruction::I32Const(*v as i32),
+                    Literal::String(_) => return Err(unsupported(&Type::String, expression)),
+                    Literal::Array(_) | Literal::Null => {
+                        panic!("only checked literals are compiled")
+                    }
+                };
+                self.emit(instruction);
+                literal.default_type().unwrap()
+            }
+            Expression::Add(lhs, rhs) => self.helper(Helper::Add, lhs, rhs)?,
+            Expression::Sub(lhs, rhs) => self.helper(Helper::Sub, lhs, rhs)?,
+            Expression::Mul(lhs, rhs) => self.helper(Helper::Mul, lhs, rhs)?,
+            Expression::Pow(lhs, rhs) => self.helper(Helper::Pow, lhs, rhs)?,
+            Expression::Shl(lhs, rhs) => self.helper(Helper::Shl, lhs, rhs)?,
+            Expression::Shr(lhs, rhs) => self.helper(Helper::Shr, lhs, rhs)?,
+            // division traps on a zero divisor and on overflows, the remainder only on zero
+            Expression::Div(lhs, rhs) => self.numeric(Numeric::DivS, Numeric::DivU, lhs, rhs)?,
+            Expression::Mod(lhs, rhs) => match self.numeric_operands(lhs, rhs)? {
+                ty if ty.is_signed() => self.call_helper(Helper::Rem, ty),
+                ty => {
+                    self.emit(Instruction::Numeric(
+                        value_type(&ty).unwrap(),
+                        Numeric::RemU,
+                    ));
+                    ty
+                }
+            },
+            Expression::BitAnd(lhs, rhs) => self.numeric(Numeric::And, Numeric::And, lhs, rhs)?,
+            Expression::BitOr(lhs, rhs) => self.numeric(Numeric::Or, Numeric::Or, lhs, rhs)?,
+            Expression::BitXor(lhs, rhs) => self.numeric(Numeric::Xor, Numeric::Xor, lhs, rhs)?,
+            Expression::Eq(lhs, rhs) => self.comparison(Numeric::Eq, Numeric::Eq, lhs, rhs)?,
+            Expression::Neq(lhs, rhs) => self.comparison(Numeric::Ne, Numeric::Ne, lhs, rhs)?,
+            Expression::Lt(lhs, rhs) => self.comparison(Numeric::LtS, Numeric::LtU, lhs, rhs)?,
+            Expression::Le(lhs, rhs) => self.comparison(Numeric::LeS, Numeric::LeU, lhs, rhs)?,
+            Expression::Gt(lhs, rhs) => self.comparison(Numeric::GtS, Numeric::GtU, lhs, rhs)?,
+            Expression::Ge(lhs, rhs) => self.comparison(Numeric::GeS, Numeric::GeU, lhs, rhs)?,
+            // `&&` and `||` only evaluate their right operand when needed
+            Expression::And(lhs, rhs) => {
+                self.expression(lhs)?;
+                self.emit(Instruction::If(Some(ValueType::I32)));
+                self.expression(rhs)?;
+                self.emit(Instruction::Else);
+                self.emit(Instruction::I32Const(0));
+                self.emit(Instruction::End);
+                Type::Boolean
+            }
+            Expression::Or(lhs, rhs) => {
+                self.expression(lhs)?;
+                self.emit(Instruction::If(Some(ValueType::I32)));
+                self.emit(Instruction::I32Const(1));
+                self.emit(Instruction::Else);
+                self.expression(rhs)?;
+                self.emit(Instruction::End);
+                Type::Boolean
+            }
+            Expression::Not(operand) => {
+                self.expression(operand)?;
+                self.emit(Instruction::Numeric(ValueType::I32, Numeric::Eqz));
+                Type::Boolean
+            }
+            Expression::Neg(operand) => {
+                let ty = self.expression(operand)?;
+                self.call_helper(Helper::Neg, ty)
+            }
+            Expression::Ternary(condition, consequence, alternative) => {
+                self.expression(condition)?;
+                // the type of the result is only known once a branch is compiled
+                let index = self.function.body.len();
+                self.emit(Instruction::If(None));
+                let ty = self.expression(consequence)?;
+                self.function.body[index] = Instruction::If(Some(
+                    value_type(&ty).ok_or_else(|| unsupported(&ty, consequence))?,
+                ));
+                self.emit(Instruction::Else);
+                self.expression(alternative)?;
+                self.emit(Instruction::End);
+                ty
+            }
+            Expression::FunctionCall(_, arguments) => self
+                .call(arguments, expression)?
+                .expect("called functions return a value"),
+            Expression::FunctionDef(_) => {
+                panic!("anonymous functions are only compiled when bound with `auto`")
+            }
+        };
+        Ok(ty)
+    }
+
+    /// Compiles both operands, returns their type
+    fn numeric_operands(
+        &mut self,
+        lhs: &ExpressionNode,
+        rhs: &ExpressionNode,
+    ) -> Result<Type, UnsupportedError> {
+        let ty = self.expression(lhs)?;
+        self.expression(rhs)?;
+        Ok(ty)
+    }
+
+    /// An instruction which cannot overflow, picked by the signedness of the operands
+    fn numeric(
+        &mut self,
+        signed: Numeric,
+        unsigned: Numeric,
+        lhs: &ExpressionNode,
+        rhs: &ExpressionNode,
+    ) -> Result<Type, UnsupportedError> {
+        let ty = self.numeric_operands(lhs, rhs)?;
+        let numeric = if ty.is_signed() { signed } else { unsigned };
+        self.emit(Instruction::Numeric(value_type(&ty).unwrap(), numeric));
+        Ok(ty)
+    }
+
+    fn comparison(
+        &mut self,
+        signed: Numeric,
+        unsigned: Numeric,
+        lhs: &ExpressionNode,
+        rhs: &ExpressionNode,
+    ) -> Result<Type, UnsupportedError> {
+        let ty = self.expression(lhs)?;
+        self.expression(rhs)?;
+        let numeric = if ty.is_signed() { signed } else { unsigned };
+        self.emit(Instruction::Numeric(value_type(&ty).unwrap(), numeric));
+        Ok(Type::Boolean)
+    }
+
+    fn helper(
+        &mut self,
+        helper: Helper,
+        lhs: &ExpressionNode,
+        rhs: &ExpressionNode,
+    ) -> Result<Type, UnsupportedError> {
+        let ty = self.numeric_operands(lhs, rhs)?;
+        Ok(self.call_helper(helper, ty))
+    }
+
+    fn call_helper(&mut self, helper: Helper, ty: Type) -> Type {
+        let index = self.helper_index(helper, &ty);
+        self.emit(Instruction::Call(index));
+        ty
+    }
+
+    fn helper_index(&mut self, helper: Helper, ty: &Type) -> u32 {
+        let position = match self
+            .helpers
+            .iter()
+            .position(|(h, t)| *h == helper && t == ty)
+        {
+            Some(position) => position,
+            None => {
+                self.helpers.push((helper, ty.clone()));
+                self.helpers.len() - 1
+            }
+        };
+        self.base + position as u32
+    }
+
+    fn trap_if(&mut self) {
+        self.emit(Instruction::If(None));
+        self.emit(Instruction::Unreachable);
+        self.emit(Instruction::End);
+    }
+
+    /// Compiles a helper, with the semantics of `Value::binary` and `Value::neg`
+    fn compile_helper(&mut self, helper: Helper, ty: &Type) {
+        use Instruction::{
+            Block, Br, BrIf, Call, End, I32Const, I32WrapI64, I64Const, I64ExtendI32S,
+            I64ExtendI32U, If, LocalGet, LocalSet, LocalTee, Loop, Return,
+        };
+
+        let vt = value_type(ty).expect("helpers only take wasm types");
+        let arity = if helper == Helper::Neg { 1 } else { 2 };
+        self.start_function(format!("{}_{}", helper, ty), vec![vt; arity], vec![vt]);
+        let (a, b) = (0, 1);
+        let signed = ty.is_signed();
+        let wide = vt == ValueType::I64;
+        let (min, minus_one, zero, one) = if wide {
+            (I64Const(i64::MIN), I64Const(-1), I64Const(0), I64Const(1))
+        } else {
+            (I32Const(i32::MIN), I32Const(-1), I32Const(0), I32Const(1))
+        };
+        let op = |numeric| Instruction::Numeric(vt, numeric);
+
+        match helper {
+            Helper::Add | Helper::Sub | Helper::Mul if !wide => {
+                // computed on 64 bits, the result overflows if it does not fit in 32 bits
+                let extend = if signed { I64ExtendI32S } else { I64ExtendI32U };
+                let numeric = match helper {
+                    Helper::Add => Numeric::Add,
+                    Helper::Sub => Numeric::Sub,
+                    _ => Numeric::Mul,
+                };
+                let result = self.new_local(ValueType::I64);
+                for instruction in [
+                    LocalGet(a),
+                    extend,
+                    LocalGet(b),
+                    extend,
+                    Instruction::Numeric(ValueType::I64, numeric),
+                    LocalTee(result),
+                    LocalGet(result),
+                    I32WrapI64,
+                    extend,
+                    Instruction::Numeric(ValueType::I64, Numeric::Ne),
+                ] {
+                    self.emit(instruction);
+                }
+                self.trap_if();
+                self.emit(LocalGet(result));
+                self.emit(I32WrapI64);
+            }
+            Helper::Add | Helper::Sub => {
+                let numeric = if helper == Helper::Add {
+                    Numeric::Add
+                } else {
+                    Numeric::Sub
+                };
+                let result = self.new_local(vt);
+                for instruction in [LocalGet(a), LocalGet(b), op(numeric), LocalSet(result)] {
+                    self.emit(instruction);
+                }
+                let overflow: &[Instruction] = match (helper, signed) {
+                    // the result has a different sign than both operands
+                    (Helper::Add, true) => &[
+                        LocalGet(a),
+                        LocalGet(result),
+                        op(Numeric::Xor),
+                        LocalGet(b),
+                        LocalGet(result),
+                        op(Numeric::Xor),
+                        op(Numeric::And),
+                        zero,
+                        op(Numeric::LtS),
+                    ],
+                    (Helper::Add, false) => &[LocalGet(result), LocalGet(a), op(Numeric::LtU)],
+                    // the operands have different signs, and the result has the sign of `b`
+                    (_, true) => &[
+                        LocalGet(a),
+                        LocalGet(b),
+                        op(Numeric::Xor),
+                        LocalGet(a),
+                        LocalGet(result),
+                        op(Numeric::Xor),
+                        op(Numeric::And),
+                        zero,
+                        op(Numeric::LtS),
+                    ],
+                    (_, false) => &[LocalGet(a), LocalGet(b), op(Numeric::LtU)],
+                };
+                for instruction in overflow {
+                    self.emit(*instruction);
+                }
+                self.trap_if();
+                self.emit(LocalGet(result));
+            }
+            Helper::Mul => {
+                // the product overflows if dividing it by `a` does not give `b` back
+                for instruction in [LocalGet(a), op(Numeric::Eqz), If(None), zero, Return, End] {
+                    self.emit(instruction);
+                }
+                if signed {
+                    // `-1 * MIN` overflows, and dividing `MIN` by `-1` would trap
+                    for instruction in [LocalGet(a), minus_one, op(Numeric::Eq), If(None)] {
+                        self.emit(instruction);
+                    }
+                    for instruction in [LocalGet(b), min, op(Numeric::Eq)] {
+                        self.emit(instruction);
+                    }
+                    self.trap_if();
+                    for instruction in [zero, LocalGet(b), op(Numeric::Sub), Return, End] {
+                        self.emit(instruction);
+                    }
+                }
+                let result = self.new_local(vt);
+                let div = if signed { Numeric::DivS } else { Numeric::DivU };
+                for instruction in [
+                    LocalGet(a),
+                    LocalGet(b),
+                    op(Numeric::Mul),
+                    LocalTee(result),
+                    LocalGet(a),
+                    op(div),
+                    LocalGet(b),
+                    op(Numeric::Ne),
+                ] {
+                    self.emit(instruction);
+                }
+                self.trap_if();
+                self.emit(LocalGet(result));
+            }
+            Helper::Rem => {
+                // only called for signed integers, `MIN % -1` overflows in outer
+                for instruction in [
+                    LocalGet(a),
+                    min,
+                    op(Numeric::Eq),
+                    LocalGet(b),
+                    minus_one,
+                    op(Numeric::Eq),
+                    Instruction::Numeric(ValueType::I32, Numeric::And),
+                ] {
+                    self.emit(instruction);
+                }
+                self.trap_if();
+                for instruction in [LocalGet(a), LocalGet(b), op(Numeric::RemS)] {
+                    self.emit(instruction);
+                }
+            }
+            Helper::Pow => {
+                // a negative exponent is an error, one which does not fit in `u32` overflows
+                if signed {
+                    for instruction in [LocalGet(b), zero, op(Numeric::LtS)] {
+                        self.emit(instruction);
+                    }
+                    self.trap_if();
+                }
+                if wide {
+                    for instruction in [LocalGet(b), I64Const(u32::MAX as i64), op(Numeric::GtU)] {
+                        self.emit(instruction);
+                    }
+                    self.trap_if();
+                }
+                // exponentiation by squaring, the base is only squared when still needed
+                let mul = self.helper_index(Helper::Mul, ty);
+                let result = self.new_local(vt);
+                for instruction in [
+                    one,
+                    LocalSet(result),
+                    Block(None),
+                    Loop(None),
+                    LocalGet(b),
+                    op(Numeric::Eqz),
+                    BrIf(1),
+                    LocalGet(b),
+                    one,
+                    op(Numeric::And),
+                    zero,
+                    op(Numeric::Ne),
+                    If(None),
+                    LocalGet(result),
+                    LocalGet(a),
+                    Call(mul),
+                    LocalSet(result),
+                    End,
+                    LocalGet(b),
+                    one,
+                    op(Numeric::ShrU),
+                    LocalTee(b),
+                    op(Numeric::Eqz),
+                    BrIf(1),
+                    LocalGet(a),
+                    LocalGet(a),
+                    Call(mul),
+                    LocalSet(a),
+                    Br(0),
+                    End,
+                    End,
+                    LocalGet(result),
+                ] {
+                    self.emit(instruction);
+                }
+            }
+            Helper::Shl | Helper::Shr => {
+                // shifting by the width or more overflows, wasm would take it modulo the
+                // width, negative amounts are larger than the width as unsigned
+                let width = if wide { I64Const(64) } else { I32Const(32) };
+                for instruction in [LocalGet(b), width, op(Numeric::GeU)] {
+                    self.emit(instruction);
+                }
+                self.trap_if();
+                let numeric = match (helper, signed) {
+                    (Helper::Shl, _) => Numeric::Shl,
+                    (_, true) => Numeric::ShrS,
+                    (_, false) => Numeric::ShrU,
+                };
+                for instruction in [LocalGet(a), LocalGet(b), op(numeric)] {
+                    self.emit(instruction);
+                }
+                if helper == Helper::Shl {
+                    // bits shifted out, or into the sign bit, overflow: shifting back does
+                    // not give `a`
+                    let result = self.new_local(vt);
+                    let back = if signed { Numeric::ShrS } else { Numeric::ShrU };
+                    for instruction in [
+                        LocalTee(result),
+                        LocalGet(b),
+                        op(back),
+                        LocalGet(a),
+                        op(Numeric::Ne),
+                    ] {
+                        self.emit(instruction);
+                    }
+                    self.trap_if();
+                    self.emit(LocalGet(result));
+                }
+            }
+            Helper::Neg => {
+                for instruction in [LocalGet(a), min, op(Numeric::Eq)] {
+                    self.emit(instruction);
+                }
+                self.trap_if();
+                for instruction in [zero, LocalGet(a), op(Numeric::Sub)] {
+                    self.emit(instruction);
+                }
+            }
+        }
+        self.finish_function();
+    }
+}
+
+fn value_type(ty: &Type) -> Option<ValueType> {
+    match ty {
+        Type::Int(32) | Type::UInt(32) | Type::Boolean => Some(ValueType::I32),
+        Type::Int(64) | Type::UInt(64) => Some(ValueType::I64),
+        _ => None,
+    }
+}
+
+fn unsupported<T>(ty: &Type, node: &Node<T>) -> UnsupportedError {
+    UnsupportedError::new(unsupported_type(ty), node.start, node.span)
+}
+
+fn unsupported_type(ty: &Type) -> String {
+    match ty {
+        Type::String => String::from("Strings"),
+        _ => format!("Values of type `{}`", ty),
+    }
+}
@@ -0,0 +1,313 @@
+//! WebAssembly backend: lowers the functions of a checked program to a wasm module, printed
+//! as `.wat` text by `Display` and encoded with [`encode`].
+//!
+//! The module is a library: every function of the program is part of it, the ones defined at
+//! the top level are exported under their name, and the top level statements are left out.
+//! Runtime errors of outer, like overflows or the use of an uninitialized variable, trap.
+
+pub mod binary;
+pub mod compiler;
+
+pub use binary::encode;
+pub use compiler::{compile, UnsupportedError};
+
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    I32,
+    I64,
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValueType::I32 => write!(f, "i32"),
+            ValueType::I64 => write!(f, "i64"),
+        }
+    }
+}
+
+/// Type of the value a `block`, `loop` or `if` leaves on the stack
+pub type BlockType = Option<ValueType>;
+
+/// Numeric instructions, available on both `i32` and `i64`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Numeric {
+    Eqz,
+    Eq,
+    Ne,
+    LtS,
+    LtU,
+    GtS,
+    GtU,
+    LeS,
+    LeU,
+    GeS,
+    GeU,
+    Add,
+    Sub,
+    Mul,
+    DivS,
+    DivU,
+    RemS,
+    RemU,
+    And,
+    Or,
+    Xor,
+    Shl,
+    ShrS,
+    ShrU,
+}
+
+impl fmt::Display for Numeric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Numeric::Eqz => "eqz",
+            Numeric::Eq => "eq",
+            Numeric::Ne => "ne",
+            Numeric::LtS => "lt_s",
+            Numeric::LtU => "lt_u",
+            Numeric::GtS => "gt_s",
+            Numeric::GtU => "gt_u",
+            Numeric::LeS => "le_s",
+            Numeric::LeU => "le_u",
+            Numeric::GeS => "ge_s",
+            Numeric::GeU => "ge_u",
+            Numeric::Add => "add",
+            Numeric::Sub => "sub",
+            Numeric::Mul => "mul",
+            Numeric::DivS => "div_s",
+            Numeric::DivU => "div_u",
+            Numeric::RemS => "rem_s",
+            Numeric::RemU => "rem_u",
+            Numeric::And => "and",
+            Numeric::Or => "or",
+            Numeric::Xor => "xor",
+            Numeric::Shl => "shl",
+            Numeric::ShrS => "shr_s",
+            Numeric::ShrU => "shr_u",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The subset of WebAssembly instructions the backend emits, branch targets are relative
+/// depths like in the binary format
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    Unreachable,
+    Block(BlockType),
+    Loop(BlockType),
+    If(BlockType),
+    Else,
+    End,
+    Br(u32),
+    BrIf(u32),
+    Return,
+    Call(u32),
+    Drop,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    I32Const(i32),
+    I64Const(i64),
+    Numeric(ValueType, Numeric),
+    I32WrapI64,
+    I64ExtendI32S,
+    I64ExtendI32U,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn block(f: &mut fmt::Formatter, name: &str, ty: &BlockType) -> fmt::Result {
+            match ty {
+                Some(ty) => write!(f, "{} (result {})", name, ty),
+                None => write!(f, "{}", name),
+            }
+        }
+        match self {
+            Instruction::Unreachable => write!(f, "unreachable"),
+            Instruction::Block(ty) => block(f, "block", ty),
+            Instruction::Loop(ty) => block(f, "loop", ty),
+            Instruction::If(ty) => block(f, "if", ty),
+            Instruction::Else => write!(f, "else"),
+            Instruction::End => write!(f, "end"),
+            Instruction::Br(depth) => write!(f, "br {}", depth),
+            Instruction::BrIf(depth) => write!(f, "br_if {}", depth),
+            Instruction::Return => write!(f, "return"),
+            Instruction::Call(function) => write!(f, "call {}", function),
+            Instruction::Drop => write!(f, "drop"),
+            Instruction::LocalGet(index) => write!(f, "local.get {}", index),
+            Instruction::LocalSet(index) => write!(f, "local.set {}", index),
+            Instruction::LocalTee(index) => write!(f, "local.tee {}", index),
+            Instruction::I32Const(value) => write!(f, "i32.const {}", value),
+            Instruction::I64Const(value) => write!(f, "i64.const {}", value),
+            Instruction::Numeric(ty, numeric) => write!(f, "{}.{}", ty, numeric),
+            Instruction::I32WrapI64 => write!(f, "i32.wrap_i64"),
+            Instruction::I64ExtendI32S => write!(f, "i64.extend_i32_s"),
+            Instruction::I64ExtendI32U => write!(f, "i64.extend_i32_u"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionType {
+    pub parameters: Vec<ValueType>,
+    pub results: Vec<ValueType>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// the name of the outer function, only kept for the text format
+    pub name: String,
+    /// index of the signature in the type section
+    pub ty: u32,
+    /// the locals besides the parameters
+    pub locals: Vec<ValueType>,
+    /// the instructions, without the final `end`
+    pub body: Vec<Instruction>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Export {
+    pub name: String,
+    pub function: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    pub types: Vec<FunctionType>,
+    pub functions: Vec<Function>,
+    pub exports: Vec<Export>,
+}
+
+/// Prints the module in the WebAssembly text format
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn types(f: &mut fmt::Formatter, kind: &str, types: &[ValueType]) -> fmt::Result {
+            if !types.is_empty() {
+                write!(f, " ({}", kind)?;
+                for ty in types {
+                    write!(f, " {}", ty)?;
+                }
+                write!(f, ")")?;
+            }
+            Ok(())
+        }
+
+        writeln!(f, "(module")?;
+        for (index, ty) in self.types.iter().enumerate() {
+            write!(f, "  (type (;{};) (func", index)?;
+            types(f, "param", &ty.parameters)?;
+            types(f, "result", &ty.results)?;
+            writeln!(f, "))")?;
+        }
+        for (index, function) in self.functions.iter().enumerate() {
+            let ty = &self.types[function.ty as usize];
+            write!(f, "  (func (;{};) (type {})", index, function.ty)?;
+            types(f, "param", &ty.parameters)?;
+            types(f, "result", &ty.results)?;
+            writeln!(f, " ;; {}", function.name)?;
+            if !function.locals.is_empty() {
+                write!(f, "   ")?;
+                types(f, "local", &function.locals)?;
+                writeln!(f)?;
+            }
+            let mut depth = 2;
+            for instruction in &function.body {
+                if matches!(instruction, Instruction::Else | Instruction::End) {
+                    depth -= 1;
+                }
+                writeln!(f, "{:width$}{}", "", instruction, width = depth * 2)?;
+                if matches!(
+                    instruction,
+                    Instruction::Block(_)
+                        | Instruction::Loop(_)
+                        | Instruction::If(_)
+                        | Instruction::Else
+                ) {
+                    depth += 1;
+                }
+            }
+            writeln!(f, "  )")?;
+        }
+        for export in &self.exports {
+            writeln!(
+                f,
+                "  (export \"{}\" (func {}))",
+                export.name, export.function
+            )?;
+        }
+        write!(f, ")")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing;
+
+    #[test]
+    fn text_format() {
+        let checked = testing::check(
+            r#"
+            func max(a: u32, b: u32): u32 {
+                return a > b ? a : b;
+            }
+            func twice(x: i64): i64 { return x << 1; }
+            return max(1, 2);
+            "#,
+        );
+        let module = super::compile(&checked.program, &checked.symbols).unwrap();
+        assert_eq!(
+            module.to_string(),
+            r#"(module
+  (type (;0;) (func (param i32 i32) (result i32)))
+  (type (;1;) (func (param i64) (result i64)))
+  (type (;2;) (func (param i64 i64) (result i64)))
+  (func (;0;) (type 0) (param i32 i32) (result i32) ;; max
+    local.get 0
+    local.get 1
+    i32.gt_u
+    if (result i32)
+      local.get 0
+    else
+      local.get 1
+    end
+    return
+    unreachable
+  )
+  (func (;1;) (type 1) (param i64) (result i64) ;; twice
+    local.get 0
+    i64.const 1
+    call 2
+    return
+    unreachable
+  )
+  (func (;2;) (type 2) (param i64 i64) (result i64) ;; shl_i64
+    (local i64)
+    local.get 1
+    i64.const 64
+    i64.ge_u
+    if
+      unreachable
+    end
+    local.get 0
+    local.get 1
+    i64.shl
+    local.tee 2
+    local.get 1
+    i64.shr_s
+    local.get 0
+    i64.ne
+    if
+      unreachable
+    end
+    local.get 2
+  )
+  (export "max" (func 0))
+  (export "twice" (func 1))
+)"#
+        );
+    }
+}